@@ -1,12 +1,21 @@
 /// Breadth First Search
 pub mod bfs {
 
-    use hex2d::Coordinate;
+    use hex2d::{Coordinate, Direction};
     use hex2d;
 
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+
+    use geom::{Bounds, Topology};
+    use map::HexBitSet;
+
+    use hashing::{InternalHashMap, OutputHashSet};
+
     use std::hash;
+    use std::collections::HashSet;
     use std::collections::VecDeque;
-    use std::collections::HashMap;
     use std::collections::hash_map::Entry::{Occupied,Vacant};
 
     struct Visited<I = i32>
@@ -14,47 +23,332 @@ pub mod bfs {
         {
             prev : Coordinate<I>,
             dist : u32,
+            /// Changes of direction on the way (only tracked with `TieBreak::Straight`)
+            turns : u32,
         }
 
+    /// Number of Coordinates expanded between checks of cancellable searches
+    pub const CANCEL_CHECK_INTERVAL : usize = 64;
+
+    /// Progress of a search run with a budget (see `Traverser::run_for`)
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum SearchStatus<I = i32> where
+        I : hex2d::Integer
+    {
+        /// Budget ran out before anything was found
+        InProgress,
+        /// Next destination
+        Found(Coordinate<I>),
+        /// Nothing more to find
+        Exhausted,
+    }
+
     /// Breadth First Search
     ///
     /// Use BFS to find closest (in walk steps) Coordinates that satisfy `is_dest` and can be
     /// reached with a walk through coordinates for which `can_pass` returns true.
-    pub struct Traverser<FCanPass, FIsDest, I = i32> where
+    ///
+    /// Both closures can be `FnMut`, eg. to count or cache queries.
+    pub struct Traverser<FCanPass, FIsDest, I = i32, FFilter = AnyStep<I>> where
         I : hex2d::Integer,
         I : hash::Hash,
-        FCanPass : Fn(Coordinate<I>) -> bool,
-        FIsDest : Fn(Coordinate<I>) -> bool
+        FCanPass : FnMut(Coordinate<I>) -> bool,
+        FIsDest : FnMut(Coordinate<I>) -> bool,
+        FFilter : FnMut(Coordinate<I>, Coordinate<I>, Direction) -> bool
     {
-        visited : HashMap<Coordinate<I>, Visited<I>>,
+        visited : InternalHashMap<Coordinate<I>, Visited<I>>,
+        order : Vec<Coordinate<I>>,
         to_traverse : VecDeque<Coordinate<I>>,
         can_pass : FCanPass,
         is_dest : FIsDest,
         start : Coordinate<I>,
+        max_distance : Option<u32>,
+        bounds : Option<Bounds<I>>,
+        topology : Topology<I>,
+        tie_break : TieBreak,
+        neighbor_filter : FFilter,
+    }
+
+    /// How to choose between equally good ways to reach a Coordinate
+    pub(crate) enum TieBreak {
+        /// First found, with neighbors in `Direction::all()` order
+        Fixed,
+        /// Neighbors shuffled with a seeded generator
+        Random(Box<StdRng>),
+        /// Keep the direction of the previous step
+        Straight,
+    }
+
+    /// `1` if stepping from `pos` to `npos` changes the direction of the step from `prev` to `pos`
+    pub(crate) fn turn<I>(prev : Coordinate<I>, pos : Coordinate<I>, npos : Coordinate<I>) -> u32 where
+        I : hex2d::Integer
+    {
+        (prev != pos && npos - pos != pos - prev) as u32
+    }
+
+    /// Function pointer used for closures not set in a `TraverserBuilder`
+    pub type Anything<I> = fn(Coordinate<I>) -> bool;
+
+    pub(crate) fn anything<I>(_ : Coordinate<I>) -> bool where
+        I : hex2d::Integer
+    {
+        true
+    }
+
+    /// Function pointer used for the neighbor filter not set in a `TraverserBuilder`
+    pub type AnyStep<I> = fn(Coordinate<I>, Coordinate<I>, Direction) -> bool;
+
+    pub(crate) fn any_step<I>(_ : Coordinate<I>, _ : Coordinate<I>, _ : Direction) -> bool where
+        I : hex2d::Integer
+    {
+        true
+    }
+
+    /// Builder of `Traverser` with optional configuration
+    ///
+    /// By default every Coordinate can be passed and is a destination.
+    pub struct TraverserBuilder<FCanPass, FIsDest, I = i32, FFilter = AnyStep<I>> where
+        I : hex2d::Integer
+    {
+        can_pass : FCanPass,
+        is_dest : FIsDest,
+        start : Coordinate<I>,
+        max_distance : Option<u32>,
+        bounds : Option<Bounds<I>>,
+        topology : Topology<I>,
+        capacity : usize,
+        tie_break : TieBreak,
+        neighbor_filter : FFilter,
+    }
+
+    impl<FCanPass, FIsDest, I, FFilter> TraverserBuilder<FCanPass, FIsDest, I, FFilter> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : std::ops::AddAssign,
+        FCanPass : FnMut(Coordinate<I>) -> bool,
+        FIsDest : FnMut(Coordinate<I>) -> bool,
+        FFilter : FnMut(Coordinate<I>, Coordinate<I>, Direction) -> bool
+    {
+        /// Walk only through Coordinates for which `can_pass` returns true
+        pub fn can_pass<F>(self, can_pass : F) -> TraverserBuilder<F, FIsDest, I, FFilter> where
+            F : FnMut(Coordinate<I>) -> bool
+        {
+            TraverserBuilder {
+                can_pass,
+                is_dest: self.is_dest,
+                start: self.start,
+                max_distance: self.max_distance,
+                bounds: self.bounds,
+                topology: self.topology,
+                capacity: self.capacity,
+                tie_break: self.tie_break,
+                neighbor_filter: self.neighbor_filter,
+            }
+        }
+
+        /// Return only Coordinates for which `is_dest` returns true
+        pub fn is_dest<F>(self, is_dest : F) -> TraverserBuilder<FCanPass, F, I, FFilter> where
+            F : FnMut(Coordinate<I>) -> bool
+        {
+            TraverserBuilder {
+                can_pass: self.can_pass,
+                is_dest,
+                start: self.start,
+                max_distance: self.max_distance,
+                bounds: self.bounds,
+                topology: self.topology,
+                capacity: self.capacity,
+                tie_break: self.tie_break,
+                neighbor_filter: self.neighbor_filter,
+            }
+        }
+
+        /// Don't visit Coordinates more than `max_distance` steps away from `start`
+        pub fn max_distance(mut self, max_distance : u32) -> Self {
+            self.max_distance = Some(max_distance);
+            self
+        }
+
+        /// Don't visit Coordinates outside of `bounds`
+        pub fn bounds(mut self, bounds : Bounds<I>) -> Self {
+            self.bounds = Some(bounds);
+            self
+        }
+
+        /// Stop movement upon entering Coordinates for which `in_zoc` returns true
+        ///
+        /// Such Coordinates can still be entered (and found), but are not walked through, like
+        /// in the zone of control of wargames (see `zone_of_control`). `start` can always be
+        /// left.
+        pub fn zone_of_control<F>(self, mut in_zoc : F) -> TraverserBuilder<impl FnMut(Coordinate<I>) -> bool, FIsDest, I, FFilter> where
+            F : FnMut(Coordinate<I>) -> bool
+        {
+            let start = self.start;
+            let mut can_pass = self.can_pass;
+            TraverserBuilder {
+                can_pass: move |c| can_pass(c) && (c == start || !in_zoc(c)),
+                is_dest: self.is_dest,
+                start,
+                max_distance: self.max_distance,
+                bounds: self.bounds,
+                topology: self.topology,
+                capacity: self.capacity,
+                tie_break: self.tie_break,
+                neighbor_filter: self.neighbor_filter,
+            }
+        }
+
+        /// Walk the world of `topology`
+        ///
+        /// Neighbors are wrapped with `Topology::wrap`, so searches cross the seams of wrapping
+        /// worlds. `start` is wrapped too, and all returned Coordinates are canonical.
+        pub fn topology(mut self, topology : Topology<I>) -> Self {
+            self.topology = topology;
+            self
+        }
+
+        /// Expand neighbors in random order, seeded from `rng`
+        ///
+        /// Equally short paths are picked at random instead of always hugging the same
+        /// pattern, so units look less robotic. The same seed gives the same paths. Replaces
+        /// `prefer_straight`.
+        pub fn shuffle<R>(mut self, rng : &mut R) -> Self where
+            R : Rng
+        {
+            self.tie_break = TieBreak::Random(Box::new(StdRng::seed_from_u64(rng.gen())));
+            self
+        }
+
+        /// Among equally short paths, prefer ones that change direction less often
+        ///
+        /// Every Coordinate is reached the way with the fewest turns so far, which gives paths
+        /// with fewer zig-zags (though not always the fewest possible). Replaces `shuffle`.
+        pub fn prefer_straight(mut self) -> Self {
+            self.tie_break = TieBreak::Straight;
+            self
+        }
+
+        /// Take only steps for which `filter(from, to, direction)` returns true
+        ///
+        /// For rules that depend on the step rather than on the entered Coordinate alone, eg.
+        /// no moving backward, or alternating tile colors. `to` is canonical (see
+        /// `topology`), and `direction` is that of the step from `from`.
+        pub fn neighbor_filter<F>(self, filter : F) -> TraverserBuilder<FCanPass, FIsDest, I, F> where
+            F : FnMut(Coordinate<I>, Coordinate<I>, Direction) -> bool
+        {
+            TraverserBuilder {
+                can_pass: self.can_pass,
+                is_dest: self.is_dest,
+                start: self.start,
+                max_distance: self.max_distance,
+                bounds: self.bounds,
+                topology: self.topology,
+                capacity: self.capacity,
+                tie_break: self.tie_break,
+                neighbor_filter: filter,
+            }
+        }
+
+        /// Make room for `expected_nodes` visited Coordinates
+        pub fn capacity(mut self, expected_nodes : usize) -> Self {
+            self.capacity = expected_nodes;
+            self
+        }
+
+        /// Create the Traverser
+        pub fn build(self) -> Traverser<FCanPass, FIsDest, I, FFilter> {
+            let start = self.topology.wrap(self.start);
+            let mut traverser = Traverser::with_filter(self.can_pass, self.is_dest, start, self.capacity, self.neighbor_filter);
+            traverser.max_distance = self.max_distance;
+            traverser.bounds = self.bounds;
+            traverser.topology = self.topology;
+            traverser.tie_break = self.tie_break;
+            traverser
+        }
+    }
+
+    impl<I> Traverser<Anything<I>, Anything<I>, I> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : std::ops::AddAssign
+    {
+        /// Start building a Traverser starting at `start`
+        pub fn builder(start : Coordinate<I>) -> TraverserBuilder<Anything<I>, Anything<I>, I> {
+            TraverserBuilder {
+                can_pass: anything,
+                is_dest: anything,
+                start,
+                max_distance: None,
+                bounds: None,
+                topology: Topology::Flat,
+                capacity: 0,
+                tie_break: TieBreak::Fixed,
+                neighbor_filter: any_step,
+            }
+        }
     }
 
     impl<FCanPass, FIsDest, I> Traverser<FCanPass, FIsDest, I> where
         I : hex2d::Integer,
         I : hash::Hash,
         I : std::ops::AddAssign,
-        FCanPass : Fn(Coordinate<I>) -> bool,
-        FIsDest : Fn(Coordinate<I>) -> bool
+        FCanPass : FnMut(Coordinate<I>) -> bool,
+        FIsDest : FnMut(Coordinate<I>) -> bool
     {
 
         /// Create a Traverser instance with initial conditions
         pub fn new(can_pass : FCanPass, is_dest : FIsDest, start: Coordinate<I>) -> Traverser<FCanPass, FIsDest, I> {
+            Self::with_capacity(can_pass, is_dest, start, 0)
+        }
+
+        /// Create a Traverser instance with room for `expected_nodes` visited Coordinates
+        ///
+        /// Avoids rehashing when the size of the search is known upfront, eg. for searches
+        /// limited to a radius.
+        pub fn with_capacity(
+            can_pass : FCanPass,
+            is_dest : FIsDest,
+            start: Coordinate<I>,
+            expected_nodes : usize,
+            ) -> Traverser<FCanPass, FIsDest, I> {
+            Traverser::with_filter(can_pass, is_dest, start, expected_nodes, any_step)
+        }
+    }
+
+    impl<FCanPass, FIsDest, I, FFilter> Traverser<FCanPass, FIsDest, I, FFilter> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : std::ops::AddAssign,
+        FCanPass : FnMut(Coordinate<I>) -> bool,
+        FIsDest : FnMut(Coordinate<I>) -> bool,
+        FFilter : FnMut(Coordinate<I>, Coordinate<I>, Direction) -> bool
+    {
+        /// Like `with_capacity`, taking only steps allowed by `neighbor_filter`
+        fn with_filter(
+            can_pass : FCanPass,
+            is_dest : FIsDest,
+            start: Coordinate<I>,
+            expected_nodes : usize,
+            neighbor_filter : FFilter,
+            ) -> Traverser<FCanPass, FIsDest, I, FFilter> {
             let mut to_traverse = VecDeque::new();
             to_traverse.push_back(start);
 
-            let mut visited = HashMap::new();
-            visited.insert(start, Visited{prev: start, dist: 0});
+            let mut visited = InternalHashMap::with_capacity_and_hasher(expected_nodes, Default::default());
+            visited.insert(start, Visited{prev: start, dist: 0, turns: 0});
 
             Traverser {
-                visited: visited,
-                to_traverse: to_traverse,
-                can_pass: can_pass,
-                is_dest: is_dest,
-                start: start,
+                visited,
+                order: vec!(start),
+                to_traverse,
+                can_pass,
+                is_dest,
+                start,
+                max_distance: None,
+                bounds: None,
+                topology: Topology::Flat,
+                tie_break: TieBreak::Fixed,
+                neighbor_filter,
             }
         }
 
@@ -62,38 +356,122 @@ pub mod bfs {
         ///
         /// Can be called multiple times, each time returning next coordinate
         pub fn find(&mut self) -> Option<Coordinate<I>> {
+            loop {
+                match self.step() {
+                    SearchStatus::InProgress => {},
+                    SearchStatus::Found(pos) => return Some(pos),
+                    SearchStatus::Exhausted => return None,
+                }
+            }
+        }
+
+        /// Like `find`, but give up after expanding `budget` Coordinates
+        ///
+        /// Returns `InProgress` if the budget ran out first; calling it again continues where
+        /// it stopped. Lets long searches be spread over many frames.
+        pub fn run_for(&mut self, budget : usize) -> SearchStatus<I> {
+            for _ in 0..budget {
+                match self.step() {
+                    SearchStatus::InProgress => {},
+                    status => return status,
+                }
+            }
+            SearchStatus::InProgress
+        }
 
+        /// Like `find`, but stop early when `cancelled` returns true
+        ///
+        /// `cancelled` is checked every `CANCEL_CHECK_INTERVAL` expanded Coordinates, eg.
+        /// `|| flag.load(Ordering::Relaxed)` on an `Arc<AtomicBool>` shared with the thread that
+        /// requested the path. Returns `InProgress` if cancelled; the search can still be resumed.
+        pub fn find_unless<F>(&mut self, mut cancelled : F) -> SearchStatus<I> where
+            F : FnMut() -> bool
+        {
             loop {
-                let pos = match self.to_traverse.pop_front() {
-                    None => return None,
-                    Some(coord) => coord,
-                };
+                if cancelled() {
+                    return SearchStatus::InProgress;
+                }
+                match self.run_for(CANCEL_CHECK_INTERVAL) {
+                    SearchStatus::InProgress => {},
+                    status => return status,
+                }
+            }
+        }
 
-                // Traverse before returning, so `find` can be call subsequently
-                // for more than just first answer
-                if (self.can_pass)(pos) {
+        /// Expand a single Coordinate
+        fn step(&mut self) -> SearchStatus<I> {
+            let pos = match self.to_traverse.pop_front() {
+                Some(pos) => pos,
+                None => return SearchStatus::Exhausted,
+            };
 
-                    let &Visited{dist, ..} = self.visited.get(&pos).expect("BFS: Should have been visited already");
+            // Traverse before returning, so `find` can be call subsequently
+            // for more than just first answer
+            if (self.can_pass)(pos) {
 
-                    let dist = dist + 1;
+                let &Visited{dist, prev, turns} = self.visited.get(&pos).expect("BFS: Should have been visited already");
 
-                    for &npos in pos.neighbors().iter() {
-                        match self.visited.entry(npos) {
-                            Occupied(_) => { /* already visited */ }
-                            Vacant(entry) => {
-                                entry.insert(Visited{prev: pos, dist: dist});
-                                self.to_traverse.push_back(npos);
+                let dist = dist + 1;
+
+                let mut neighbors = self.topology.neighbors(pos);
+                let mut directions = *Direction::all();
+                if let TieBreak::Random(ref mut rng) = self.tie_break {
+                    let mut order = [0, 1, 2, 3, 4, 5];
+                    order.shuffle(rng);
+                    neighbors = order.map(|i| neighbors[i]);
+                    directions = order.map(|i| directions[i]);
+                }
+
+                for (&npos, &dir) in neighbors.iter().zip(directions.iter()) {
+                    if !(self.neighbor_filter)(pos, npos, dir) {
+                        continue;
+                    }
+                    let turns = match self.tie_break {
+                        TieBreak::Straight => turns + turn(prev, pos, npos),
+                        _ => 0,
+                    };
+                    if self.max_distance.is_some_and(|max| dist > max) ||
+                        self.bounds.is_some_and(|bounds| !bounds.contains(npos)) {
+                        continue;
+                    }
+                    match self.visited.entry(npos) {
+                        Occupied(mut entry) => {
+                            // Not expanded yet, so nothing depends on its `prev`
+                            if entry.get().dist == dist && entry.get().turns > turns {
+                                entry.insert(Visited{prev: pos, dist, turns});
                             }
                         }
+                        Vacant(entry) => {
+                            entry.insert(Visited{prev: pos, dist, turns});
+                            self.order.push(npos);
+                            self.to_traverse.push_back(npos);
+                        }
                     }
                 }
+            }
 
-                if (self.is_dest)(pos) {
-                    return Some(pos);
-                }
+            if (self.is_dest)(pos) {
+                SearchStatus::Found(pos)
+            } else {
+                SearchStatus::InProgress
             }
         }
 
+        /// All Coordinates visited so far, in the order they were reached
+        ///
+        /// Neighbors are expanded in `Direction::all()` order (unless shuffled, with a fixed seed),
+        /// so the same inputs always give the same order, regardless of hashing.
+        pub fn visited(&self) -> impl Iterator<Item = Coordinate<I>> + '_ {
+            self.order.iter().cloned()
+        }
+
+        /// Number of steps from `start` to `pos`
+        ///
+        /// Returns `None` for Coordinates that were not yet visited.
+        pub fn distance(&self, pos : Coordinate<I>) -> Option<u32> {
+            self.visited.get(&pos).map(|entry| entry.dist)
+        }
+
         /// Return neighbor Coordinate to `pos` that is one step closer to
         /// `start` from initial conditions.
         ///
@@ -125,237 +503,3974 @@ pub mod bfs {
             }
         }
     }
-}
-
-/// Very tricky, but (hopefully) good enough, recursive LoS algorithm
-pub mod los {
-    use hex2d;
-    use hex2d::Angle::{Left,Right};
 
-    use hex2d::Direction;
-    use hex2d::Coordinate;
+    /// Zone of control of Coordinates for which `exerts` returns true (eg. enemy units)
+    ///
+    /// Returns true for all neighbors of such Coordinates. Meant for
+    /// `TraverserBuilder::zone_of_control`.
+    pub fn zone_of_control<FExerts, I>(exerts : FExerts) -> impl Fn(Coordinate<I>) -> bool where
+        I : hex2d::Integer,
+        FExerts : Fn(Coordinate<I>) -> bool
+    {
+        move |c| c.neighbors().iter().any(|&n| exerts(n))
+    }
 
-    fn los_rec<FOpaqueness, FVisible, I>(
-        opaqueness : &FOpaqueness,
-        visible : &mut FVisible,
-        light: I,
-        pos : Coordinate<I>,
-        start_dir : Direction,
-        main_dir : Direction,
-        dir : Option<Direction>,
-        pdir : Option<Direction>,
-    ) where
+    /// Pick a random Coordinate reachable from `start`
+    ///
+    /// Every Coordinate that can be reached from `start` in at most `max_dist` steps through
+    /// Coordinates for which `can_pass` returns true (including `start` itself) has the same
+    /// chance of being picked. Returns `None` if `start` itself can't be passed.
+    pub fn sample_reachable<FCanPass, R, I>(
+        start : Coordinate<I>,
+        can_pass : FCanPass,
+        max_dist : u32,
+        rng : &mut R
+        ) -> Option<Coordinate<I>> where
         I : hex2d::Integer,
+        I : hash::Hash,
         I : std::ops::AddAssign,
-        FOpaqueness : Fn(Coordinate<I>) -> I,
-        FVisible : FnMut(Coordinate<I>, I)
-        {
-
-            let mut light = light;
-            let opaq = opaqueness(pos);
+        FCanPass : Fn(Coordinate<I>) -> bool,
+        R : Rng
+    {
+        let mut traverser = Traverser::new(&can_pass, &can_pass, start);
+        let mut res = None;
+        let mut count = 0;
 
-            if opaq >= light {
-                return;
-            } else {
-                light = light - opaq;
+        while let Some(pos) = traverser.find() {
+            if traverser.distance(pos).unwrap() > max_dist {
+                break;
             }
 
-            visible(pos, light);
-
-            let neighbors = match (dir, pdir) {
-                (Some(dir), Some(pdir)) => {
-                    if dir == pdir {
-                        vec!(dir)
-                    } else {
-                        vec!(dir, pdir)
-                    }
-                },
-                (Some(dir), None) => {
-                    if main_dir == dir {
-                        vec!(dir, dir + Left, dir + Right)
-                    } else {
-                        vec!(dir, main_dir)
-                    }
-                },
-                _ => {
-                    vec!(main_dir, main_dir + Left, main_dir + Right)
-                }
-            };
-
-            for &d in neighbors.iter() {
-                let npos = pos + d;
-                match dir {
-                    Some(_) => los_rec::<FOpaqueness, FVisible, I>(opaqueness, visible, light, npos, start_dir, d, Some(d), dir),
-                    None => los_rec::<FOpaqueness, FVisible, I>(opaqueness, visible, light, npos, start_dir, main_dir, Some(d), dir),
-                }
+            count += 1;
+            if rng.gen_range(0..count) == 0 {
+                res = Some(pos);
             }
         }
 
+        res
+    }
 
-    /// Starting from `pos` in direction `dir`, call `visible` for each visible Coordinate.
+    /// All Coordinates of `bounds` reachable from `start`
     ///
-    /// Use `light` as starting range of the LoS. For each visible Coordinate, the value returned
-    /// by `opaqueness` will be subtracted from `light` to check if the LoS should finish due to
-    /// "lack of visibility". `opaqueness` should typically return 1 for fully transparent
-    /// Coordinates, and anything bigger than initial `light` for fully opaque Coordinates.
-    pub fn los<FOpaqueness, FVisible, I>(
-        opaqueness : &FOpaqueness,
-        visible : &mut FVisible,
-        light: I,
-        pos : Coordinate<I>,
-        dirs : &[Direction],
-    ) where
+    /// Walks only through Coordinates of `bounds` for which `can_pass` returns true. The result
+    /// is empty if `start` itself can't be passed.
+    pub fn reachable<FCanPass, I>(
+        start : Coordinate<I>,
+        can_pass : FCanPass,
+        bounds : Bounds<I>,
+        ) -> HexBitSet<I> where
         I : hex2d::Integer,
+        I : hash::Hash,
         I : std::ops::AddAssign,
-        FOpaqueness : Fn(Coordinate<I>) -> I,
-        FVisible : FnMut(Coordinate<I>, I)
-        {
-            for dir in dirs.iter() {
-                los_rec::<FOpaqueness, FVisible, I>(opaqueness, visible, light, pos, *dir, *dir, None, None);
-            }
-        }
-}
-
-/// Combination of tricky Los with straight line checking
-pub mod los2 {
-    use hex2d;
-    use hex2d::Angle::{Left, Right, Forward};
-    use hex2d::Direction;
-    use hex2d::Coordinate;
-    use num::{FromPrimitive};
-    use std::collections::HashSet;
-    use std::hash;
-    use std::ops::{Add};
-    use std::cmp;
+        FCanPass : Fn(Coordinate<I>) -> bool
+    {
+        let can_pass = |c| bounds.contains(c) && can_pass(c);
+        let mut traverser = Traverser::new(&can_pass, &can_pass, start);
+        let mut res = HexBitSet::new(bounds);
+        res.extend(::std::iter::from_fn(|| traverser.find()));
+        res
+    }
 
-    fn los_check_line<FOpaqueness, I>(
-        opaqueness : &FOpaqueness,
-        light: I,
-        start : Coordinate<I>,
-        pos : Coordinate<I>,
-        ) -> (bool, I)
-        where
+    /// Coordinates within walking range of all `(start, range)` pairs of `ranges`
+    ///
+    /// Like `geom::ranges_intersection`, but distances are counted in steps through
+    /// Coordinates for which `can_pass` returns true, and only such Coordinates are included.
+    /// Empty if `ranges` is.
+    pub fn walk_ranges_intersection<FCanPass, I>(
+        ranges : &[(Coordinate<I>, u32)],
+        can_pass : FCanPass,
+        ) -> OutputHashSet<Coordinate<I>> where
         I : hex2d::Integer,
-        I : hash::Hash+Eq,
+        I : hash::Hash,
         I : std::ops::AddAssign,
-        for <'a> &'a I: Add<&'a I, Output = I>,
-        FOpaqueness : Fn(Coordinate<I>) -> I
+        FCanPass : Fn(Coordinate<I>) -> bool
     {
+        let mut res : Option<OutputHashSet<Coordinate<I>>> = None;
+        for &(start, range) in ranges {
+            let mut traverser = Traverser::builder(start)
+                .can_pass(&can_pass)
+                .is_dest(&can_pass)
+                .max_distance(range)
+                .build();
+            let reached : OutputHashSet<_> = ::std::iter::from_fn(|| traverser.find())
+                .filter(|c| res.as_ref().is_none_or(|res| res.contains(c)))
+                .collect();
+            res = Some(reached);
+        }
+        res.unwrap_or_default()
+    }
 
-        let mut opaq_sum1 : I = FromPrimitive::from_i8(0).unwrap();
-        let mut last1 = start;
+    /// Two Coordinates and the path between them, both included
+    pub type Connection<I> = (Coordinate<I>, Coordinate<I>, Vec<Coordinate<I>>);
 
-        let mut opaq_sum2 : I = FromPrimitive::from_i8(0).unwrap();
-        let mut last2 = start;
+    /// Closest pair of Coordinates between `set_a` and `set_b`, with the path connecting them
+    ///
+    /// Runs a single BFS from all Coordinates of `set_a` at once, walking through Coordinates
+    /// for which `can_pass` returns true, until it enters `set_b`. Coordinates of the sets can
+    /// always be entered, so regions can be connected through walls.
+    ///
+    /// Returns `(a, b, path)`, where the path goes from `a` to `b`, both included, or `None`
+    /// if the sets can't be connected. Equally close pairs are picked deterministically.
+    pub fn closest_between<FCanPass, I, SA, SB>(
+        set_a : &HashSet<Coordinate<I>, SA>,
+        set_b : &HashSet<Coordinate<I>, SB>,
+        mut can_pass : FCanPass,
+        ) -> Option<Connection<I>> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        SA : hash::BuildHasher,
+        SB : hash::BuildHasher,
+        FCanPass : FnMut(Coordinate<I>) -> bool
+    {
+        let mut sources : Vec<_> = set_a.iter().cloned().collect();
+        sources.sort();
 
-        for (c1, c2) in start.line_to_with_edge_detection_iter(pos) {
-            if opaq_sum1 < light {
-                let opaq1 = opaqueness(c1);
-                opaq_sum1 = opaq_sum1 + opaq1;
-                last1 = c1;
-            }
+        let mut prev = InternalHashMap::default();
+        let mut to_traverse = VecDeque::new();
+        for &c in &sources {
+            prev.insert(c, c);
+            to_traverse.push_back(c);
+        }
 
-            if opaq_sum2 < light {
-                let opaq2 = opaqueness(c2);
-                opaq_sum2 = opaq_sum2 + opaq2;
-                last2 = c2;
+        while let Some(pos) = to_traverse.pop_front() {
+            if set_b.contains(&pos) {
+                let mut path = vec!(pos);
+                while prev[path.last().unwrap()] != *path.last().unwrap() {
+                    let c = prev[path.last().unwrap()];
+                    path.push(c);
+                }
+                path.reverse();
+                return Some((path[0], pos, path));
             }
-        };
 
-        match (last1 == pos, last2 == pos) {
-            (true, true) => (true, light - cmp::min(opaq_sum1, opaq_sum2)),
-            (true, false) => (true, light - opaq_sum1),
-            (false, true) => (true, light - opaq_sum2),
-            (false, false) => (false, light - light),
+            for &npos in pos.neighbors().iter() {
+                if let Vacant(entry) = prev.entry(npos) {
+                    if set_b.contains(&npos) || can_pass(npos) {
+                        entry.insert(pos);
+                        to_traverse.push_back(npos);
+                    }
+                }
+            }
         }
+        None
     }
+}
 
-    fn los_rec<FOpaqueness, FVisible, I>(
-        opaqueness : &FOpaqueness,
-        visible : &mut FVisible,
-        light: I,
-        start : Coordinate<I>,
-        pos : Coordinate<I>,
-        dir : Direction,
-        visited : &mut HashSet<Coordinate<I>>,
-    ) where
-        I : hex2d::Integer,
-        I : hash::Hash+Eq,
-        I : std::ops::AddAssign,
-        for <'a> &'a I: Add<&'a I, Output = I>,
-        FOpaqueness : Fn(Coordinate<I>) -> I,
-        FVisible : FnMut(Coordinate<I>, I)
-        {
-            if visited.contains(&pos) {
-                return;
-            } else {
-                visited.insert(pos);
-            }
+/// Breadth First Search over Positions
+///
+/// Like `bfs`, but every node is a `Position`: a Coordinate together with the Direction it was
+/// entered in. Allows facing-dependent passability (eg. arrow slits, one-way ledges) and gives
+/// the heading at each step of the found paths.
+pub mod bfs_position {
 
-            let (directly_visible, v_light) = los_check_line(
-                opaqueness,
-                light,
+    use hex2d::{Direction, Position};
+    use hex2d;
+
+    use hashing::InternalHashMap;
+
+    use std::hash;
+    use std::collections::VecDeque;
+    use std::collections::hash_map::Entry::{Occupied,Vacant};
+
+    struct Visited<I = i32>
+        where I : hex2d::Integer
+        {
+            prev : Position<I>,
+            dist : u32,
+        }
+
+    /// Breadth First Search over Positions
+    ///
+    /// From every Position, each neighbor Coordinate is entered facing the Direction of the
+    /// step. `can_pass` and `is_dest` get the Position that would be entered, so the same
+    /// Coordinate can be passable from one side only.
+    ///
+    /// Both closures can be `FnMut`, eg. to count or cache queries.
+    pub struct Traverser<FCanPass, FIsDest, I = i32, FFilter = AnyStep<I>> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        FCanPass : FnMut(Position<I>) -> bool,
+        FIsDest : FnMut(Position<I>) -> bool,
+        FFilter : FnMut(Position<I>, Position<I>, Direction) -> bool
+    {
+        visited : InternalHashMap<Position<I>, Visited<I>>,
+        order : Vec<Position<I>>,
+        to_traverse : VecDeque<Position<I>>,
+        can_pass : FCanPass,
+        is_dest : FIsDest,
+        start : Position<I>,
+        neighbor_filter : FFilter,
+    }
+
+    /// Function pointer used for the neighbor filter not set with `Traverser::neighbor_filter`
+    pub type AnyStep<I> = fn(Position<I>, Position<I>, Direction) -> bool;
+
+    fn any_step<I>(_ : Position<I>, _ : Position<I>, _ : Direction) -> bool where
+        I : hex2d::Integer
+    {
+        true
+    }
+
+    impl<FCanPass, FIsDest, I> Traverser<FCanPass, FIsDest, I> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : std::ops::AddAssign,
+        FCanPass : FnMut(Position<I>) -> bool,
+        FIsDest : FnMut(Position<I>) -> bool
+    {
+
+        /// Create a Traverser instance with initial conditions
+        pub fn new(can_pass : FCanPass, is_dest : FIsDest, start : Position<I>) -> Traverser<FCanPass, FIsDest, I> {
+            let mut to_traverse = VecDeque::new();
+            to_traverse.push_back(start);
+
+            let mut visited = InternalHashMap::default();
+            visited.insert(start, Visited{prev: start, dist: 0});
+
+            Traverser {
+                visited,
+                order: vec!(start),
+                to_traverse,
+                can_pass,
+                is_dest,
                 start,
-                pos);
+                neighbor_filter: any_step,
+            }
+        }
+    }
 
-            if directly_visible {
-                visible(pos, v_light);
-            } else {
-                let dir_to = start.direction_to_cw(pos).unwrap_or(dir);
-                let neighbors = vec!(Left, Right);
-                for npos in neighbors.iter()
-                    .map(|&rd| dir_to + rd)
-                        .map(|dir| pos + dir) {
+    impl<FCanPass, FIsDest, I, FFilter> Traverser<FCanPass, FIsDest, I, FFilter> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : std::ops::AddAssign,
+        FCanPass : FnMut(Position<I>) -> bool,
+        FIsDest : FnMut(Position<I>) -> bool,
+        FFilter : FnMut(Position<I>, Position<I>, Direction) -> bool
+    {
+        /// Take only steps for which `filter(from, to, direction)` returns true
+        ///
+        /// Unlike `can_pass`, sees the facing before the step, eg. to forbid turning back or
+        /// sharp turns. Meant to be set right after `new`, before searching.
+        pub fn neighbor_filter<F>(self, filter : F) -> Traverser<FCanPass, FIsDest, I, F> where
+            F : FnMut(Position<I>, Position<I>, Direction) -> bool
+        {
+            Traverser {
+                visited: self.visited,
+                order: self.order,
+                to_traverse: self.to_traverse,
+                can_pass: self.can_pass,
+                is_dest: self.is_dest,
+                start: self.start,
+                neighbor_filter: filter,
+            }
+        }
 
-                            let (side_visible, v_light) = los_check_line(
-                                opaqueness,
-                                light,
-                                start,
-                                npos,);
+        /// Find next closest Position.
+        ///
+        /// Can be called multiple times, each time returning next Position
+        pub fn find(&mut self) -> Option<Position<I>> {
+
+            loop {
+                let pos = self.to_traverse.pop_front()?;
 
-                            if side_visible {
-                                visible(pos, v_light);
+                if (self.can_pass)(pos) {
+                    let dist = self.visited[&pos].dist + 1;
+
+                    for &dir in Direction::all().iter() {
+                        let npos = Position::new(pos.coord + dir, dir);
+                        if !(self.neighbor_filter)(pos, npos, dir) {
+                            continue;
+                        }
+                        match self.visited.entry(npos) {
+                            Occupied(_) => { /* already visited */ }
+                            Vacant(entry) => {
+                                entry.insert(Visited{prev: pos, dist});
+                                self.order.push(npos);
+                                self.to_traverse.push_back(npos);
                             }
                         }
-                return;
+                    }
+                }
+
+                if (self.is_dest)(pos) {
+                    return Some(pos);
+                }
             }
+        }
 
-            let neighbors = vec!(Forward, Left, Right);
+        /// All Positions visited so far, in the order they were reached
+        pub fn visited(&self) -> impl Iterator<Item = Position<I>> + '_ {
+            self.order.iter().cloned()
+        }
 
-            for &a in neighbors.iter() {
-                let npos = pos + (dir + a);
-                los_rec::<FOpaqueness, FVisible, I>(
-                    opaqueness, visible, light, start, npos, dir, visited
-                    );
+        /// Number of steps from `start` to `pos`
+        ///
+        /// Returns `None` for Positions that were not yet visited.
+        pub fn distance(&self, pos : Position<I>) -> Option<u32> {
+            self.visited.get(&pos).map(|entry| entry.dist)
+        }
+
+        /// Return Position one step closer to `start` from which `pos` was entered
+        ///
+        /// Returns `None` for Positions that were not yet visited.
+        /// Returns `start` for `start` (from initial conditions)
+        pub fn backtrace(&self, pos : Position<I>) -> Option<Position<I>> {
+            self.visited.get(&pos).map(|entry| entry.prev)
+        }
+
+        /// Whole path from `start` to `pos`, both included, with the facing at every step
+        ///
+        /// Returns `None` for Positions that were not yet visited.
+        pub fn path(&self, mut pos : Position<I>) -> Option<Vec<Position<I>>> {
+            let mut path = vec!(pos);
+            while pos != self.start {
+                pos = self.backtrace(pos)?;
+                path.push(pos);
             }
+            path.reverse();
+            Some(path)
+        }
+    }
+}
+
+/// Dijkstra's cheapest path search
+///
+/// Like `bfs`, but steps have costs, and extra edges (portals) can connect distant
+/// Coordinates, eg. stairs, teleporters and tunnels.
+pub mod dijkstra {
+
+    use hex2d::{Coordinate, Direction};
+    use hex2d;
+
+    use geom::Topology;
+    use super::bfs::{any_step, anything, turn, AnyStep, Anything, SearchStatus, TieBreak, CANCEL_CHECK_INTERVAL};
+    use hashing::InternalHashMap;
+
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    use std::hash;
+    use std::cmp::Reverse;
+    use std::collections::BinaryHeap;
+    use std::collections::hash_map::Entry::{Occupied,Vacant};
+
+    struct Visited<I = i32>
+        where I : hex2d::Integer
+        {
+            prev : Coordinate<I>,
+            cost : u32,
+            done : bool,
+            /// Changes of direction on the way (only tracked with `TieBreak::Straight`)
+            turns : u32,
         }
 
+    /// Extra edge from the first Coordinate to the second, with a cost
+    pub type Portal<I = i32> = (Coordinate<I>, Coordinate<I>, u32);
 
-    /// Starting from `pos` in direction `dir`, call `visible` for each visible Coordinate.
+    /// Cost of a step between neighbors, or `None` if it can't be taken
     ///
-    /// Use `light` as starting range of the LoS. For each visible Coordinate, the value returned
-    /// by `opaqueness` will be subtracted from `light` to check if the LoS should finish due to
-    /// "lack of visibility". `opaqueness` should typically return 1 for fully transparent
-    /// Coordinates, and anything bigger than initial `light` for fully opaque Coordinates.
-    pub fn los<FOpaqueness, FVisible, I>(
-        opaqueness : &FOpaqueness,
-        visible : &mut FVisible,
-        light: I,
-        pos : Coordinate<I>,
-        dirs : &[Direction],
-    ) where
+    /// Implemented for all matching closures.
+    pub trait StepCost<I> : FnMut(Coordinate<I>, Coordinate<I>) -> Option<u32> where
+        I : hex2d::Integer
+    {
+    }
+
+    impl<F, I> StepCost<I> for F where
+        I : hex2d::Integer,
+        F : FnMut(Coordinate<I>, Coordinate<I>) -> Option<u32>
+    {
+    }
+
+    /// Function pointer used for the step cost not set in a `TraverserBuilder`
+    pub type UnitCost<I> = fn(Coordinate<I>, Coordinate<I>) -> Option<u32>;
+
+    fn unit_cost<I>(_ : Coordinate<I>, _ : Coordinate<I>) -> Option<u32> where
+        I : hex2d::Integer
+    {
+        Some(1)
+    }
+
+    /// Dijkstra's cheapest path search
+    ///
+    /// Finds Coordinates that satisfy `is_dest`, from the cheapest to reach. `cost(from, to)`
+    /// gives the cost of a step between neighbors, or `None` if `to` can't be entered from
+    /// `from`. Portals are followed regardless of `cost`.
+    ///
+    /// Both closures can be `FnMut`, eg. to count or cache queries.
+    pub struct Traverser<FCost, FIsDest, I = i32, FFilter = AnyStep<I>> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        FCost : FnMut(Coordinate<I>, Coordinate<I>) -> Option<u32>,
+        FIsDest : FnMut(Coordinate<I>) -> bool,
+        FFilter : FnMut(Coordinate<I>, Coordinate<I>, Direction) -> bool
+    {
+        visited : InternalHashMap<Coordinate<I>, Visited<I>>,
+        /// `(cost, tie-breaker, Coordinate)`
+        to_traverse : BinaryHeap<Reverse<(u32, u32, Coordinate<I>)>>,
+        cost : FCost,
+        is_dest : FIsDest,
+        start : Coordinate<I>,
+        portals : InternalHashMap<Coordinate<I>, Vec<(Coordinate<I>, u32)>>,
+        topology : Topology<I>,
+        tie_break : TieBreak,
+        neighbor_filter : FFilter,
+    }
+
+    /// Builder of `Traverser` with optional configuration
+    ///
+    /// By default every step costs `1`, and every Coordinate is a destination.
+    pub struct TraverserBuilder<FCost, FIsDest, I = i32, FFilter = AnyStep<I>> where
+        I : hex2d::Integer
+    {
+        cost : FCost,
+        is_dest : FIsDest,
+        start : Coordinate<I>,
+        portals : Vec<Portal<I>>,
+        topology : Topology<I>,
+        tie_break : TieBreak,
+        neighbor_filter : FFilter,
+    }
+
+    impl<FCost, FIsDest, I, FFilter> TraverserBuilder<FCost, FIsDest, I, FFilter> where
         I : hex2d::Integer,
         I : hash::Hash,
         I : std::ops::AddAssign,
-        for <'a> &'a I: Add<&'a I, Output = I>,
-        FOpaqueness : Fn(Coordinate<I>) -> I,
-        FVisible : FnMut(Coordinate<I>, I)
+        FCost : FnMut(Coordinate<I>, Coordinate<I>) -> Option<u32>,
+        FIsDest : FnMut(Coordinate<I>) -> bool,
+        FFilter : FnMut(Coordinate<I>, Coordinate<I>, Direction) -> bool
+    {
+        /// Set the cost of a step between neighbors
+        pub fn cost<F>(self, cost : F) -> TraverserBuilder<F, FIsDest, I, FFilter> where
+            F : FnMut(Coordinate<I>, Coordinate<I>) -> Option<u32>
         {
-            for dir in dirs.iter() {
-                let mut visited = HashSet::new();
-                los_rec::<FOpaqueness, FVisible, I>(
-                    opaqueness, visible, light, pos, pos, *dir, &mut visited
-                    );
+            TraverserBuilder {
+                cost,
+                is_dest: self.is_dest,
+                start: self.start,
+                portals: self.portals,
+                topology: self.topology,
+                tie_break: self.tie_break,
+                neighbor_filter: self.neighbor_filter,
+            }
+        }
+
+        /// Set the destination condition
+        pub fn is_dest<F>(self, is_dest : F) -> TraverserBuilder<FCost, F, I, FFilter> where
+            F : FnMut(Coordinate<I>) -> bool
+        {
+            TraverserBuilder {
+                cost: self.cost,
+                is_dest,
+                start: self.start,
+                portals: self.portals,
+                topology: self.topology,
+                tie_break: self.tie_break,
+                neighbor_filter: self.neighbor_filter,
+            }
+        }
+
+        /// Stop movement upon entering Coordinates for which `in_zoc` returns true
+        ///
+        /// See `bfs::TraverserBuilder::zone_of_control`. Portals can still be taken from them.
+        pub fn zone_of_control<F>(self, mut in_zoc : F) -> TraverserBuilder<impl StepCost<I>, FIsDest, I, FFilter> where
+            F : FnMut(Coordinate<I>) -> bool
+        {
+            let start = self.start;
+            let mut cost = self.cost;
+            TraverserBuilder {
+                cost: move |from, to| if from != start && in_zoc(from) { None } else { cost(from, to) },
+                is_dest: self.is_dest,
+                start,
+                portals: self.portals,
+                topology: self.topology,
+                tie_break: self.tie_break,
+                neighbor_filter: self.neighbor_filter,
+            }
+        }
+
+        /// Break ties between equally cheap Coordinates at random, seeded from `rng`
+        ///
+        /// See `bfs::TraverserBuilder::shuffle`.
+        pub fn shuffle<R>(mut self, rng : &mut R) -> Self where
+            R : Rng
+        {
+            self.tie_break = TieBreak::Random(Box::new(StdRng::seed_from_u64(rng.gen())));
+            self
+        }
+
+        /// Among equally cheap paths, prefer ones that keep going in the same direction
+        ///
+        /// See `bfs::TraverserBuilder::prefer_straight`.
+        pub fn prefer_straight(mut self) -> Self {
+            self.tie_break = TieBreak::Straight;
+            self
+        }
+
+        /// Add one-way `portals`
+        ///
+        /// Add both directions for portals that work both ways.
+        pub fn portals(mut self, portals : &[Portal<I>]) -> Self {
+            self.portals.extend_from_slice(portals);
+            self
+        }
+
+        /// Walk the world of `topology` (see `bfs::TraverserBuilder::topology`)
+        pub fn topology(mut self, topology : Topology<I>) -> Self {
+            self.topology = topology;
+            self
+        }
+
+        /// Take only neighbor steps for which `filter(from, to, direction)` returns true
+        ///
+        /// See `bfs::TraverserBuilder::neighbor_filter`. Portals are not filtered.
+        pub fn neighbor_filter<F>(self, filter : F) -> TraverserBuilder<FCost, FIsDest, I, F> where
+            F : FnMut(Coordinate<I>, Coordinate<I>, Direction) -> bool
+        {
+            TraverserBuilder {
+                cost: self.cost,
+                is_dest: self.is_dest,
+                start: self.start,
+                portals: self.portals,
+                topology: self.topology,
+                tie_break: self.tie_break,
+                neighbor_filter: filter,
+            }
+        }
+
+        /// Create the Traverser
+        pub fn build(self) -> Traverser<FCost, FIsDest, I, FFilter> {
+            let mut traverser = Traverser::with_filter(self.cost, self.is_dest, self.topology.wrap(self.start), self.neighbor_filter);
+            for (from, to, cost) in self.portals {
+                let (from, to) = (self.topology.wrap(from), self.topology.wrap(to));
+                traverser.portals.entry(from).or_default().push((to, cost));
+            }
+            traverser.topology = self.topology;
+            traverser.tie_break = self.tie_break;
+            traverser
+        }
+    }
+
+    impl<I> Traverser<UnitCost<I>, Anything<I>, I> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : std::ops::AddAssign
+    {
+        /// Start building a Traverser starting at `start`
+        pub fn builder(start : Coordinate<I>) -> TraverserBuilder<UnitCost<I>, Anything<I>, I> {
+            TraverserBuilder {
+                cost: unit_cost,
+                is_dest: anything,
+                start,
+                portals: vec!(),
+                topology: Topology::Flat,
+                tie_break: TieBreak::Fixed,
+                neighbor_filter: any_step,
+            }
+        }
+    }
+
+    impl<FCost, FIsDest, I> Traverser<FCost, FIsDest, I> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : std::ops::AddAssign,
+        FCost : FnMut(Coordinate<I>, Coordinate<I>) -> Option<u32>,
+        FIsDest : FnMut(Coordinate<I>) -> bool
+    {
+        /// Create a Traverser instance with initial conditions
+        pub fn new(cost : FCost, is_dest : FIsDest, start : Coordinate<I>) -> Traverser<FCost, FIsDest, I> {
+            Traverser::with_filter(cost, is_dest, start, any_step)
+        }
+    }
+
+    impl<FCost, FIsDest, I, FFilter> Traverser<FCost, FIsDest, I, FFilter> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : std::ops::AddAssign,
+        FCost : FnMut(Coordinate<I>, Coordinate<I>) -> Option<u32>,
+        FIsDest : FnMut(Coordinate<I>) -> bool,
+        FFilter : FnMut(Coordinate<I>, Coordinate<I>, Direction) -> bool
+    {
+        /// Like `new`, taking only neighbor steps allowed by `neighbor_filter`
+        fn with_filter(
+            cost : FCost,
+            is_dest : FIsDest,
+            start : Coordinate<I>,
+            neighbor_filter : FFilter,
+            ) -> Traverser<FCost, FIsDest, I, FFilter> {
+            let mut visited = InternalHashMap::default();
+            visited.insert(start, Visited{prev: start, cost: 0, done: false, turns: 0});
+
+            let mut to_traverse = BinaryHeap::new();
+            to_traverse.push(Reverse((0, 0, start)));
+
+            Traverser {
+                visited,
+                to_traverse,
+                cost,
+                is_dest,
+                start,
+                portals: InternalHashMap::default(),
+                topology: Topology::Flat,
+                tie_break: TieBreak::Fixed,
+                neighbor_filter,
+            }
+        }
+
+        /// Reach `npos` from `pos` with a total of `ncost`, if that's cheaper than known
+        fn relax(&mut self, pos : Coordinate<I>, npos : Coordinate<I>, ncost : u32) {
+            let turns = match self.tie_break {
+                TieBreak::Straight => {
+                    let entry = &self.visited[&pos];
+                    entry.turns + turn(entry.prev, pos, npos)
+                },
+                _ => 0,
+            };
+            let visited = Visited{prev: pos, cost: ncost, done: false, turns};
+            match self.visited.entry(npos) {
+                Occupied(mut entry) if !entry.get().done && entry.get().cost == ncost => {
+                    if entry.get().turns > turns {
+                        entry.insert(visited);
+                    }
+                    return;
+                },
+                Occupied(entry) if entry.get().done || entry.get().cost <= ncost => return,
+                Occupied(mut entry) => { entry.insert(visited); },
+                Vacant(entry) => { entry.insert(visited); },
+            }
+            let tie = match self.tie_break {
+                TieBreak::Random(ref mut rng) => rng.gen(),
+                _ => 0,
+            };
+            self.to_traverse.push(Reverse((ncost, tie, npos)));
+        }
+
+        /// Find next cheapest Coordinate.
+        ///
+        /// Can be called multiple times, each time returning next Coordinate
+        pub fn find(&mut self) -> Option<Coordinate<I>> {
+            loop {
+                match self.step() {
+                    SearchStatus::InProgress => {},
+                    SearchStatus::Found(pos) => return Some(pos),
+                    SearchStatus::Exhausted => return None,
+                }
+            }
+        }
+
+        /// Like `find`, but give up after expanding `budget` Coordinates
+        ///
+        /// Returns `InProgress` if the budget ran out first; calling it again continues where
+        /// it stopped.
+        pub fn run_for(&mut self, budget : usize) -> SearchStatus<I> {
+            for _ in 0..budget {
+                match self.step() {
+                    SearchStatus::InProgress => {},
+                    status => return status,
+                }
+            }
+            SearchStatus::InProgress
+        }
+
+        /// Like `find`, but stop early when `cancelled` returns true
+        ///
+        /// See `bfs::Traverser::find_unless`.
+        pub fn find_unless<F>(&mut self, mut cancelled : F) -> SearchStatus<I> where
+            F : FnMut() -> bool
+        {
+            loop {
+                if cancelled() {
+                    return SearchStatus::InProgress;
+                }
+                match self.run_for(CANCEL_CHECK_INTERVAL) {
+                    SearchStatus::InProgress => {},
+                    status => return status,
+                }
+            }
+        }
+
+        /// Expand a single Coordinate, skipping outdated queue entries
+        fn step(&mut self) -> SearchStatus<I> {
+            loop {
+                let Reverse((cost, _, pos)) = match self.to_traverse.pop() {
+                    Some(entry) => entry,
+                    None => return SearchStatus::Exhausted,
+                };
+
+                {
+                    let entry = self.visited.get_mut(&pos).expect("Dijkstra: Should have been visited already");
+                    if entry.done || entry.cost != cost {
+                        continue;
+                    }
+                    entry.done = true;
+                }
+
+                for (&npos, &dir) in self.topology.neighbors(pos).iter().zip(Direction::all().iter()) {
+                    if !(self.neighbor_filter)(pos, npos, dir) {
+                        continue;
+                    }
+                    if let Some(step) = (self.cost)(pos, npos) {
+                        self.relax(pos, npos, cost.saturating_add(step));
+                    }
+                }
+
+                let portals = self.portals.get(&pos).cloned().unwrap_or_default();
+                for (npos, step) in portals {
+                    self.relax(pos, npos, cost.saturating_add(step));
+                }
+
+                if (self.is_dest)(pos) {
+                    return SearchStatus::Found(pos);
+                }
+                return SearchStatus::InProgress;
             }
         }
+
+        /// Cost of the cheapest path from `start` to `pos` found so far
+        ///
+        /// Final for Coordinates returned by `find`. Returns `None` for Coordinates that were
+        /// not yet reached.
+        pub fn distance(&self, pos : Coordinate<I>) -> Option<u32> {
+            self.visited.get(&pos).map(|entry| entry.cost)
+        }
+
+        /// Return Coordinate one step closer to `start` on the cheapest path to `pos`
+        ///
+        /// It's a neighbor of `pos`, or the other end of a portal leading to it.
+        ///
+        /// Returns `None` for Coordinates that were not yet reached.
+        /// Returns `start` for `start` (from initial conditions)
+        pub fn backtrace(&self, pos : Coordinate<I>) -> Option<Coordinate<I>> {
+            self.visited.get(&pos).map(|entry| entry.prev)
+        }
+
+        /// Whole path from `start` to `pos`, both included
+        ///
+        /// Returns `None` for Coordinates that were not yet reached.
+        pub fn path(&self, mut pos : Coordinate<I>) -> Option<Vec<Coordinate<I>>> {
+            let mut path = vec!(pos);
+            while pos != self.start {
+                pos = self.backtrace(pos)?;
+                path.push(pos);
+            }
+            path.reverse();
+            Some(path)
+        }
+    }
+
+    /// Find the most attractive of weighted `targets`, and the cheapest path to it
+    ///
+    /// `targets` are `(Coordinate, priority)` pairs, and the best one has the smallest
+    /// `path cost / priority`, so eg. food with priority `3` is preferred over gold with
+    /// priority `1`, unless it's more than three times further away. Targets with non-positive
+    /// priority are ignored. `cost` works like in `Traverser`.
+    ///
+    /// A single search is used for all the targets, and stops as soon as no unreached target
+    /// can be better than the best one found. Returns `None` if no target can be reached.
+    /// Bound the map with `cost`, or the search never ends if no target can be reached.
+    pub fn best_target<FCost, I>(
+        start : Coordinate<I>,
+        cost : FCost,
+        targets : &[(Coordinate<I>, f32)],
+        ) -> Option<(Coordinate<I>, Vec<Coordinate<I>>)> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : std::ops::AddAssign,
+        FCost : FnMut(Coordinate<I>, Coordinate<I>) -> Option<u32>
+    {
+        let mut priorities : InternalHashMap<Coordinate<I>, f32> = InternalHashMap::default();
+        for &(c, priority) in targets.iter().filter(|&&(_, priority)| priority > 0.0) {
+            let entry = priorities.entry(c).or_insert(priority);
+            *entry = entry.max(priority);
+        }
+        if priorities.is_empty() {
+            return None;
+        }
+        let max_priority = priorities.values().cloned().fold(0.0, f32::max);
+
+        let mut traverser = Traverser::new(cost, |_| true, start);
+        let mut best : Option<(f32, Coordinate<I>)> = None;
+        let mut remaining = priorities.len();
+
+        while let Some(pos) = traverser.find() {
+            let dist = traverser.distance(pos).unwrap() as f32;
+            if best.is_some_and(|(score, _)| dist / max_priority >= score) {
+                break;
+            }
+
+            if let Some(&priority) = priorities.get(&pos) {
+                let score = dist / priority;
+                if best.is_none_or(|(best, _)| score < best) {
+                    best = Some((score, pos));
+                }
+                remaining -= 1;
+                if remaining == 0 {
+                    break;
+                }
+            }
+        }
+
+        best.map(|(_, c)| (c, traverser.path(c).unwrap()))
+    }
+
+    /// Find the safest Coordinate reachable within `budget`, and the cheapest path to it
+    ///
+    /// `safety` scores Coordinates, higher being safer: eg. the distance to the nearest of
+    /// threats, or the value of an `InfluenceMap` of allies. Among equally safe ones the
+    /// cheapest to reach is picked, then the smallest. `cost` works like in `Traverser`.
+    ///
+    /// `start` always qualifies, so staying put is returned when nothing is safer. The search
+    /// never goes further than `budget`, so the map doesn't have to be bounded.
+    pub fn flee<FCost, FSafety, I>(
+        start : Coordinate<I>,
+        budget : u32,
+        cost : FCost,
+        mut safety : FSafety,
+        ) -> (Coordinate<I>, Vec<Coordinate<I>>) where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : std::ops::AddAssign,
+        FCost : FnMut(Coordinate<I>, Coordinate<I>) -> Option<u32>,
+        FSafety : FnMut(Coordinate<I>) -> f32
+    {
+        let mut traverser = Traverser::new(cost, |_| true, start);
+        let mut best : Option<(f32, u32, Coordinate<I>)> = None;
+
+        while let Some(pos) = traverser.find() {
+            let dist = traverser.distance(pos).unwrap();
+            if dist > budget {
+                break;
+            }
+
+            let score = safety(pos);
+            let better = match best {
+                None => true,
+                Some((best_score, best_dist, best_pos)) => {
+                    score > best_score || (score == best_score && (dist, pos) < (best_dist, best_pos))
+                },
+            };
+            if better {
+                best = Some((score, dist, pos));
+            }
+        }
+
+        let (_, _, c) = best.expect("flee: start is always reached");
+        (c, traverser.path(c).unwrap())
+    }
+
+    /// Wrap a step `cost` to allow only steps for which `filter(from, to, direction)` returns
+    /// true
+    ///
+    /// Brings `TraverserBuilder::neighbor_filter` to searches that only take a step cost, eg.
+    /// `astar::find`, `apsp::AllPairs` or `best_target`. Steps between non-adjacent
+    /// Coordinates (eg. portals) are not filtered.
+    pub fn filter_steps<FCost, FFilter, I>(mut cost : FCost, mut filter : FFilter) -> impl FnMut(Coordinate<I>, Coordinate<I>) -> Option<u32> where
+        I : hex2d::Integer,
+        FCost : FnMut(Coordinate<I>, Coordinate<I>) -> Option<u32>,
+        FFilter : FnMut(Coordinate<I>, Coordinate<I>, Direction) -> bool
+    {
+        move |from, to| {
+            let dir = if from.distance(to) == I::one() { from.direction_to_cw(to) } else { None };
+            match dir {
+                Some(dir) if !filter(from, to, dir) => None,
+                _ => cost(from, to),
+            }
+        }
+    }
+}
+
+/// A* cheapest path search, with landmark (ALT) heuristics and goal bounding
+///
+/// For repeated queries on a static map, `Landmarks` precomputes distances from a few
+/// Coordinates and gives a much better heuristic than plain hex distance on maze-like maps.
+/// `GoalBounds` goes further, pruning steps that can't lead to the destination.
+pub mod astar {
+    use hex2d::Coordinate;
+    use hex2d;
+
+    use hashing::InternalHashMap;
+
+    use std::hash;
+    use std::cmp::Reverse;
+    use std::collections::BinaryHeap;
+
+    use geom::Bounds;
+    use map::MapRevision;
+
+    use super::dijkstra;
+
+    /// Cheapest path from `start` to `dest`, with its cost
+    ///
+    /// `cost` works like in `dijkstra::Traverser`. `heuristic` estimates the cost from a
+    /// Coordinate to `dest`, and must never overestimate it, or the path found might not be
+    /// the cheapest. Returns `None` if `dest` can't be reached; bound the map with `cost`, or
+    /// the search never ends in that case.
+    pub fn find<FCost, FHeuristic, I>(
+        start : Coordinate<I>,
+        dest : Coordinate<I>,
+        mut cost : FCost,
+        mut heuristic : FHeuristic,
+        ) -> Option<(Vec<Coordinate<I>>, u32)> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        FCost : FnMut(Coordinate<I>, Coordinate<I>) -> Option<u32>,
+        FHeuristic : FnMut(Coordinate<I>) -> u32
+    {
+        // Coordinate -> (previous, cost so far, done)
+        let mut visited = InternalHashMap::default();
+        let mut to_traverse = BinaryHeap::new();
+
+        visited.insert(start, (start, 0, false));
+        to_traverse.push(Reverse((heuristic(start), 0, start)));
+
+        while let Some(Reverse((_, so_far, pos))) = to_traverse.pop() {
+            {
+                let entry : &mut (Coordinate<I>, u32, bool) = visited.get_mut(&pos).expect("A*: Should have been visited already");
+                if entry.2 || entry.1 != so_far {
+                    continue;
+                }
+                entry.2 = true;
+            }
+
+            if pos == dest {
+                let mut path = vec!(pos);
+                let mut pos = pos;
+                while pos != start {
+                    pos = visited[&pos].0;
+                    path.push(pos);
+                }
+                path.reverse();
+                return Some((path, so_far));
+            }
+
+            for &npos in pos.neighbors().iter() {
+                let step = match cost(pos, npos) {
+                    Some(step) => step,
+                    None => continue,
+                };
+                let ncost = so_far.saturating_add(step);
+                match visited.get(&npos) {
+                    Some(&(_, known, done)) if done || known <= ncost => continue,
+                    _ => {},
+                }
+                visited.insert(npos, (pos, ncost, false));
+                to_traverse.push(Reverse((ncost.saturating_add(heuristic(npos)), ncost, npos)));
+            }
+        }
+        None
+    }
+
+    /// Precomputed distances from landmark Coordinates
+    ///
+    /// By the triangle inequality, the difference of distances from a landmark to two
+    /// Coordinates never exceeds the distance between them, which makes an admissible
+    /// heuristic for `find`. Step costs must be symmetric (the same both ways).
+    #[derive(Clone, Debug)]
+    pub struct Landmarks<I = i32> where
+        I : hex2d::Integer,
+        I : hash::Hash
+    {
+        landmarks : Vec<Coordinate<I>>,
+        tables : Vec<InternalHashMap<Coordinate<I>, u32>>,
+        version : u64,
+    }
+
+    /// Cost of reaching everything reachable from `start`
+    fn table<FCost, I>(start : Coordinate<I>, cost : FCost) -> InternalHashMap<Coordinate<I>, u32> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : ::std::ops::AddAssign,
+        FCost : FnMut(Coordinate<I>, Coordinate<I>) -> Option<u32>
+    {
+        let mut search = dijkstra::Traverser::new(cost, |_| true, start);
+        let mut table = InternalHashMap::default();
+        while let Some(c) = search.find() {
+            table.insert(c, search.distance(c).unwrap());
+        }
+        table
+    }
+
+    impl<I> Landmarks<I> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : ::std::ops::AddAssign
+    {
+        /// Precompute distances from `landmarks`
+        ///
+        /// Runs a full search from every landmark, so bound the map with `cost`.
+        pub fn new<FCost>(landmarks : &[Coordinate<I>], mut cost : FCost) -> Landmarks<I> where
+            FCost : FnMut(Coordinate<I>, Coordinate<I>) -> Option<u32>
+        {
+            Landmarks {
+                landmarks: landmarks.to_vec(),
+                tables: landmarks.iter().map(|&l| table(l, &mut cost)).collect(),
+                version: 0,
+            }
+        }
+
+        /// Pick `count` landmarks spread over the area reachable from `start`, and precompute
+        /// distances from them
+        ///
+        /// Every landmark is the Coordinate furthest from the ones picked before (the first
+        /// one: furthest from `start`), so they end up around the edges, where they work best.
+        pub fn farthest<FCost>(start : Coordinate<I>, count : usize, mut cost : FCost) -> Landmarks<I> where
+            FCost : FnMut(Coordinate<I>, Coordinate<I>) -> Option<u32>
+        {
+            let mut res = Landmarks { landmarks: vec!(), tables: vec!(), version: 0 };
+            let mut closest = table(start, &mut cost);
+
+            for _ in 0..count {
+                let next = closest.iter()
+                    .filter(|&(_, &d)| d > 0)
+                    .max_by(|&(a, da), &(b, db)| da.cmp(db).then(b.cmp(a)))
+                    .map(|(&c, _)| c);
+                let next = match next {
+                    Some(next) => next,
+                    None => break,
+                };
+
+                let t = table(next, &mut cost);
+                for (c, d) in closest.iter_mut() {
+                    *d = (*d).min(t.get(c).cloned().unwrap_or(u32::MAX));
+                }
+                res.landmarks.push(next);
+                res.tables.push(t);
+            }
+            res
+        }
+
+        /// Landmark Coordinates
+        pub fn landmarks(&self) -> &[Coordinate<I>] {
+            &self.landmarks
+        }
+
+        /// Lower bound of the cost from `from` to `to`
+        pub fn heuristic(&self, from : Coordinate<I>, to : Coordinate<I>) -> u32 {
+            self.tables.iter()
+                .filter_map(|t| Some((*t.get(&from)?, *t.get(&to)?)))
+                .map(|(a, b)| a.abs_diff(b))
+                .max()
+                .unwrap_or(0)
+        }
+
+        /// Version of the map the distances were computed at
+        ///
+        /// `0` (matching a fresh `MapRevision`) until set with `set_revision`.
+        pub fn revision(&self) -> u64 {
+            self.version
+        }
+
+        /// Record that the distances match the current version of `revision`
+        pub fn set_revision(&mut self, revision : &MapRevision<I>) {
+            self.version = revision.version();
+        }
+
+        /// Are the distances out of date after edits recorded in `revision`
+        ///
+        /// Edits of reached Coordinates, or of ones next to them (eg. a wall that was opened),
+        /// can change the distances.
+        pub fn is_stale(&self, revision : &MapRevision<I>) -> bool {
+            let reached = |c : &Coordinate<I>| self.tables.iter().any(|t| t.contains_key(c));
+            revision.is_dirty_since(self.version) &&
+                revision.changed_since(self.version)
+                    .any(|c| reached(&c) || c.neighbors().iter().any(&reached))
+        }
+    }
+
+    /// Cube coordinate bounding box: `[min x, max x, min y, max y, min z, max z]`
+    type CubeBox<I> = [I; 6];
+
+    /// Goal bounding tables
+    ///
+    /// For every Coordinate of `Bounds` and each of its six neighbors, stores a box around
+    /// all the destinations for which the cheapest path starts with a step to that neighbor.
+    /// At query time, steps toward boxes that don't contain the destination can be skipped
+    /// without losing the cheapest path, so searches expand very few Coordinates.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct GoalBounds<I = i32> where
+        I : hex2d::Integer
+    {
+        bounds : Bounds<I>,
+        /// `[index * 6 + neighbor]`
+        boxes : Vec<Option<CubeBox<I>>>,
+        version : u64,
+    }
+
+    impl<I> GoalBounds<I> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : ::std::ops::AddAssign
+    {
+        /// Precompute goal bounds of all Coordinates of `bounds`
+        ///
+        /// `cost` works like in `dijkstra::Traverser`, and paths never leave `bounds`. Runs a
+        /// search from every Coordinate, so it's an offline step for static maps.
+        pub fn new<FCost>(bounds : Bounds<I>, mut cost : FCost) -> GoalBounds<I> where
+            FCost : FnMut(Coordinate<I>, Coordinate<I>) -> Option<u32>
+        {
+            let mut boxes : Vec<Option<CubeBox<I>>> = vec!(None; bounds.len() * 6);
+
+            for (i, start) in bounds.iter().enumerate() {
+                let neighbors = start.neighbors();
+                let within = |a, b| if bounds.contains(b) { cost(a, b) } else { None };
+                let mut search = dijkstra::Traverser::new(within, |_| true, start);
+                let mut first_step : InternalHashMap<Coordinate<I>, usize> = InternalHashMap::default();
+
+                while let Some(c) = search.find() {
+                    if c == start {
+                        continue;
+                    }
+                    let prev = search.backtrace(c).unwrap();
+                    let dir = if prev == start {
+                        neighbors.iter().position(|&n| n == c).unwrap()
+                    } else {
+                        first_step[&prev]
+                    };
+                    first_step.insert(c, dir);
+
+                    let b = &mut boxes[i * 6 + dir];
+                    *b = Some(match *b {
+                        None => [c.x, c.x, c.y, c.y, c.z(), c.z()],
+                        Some([x0, x1, y0, y1, z0, z1]) => [
+                            x0.min(c.x), x1.max(c.x),
+                            y0.min(c.y), y1.max(c.y),
+                            z0.min(c.z()), z1.max(c.z()),
+                        ],
+                    });
+                }
+            }
+
+            GoalBounds { bounds, boxes, version: 0 }
+        }
+
+        /// Can the cheapest path from `from` to `dest` start with a step to `to`
+        ///
+        /// True for anything outside of `bounds`, so it never prunes what it knows nothing about.
+        pub fn allows(&self, from : Coordinate<I>, to : Coordinate<I>, dest : Coordinate<I>) -> bool {
+            let i = match self.bounds.index(from) {
+                Some(i) => i,
+                None => return true,
+            };
+            let dir = match from.neighbors().iter().position(|&n| n == to) {
+                Some(dir) => dir,
+                None => return true,
+            };
+            self.boxes[i * 6 + dir].is_some_and(|[x0, x1, y0, y1, z0, z1]| {
+                x0 <= dest.x && dest.x <= x1 &&
+                    y0 <= dest.y && dest.y <= y1 &&
+                    z0 <= dest.z() && dest.z() <= z1
+            })
+        }
+
+        /// Wrap step `cost` for a search toward `dest`, blocking steps that can't be on the
+        /// cheapest path
+        ///
+        /// Works with any search taking step costs, eg. `find` or `dijkstra::Traverser`.
+        pub fn prune<'a, FCost>(&'a self, dest : Coordinate<I>, mut cost : FCost) -> impl FnMut(Coordinate<I>, Coordinate<I>) -> Option<u32> + 'a where
+            FCost : FnMut(Coordinate<I>, Coordinate<I>) -> Option<u32> + 'a
+        {
+            move |from, to| if self.allows(from, to, dest) { cost(from, to) } else { None }
+        }
+
+        /// Version of the map the tables were computed at
+        ///
+        /// `0` (matching a fresh `MapRevision`) until set with `set_revision`.
+        pub fn revision(&self) -> u64 {
+            self.version
+        }
+
+        /// Record that the tables match the current version of `revision`
+        pub fn set_revision(&mut self, revision : &MapRevision<I>) {
+            self.version = revision.version();
+        }
+
+        /// Are the tables out of date after edits recorded in `revision` within `bounds`
+        pub fn is_stale(&self, revision : &MapRevision<I>) -> bool {
+            revision.is_dirty_since(self.version) &&
+                revision.changed_since(self.version).any(|c| self.bounds.contains(c))
+        }
+    }
+}
+
+/// All-pairs cheapest paths over a small region
+///
+/// Precomputes costs and first steps between every two Coordinates of `Bounds`, so path
+/// queries take no search at all. Takes `O(n^2)` memory, so it's meant for small tactical
+/// arenas (eg. hexagons of radius up to 32) queried many times.
+pub mod apsp {
+    use hex2d::Coordinate;
+    use hex2d;
+
+    use std::hash;
+
+    use geom::Bounds;
+    use map::MapRevision;
+
+    use super::dijkstra;
+
+    const NO_STEP : u8 = u8::MAX;
+
+    /// Cost and first step tables between all pairs of Coordinates of a region
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct AllPairs<I = i32> where
+        I : hex2d::Integer
+    {
+        bounds : Bounds<I>,
+        /// `[from * len + to]`, `u32::MAX` if unreachable
+        cost : Vec<u32>,
+        /// `[from * len + to]`, index of the neighbor to step to, or `NO_STEP`
+        next : Vec<u8>,
+        version : u64,
+    }
+
+    impl<I> AllPairs<I> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : ::std::ops::AddAssign
+    {
+        /// Precompute cheapest paths between all Coordinates of `bounds`
+        ///
+        /// `cost` works like in `dijkstra::Traverser`, and doesn't have to be symmetric. Paths
+        /// never leave `bounds`. Runs a search to every Coordinate, so it takes a while for
+        /// larger regions.
+        pub fn new<FCost>(bounds : Bounds<I>, mut cost : FCost) -> AllPairs<I> where
+            FCost : FnMut(Coordinate<I>, Coordinate<I>) -> Option<u32>
+        {
+            let len = bounds.len();
+            let mut res = AllPairs {
+                bounds,
+                cost: vec!(u32::MAX; len * len),
+                next: vec!(NO_STEP; len * len),
+                version: 0,
+            };
+
+            for (to_i, to) in bounds.iter().enumerate() {
+                // Search backwards, from `to`, over reversed steps
+                let reversed = |a, b| if bounds.contains(b) { cost(b, a) } else { None };
+                let mut search = dijkstra::Traverser::new(reversed, |_| true, to);
+                while let Some(from) = search.find() {
+                    let from_i = bounds.index(from).unwrap();
+                    res.cost[from_i * len + to_i] = search.distance(from).unwrap();
+                    if from != to {
+                        let step = search.backtrace(from).unwrap();
+                        let dir = from.neighbors().iter().position(|&n| n == step).unwrap();
+                        res.next[from_i * len + to_i] = dir as u8;
+                    }
+                }
+            }
+            res
+        }
+
+        /// Region covered by the tables
+        pub fn bounds(&self) -> Bounds<I> {
+            self.bounds
+        }
+
+        /// Version of the map the tables were computed at
+        ///
+        /// `0` (matching a fresh `MapRevision`) until set with `set_revision`.
+        pub fn revision(&self) -> u64 {
+            self.version
+        }
+
+        /// Record that the tables match the current version of `revision`
+        pub fn set_revision(&mut self, revision : &MapRevision<I>) {
+            self.version = revision.version();
+        }
+
+        /// Are the tables out of date after edits recorded in `revision` within `bounds`
+        pub fn is_stale(&self, revision : &MapRevision<I>) -> bool {
+            revision.is_dirty_since(self.version) &&
+                revision.changed_since(self.version).any(|c| self.bounds.contains(c))
+        }
+
+        fn entry(&self, from : Coordinate<I>, to : Coordinate<I>) -> Option<usize> {
+            Some(self.bounds.index(from)? * self.bounds.len() + self.bounds.index(to)?)
+        }
+
+        /// Cost of the cheapest path from `from` to `to`
+        ///
+        /// Returns `None` if `to` can't be reached, or either is outside of `bounds`.
+        pub fn cost(&self, from : Coordinate<I>, to : Coordinate<I>) -> Option<u32> {
+            self.entry(from, to).map(|i| self.cost[i]).filter(|&c| c != u32::MAX)
+        }
+
+        /// First step of the cheapest path from `from` to `to`
+        ///
+        /// Returns `None` if `to` can't be reached, or `from == to`.
+        pub fn next_step(&self, from : Coordinate<I>, to : Coordinate<I>) -> Option<Coordinate<I>> {
+            let i = self.entry(from, to)?;
+            if self.next[i] == NO_STEP {
+                return None;
+            }
+            Some(from.neighbors()[self.next[i] as usize])
+        }
+
+        /// Whole cheapest path from `from` to `to`, both included
+        pub fn path(&self, from : Coordinate<I>, to : Coordinate<I>) -> Option<Vec<Coordinate<I>>> {
+            self.cost(from, to)?;
+            let mut path = vec!(from);
+            let mut pos = from;
+            while pos != to {
+                pos = self.next_step(pos, to)?;
+                path.push(pos);
+            }
+            Some(path)
+        }
+    }
+}
+
+/// Paths found by searches
+pub mod path {
+    use hex2d;
+    use hex2d::Coordinate;
+    use hex2d::Direction;
+
+    use std::hash;
+
+    use hashing::OutputHashSet;
+
+    /// Part of a simplified Path (see `Path::simplify`)
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub enum Leg<I = i32> where
+        I : hex2d::Integer
+    {
+        /// `length` steps in `direction`
+        Walk {
+            /// Where the leg starts
+            start : Coordinate<I>,
+            /// Direction of every step
+            direction : Direction,
+            /// Number of steps
+            length : u32,
+        },
+        /// A single step between non-adjacent Coordinates
+        Jump {
+            /// Where the leg starts
+            from : Coordinate<I>,
+            /// Where the leg ends
+            to : Coordinate<I>,
+        },
+    }
+
+    impl<I> Leg<I> where
+        I : hex2d::Integer
+    {
+        /// Where the leg starts
+        pub fn start(&self) -> Coordinate<I> {
+            match *self {
+                Leg::Walk { start, .. } => start,
+                Leg::Jump { from, .. } => from,
+            }
+        }
+
+        /// Where the leg ends
+        pub fn end(&self) -> Coordinate<I> {
+            match *self {
+                Leg::Walk { start, direction, length } => {
+                    start + Coordinate::from(direction).scale(I::from_u32(length).unwrap())
+                },
+                Leg::Jump { to, .. } => to,
+            }
+        }
+    }
+
+    /// Walk through consecutive Coordinates, from the current position to the destination
+    ///
+    /// Also remembers movement points already spent on the next step, so steps more
+    /// expensive than a whole turn of movement can be taken over several turns.
+    #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct Path<I = i32> where
+        I : hex2d::Integer
+    {
+        coordinates : Vec<Coordinate<I>>,
+        progress : u32,
+    }
+
+    impl<I> Path<I> where
+        I : hex2d::Integer
+    {
+        /// Create a Path through `coordinates`, starting at the first one
+        ///
+        /// Panics if `coordinates` are empty.
+        pub fn new(coordinates : Vec<Coordinate<I>>) -> Path<I> {
+            assert!(!coordinates.is_empty(), "Path::new: no coordinates");
+            Path {
+                coordinates,
+                progress: 0,
+            }
+        }
+
+        /// Current position
+        pub fn start(&self) -> Coordinate<I> {
+            self.coordinates[0]
+        }
+
+        /// Destination
+        pub fn end(&self) -> Coordinate<I> {
+            *self.coordinates.last().unwrap()
+        }
+
+        /// Number of steps left
+        pub fn len(&self) -> usize {
+            self.coordinates.len() - 1
+        }
+
+        /// Is the destination reached
+        pub fn is_empty(&self) -> bool {
+            self.len() == 0
+        }
+
+        /// All Coordinates, from the current position to the destination
+        pub fn coordinates(&self) -> &[Coordinate<I>] {
+            &self.coordinates
+        }
+
+        /// Movement points already spent on the next step
+        pub fn progress(&self) -> u32 {
+            self.progress
+        }
+
+        /// Walk the Path with `points` of movement
+        ///
+        /// `cost(from, to)` gives the cost of every step, or `None` if it became blocked since
+        /// the Path was found. Walking stops at a blocked step, at the destination, or when
+        /// movement runs out; points left over from a step that couldn't be completed are
+        /// kept as progress towards it, for the next call.
+        ///
+        /// Returns the reached Coordinate and the rest of the Path, starting at it.
+        pub fn advance<FCost>(mut self, points : u32, mut cost : FCost) -> (Coordinate<I>, Path<I>) where
+            FCost : FnMut(Coordinate<I>, Coordinate<I>) -> Option<u32>
+        {
+            let mut points = points.saturating_add(self.progress);
+            let mut i = 0;
+            self.progress = 0;
+
+            while i + 1 < self.coordinates.len() {
+                match cost(self.coordinates[i], self.coordinates[i + 1]) {
+                    None => break,
+                    Some(c) if c > points => {
+                        self.progress = points;
+                        break;
+                    },
+                    Some(c) => {
+                        points -= c;
+                        i += 1;
+                    },
+                }
+            }
+
+            self.coordinates.drain(..i);
+            (self.start(), self)
+        }
+
+        /// Continue the Path with `other`
+        ///
+        /// Panics if `other` doesn't start where the Path ends.
+        pub fn append(&mut self, other : Path<I>) {
+            assert!(other.start() == self.end(), "Path::append: paths don't connect");
+            if self.is_empty() {
+                self.progress = other.progress;
+            }
+            self.coordinates.extend(other.coordinates.into_iter().skip(1));
+        }
+
+        /// Split the Path at the first occurrence of `c`
+        ///
+        /// Returns the part leading to `c` (with the progress of the Path) and the part
+        /// starting at it, or `None` if the Path doesn't go through `c`.
+        pub fn split_at(&self, c : Coordinate<I>) -> Option<(Path<I>, Path<I>)> {
+            let i = self.coordinates.iter().position(|&pos| pos == c)?;
+            let mut head = Path::new(self.coordinates[..=i].to_vec());
+            if !head.is_empty() {
+                head.progress = self.progress;
+            }
+            Some((head, Path::new(self.coordinates[i..].to_vec())))
+        }
+
+        /// Cut the Path right before the first Coordinate for which `can_pass` returns false
+        ///
+        /// The current position is never checked. Returns true if anything was cut.
+        pub fn truncate_at_first_blocked<FCanPass>(&mut self, mut can_pass : FCanPass) -> bool where
+            FCanPass : FnMut(Coordinate<I>) -> bool
+        {
+            let blocked = match self.coordinates.iter().skip(1).position(|&c| !can_pass(c)) {
+                Some(i) => i + 1,
+                None => return false,
+            };
+            self.coordinates.truncate(blocked);
+            if self.is_empty() {
+                self.progress = 0;
+            }
+            true
+        }
+
+        /// The same Path, walked from the destination back to the current position
+        ///
+        /// Progress is not kept.
+        pub fn reverse(&self) -> Path<I> {
+            Path::new(self.coordinates.iter().rev().cloned().collect())
+        }
+
+        /// Collapse the Path into legs of straight walks
+        ///
+        /// Runs of steps in the same Direction become a single `Leg::Walk`, so a long path
+        /// shrinks to a handful of legs, eg. for sending over the network or animation
+        /// keyframes. Steps between non-adjacent Coordinates (eg. through portals) become
+        /// `Leg::Jump`s. `from_legs` turns them back into a Path.
+        pub fn simplify(&self) -> Vec<Leg<I>> {
+            let mut legs = vec!();
+            for w in self.coordinates.windows(2) {
+                let (from, to) = (w[0], w[1]);
+                if from.distance(to) != I::one() {
+                    legs.push(Leg::Jump { from, to });
+                    continue;
+                }
+                let dir = from.direction_to_cw(to).unwrap();
+                match legs.last_mut() {
+                    Some(&mut Leg::Walk { direction, ref mut length, .. }) if direction == dir => *length += 1,
+                    _ => legs.push(Leg::Walk { start: from, direction: dir, length: 1 }),
+                }
+            }
+            legs
+        }
+
+        /// Path walking through `legs`, eg. from `simplify`
+        ///
+        /// Panics if `legs` are empty, or don't continue one another.
+        pub fn from_legs(legs : &[Leg<I>]) -> Path<I> {
+            assert!(!legs.is_empty(), "Path::from_legs: no legs");
+            let mut coordinates = vec!(legs[0].start());
+            for leg in legs {
+                assert!(leg.start() == *coordinates.last().unwrap(), "Path::from_legs: legs don't connect");
+                match *leg {
+                    Leg::Walk { start, direction, length } => {
+                        let mut pos = start;
+                        for _ in 0..length {
+                            pos = pos + direction;
+                            coordinates.push(pos);
+                        }
+                    },
+                    Leg::Jump { to, .. } => coordinates.push(to),
+                }
+            }
+            Path::new(coordinates)
+        }
+
+        /// All Coordinates within `width` steps of the Path
+        ///
+        /// Steps are walked through Coordinates for which `can_pass` returns true, so the
+        /// corridor doesn't leak through walls. Coordinates of the Path are always included.
+        pub fn corridor<FCanPass>(&self, width : u32, mut can_pass : FCanPass) -> OutputHashSet<Coordinate<I>> where
+            I : hash::Hash,
+            FCanPass : FnMut(Coordinate<I>) -> bool
+        {
+            let mut res : OutputHashSet<_> = self.coordinates.iter().cloned().collect();
+            let mut frontier : Vec<_> = res.iter().cloned().collect();
+
+            for _ in 0..width {
+                let mut next = vec!();
+                for pos in frontier {
+                    for &npos in pos.neighbors().iter() {
+                        if !res.contains(&npos) && can_pass(npos) {
+                            res.insert(npos);
+                            next.push(npos);
+                        }
+                    }
+                }
+                frontier = next;
+            }
+            res
+        }
+    }
+
+    impl<I> From<Vec<Coordinate<I>>> for Path<I> where
+        I : hex2d::Integer
+    {
+        fn from(coordinates : Vec<Coordinate<I>>) -> Path<I> {
+            Path::new(coordinates)
+        }
+    }
+}
+
+/// Very tricky, but (hopefully) good enough, recursive LoS algorithm
+pub mod los {
+    use hex2d;
+    use hex2d::Angle::{Left,Right};
+
+    use hex2d::Direction;
+    use hex2d::Coordinate;
+
+    use num::Zero;
+    use std::ops::ControlFlow;
+
+    /// Light (and opaqueness) value of LoS algorithms
+    ///
+    /// Independent of the Coordinate integer type, so eg. `i16` Coordinates can carry `f32`
+    /// light. Implemented for all primitive integers and floats. Unsigned light never goes
+    /// below zero.
+    pub trait Light : Copy + PartialOrd + Zero {
+        /// `self` dimmed by `opaqueness`
+        fn dim(self, opaqueness : Self) -> Self;
+    }
+
+    macro_rules! light {
+        ($($t:ty),*) => { $(
+            impl Light for $t {
+                fn dim(self, opaqueness : Self) -> Self {
+                    self - opaqueness
+                }
+            }
+        )* }
+    }
+
+    macro_rules! unsigned_light {
+        ($($t:ty),*) => { $(
+            impl Light for $t {
+                fn dim(self, opaqueness : Self) -> Self {
+                    self.saturating_sub(opaqueness)
+                }
+            }
+        )* }
+    }
+
+    light!(i8, i16, i32, i64, isize, f32, f64);
+    unsigned_light!(u8, u16, u32, u64, usize);
+
+    /// Smaller of `a` and `b`, for partially ordered `Light`
+    pub(crate) fn min<L : Light>(a : L, b : L) -> L {
+        if b < a { b } else { a }
+    }
+
+    /// Result of a `visible` callback
+    ///
+    /// Callbacks returning `()` always continue. Returning `ControlFlow::Break` stops the whole
+    /// LoS traversal early, eg. as soon as a Coordinate of interest becomes visible.
+    pub trait Flow {
+        /// Should the traversal stop
+        fn is_break(&self) -> bool;
+    }
+
+    impl Flow for () {
+        fn is_break(&self) -> bool {
+            false
+        }
+    }
+
+    impl<B> Flow for ControlFlow<B> {
+        fn is_break(&self) -> bool {
+            ControlFlow::is_break(self)
+        }
+    }
+
+    /// Directions to continue the traversal in, after stepping in `dir` (`pdir` before)
+    ///
+    /// Fixed-size array and its used length, to avoid allocating on every step.
+    fn next_dirs(main_dir : Direction, dir : Option<Direction>, pdir : Option<Direction>) -> ([Direction; 3], usize) {
+        match (dir, pdir) {
+            (Some(dir), Some(pdir)) => {
+                if dir == pdir {
+                    ([dir, dir, dir], 1)
+                } else {
+                    ([dir, pdir, pdir], 2)
+                }
+            },
+            (Some(dir), None) => {
+                if main_dir == dir {
+                    ([dir, dir + Left, dir + Right], 3)
+                } else {
+                    ([dir, main_dir, main_dir], 2)
+                }
+            },
+            _ => {
+                ([main_dir, main_dir + Left, main_dir + Right], 3)
+            }
+        }
+    }
+
+    /// Returns true if the traversal was stopped
+    fn los_rec<FOpaqueness, FVisible, R, I, L>(
+        opaqueness : &mut FOpaqueness,
+        visible : &mut FVisible,
+        light: L,
+        pos : Coordinate<I>,
+        main_dir : Direction,
+        dir : Option<Direction>,
+        pdir : Option<Direction>,
+    ) -> bool where
+        I : hex2d::Integer,
+        I : std::ops::AddAssign,
+        L : Light,
+        FOpaqueness : FnMut(Coordinate<I>) -> L,
+        FVisible : FnMut(Coordinate<I>, L) -> R,
+        R : Flow
+        {
+
+            let mut light = light;
+            let opaq = opaqueness(pos);
+
+            if opaq >= light {
+                return false;
+            } else {
+                light = light.dim(opaq);
+            }
+
+            if visible(pos, light).is_break() {
+                return true;
+            }
+
+            let (neighbors, len) = next_dirs(main_dir, dir, pdir);
+            for &d in neighbors[..len].iter() {
+                let npos = pos + d;
+                let stop = match dir {
+                    Some(_) => los_rec(opaqueness, visible, light, npos, d, Some(d), dir),
+                    None => los_rec(opaqueness, visible, light, npos, main_dir, Some(d), dir),
+                };
+                if stop {
+                    return true;
+                }
+            }
+            false
+        }
+
+
+    /// Starting from `pos` in direction `dir`, call `visible` for each visible Coordinate.
+    ///
+    /// Use `light` as starting range of the LoS. For each visible Coordinate, the value returned
+    /// by `opaqueness` will be subtracted from `light` to check if the LoS should finish due to
+    /// "lack of visibility". `opaqueness` should typically return 1 for fully transparent
+    /// Coordinates, and anything bigger than initial `light` for fully opaque Coordinates.
+    ///
+    /// `opaqueness` can be `FnMut`, eg. to count or cache queries. `visible` can stop the
+    /// traversal early by returning `ControlFlow::Break` (see `Flow`).
+    pub fn los<FOpaqueness, FVisible, R, I, L>(
+        mut opaqueness : FOpaqueness,
+        visible : &mut FVisible,
+        light: L,
+        pos : Coordinate<I>,
+        dirs : &[Direction],
+    ) where
+        I : hex2d::Integer,
+        I : std::ops::AddAssign,
+        L : Light,
+        FOpaqueness : FnMut(Coordinate<I>) -> L,
+        FVisible : FnMut(Coordinate<I>, L) -> R,
+        R : Flow
+        {
+            for dir in dirs.iter() {
+                if los_rec(&mut opaqueness, visible, light, pos, *dir, None, None) {
+                    return;
+                }
+            }
+        }
+
+    /// Arguments of a pending `los_rec` call: light, position, main, current and previous
+    /// direction
+    type Step<I, L> = (L, Coordinate<I>, Direction, Option<Direction>, Option<Direction>);
+
+    /// Traversal of `los`, driven by `next` with an explicit stack instead of recursion
+    struct Iter<FOpaqueness, I, L> where
+        I : hex2d::Integer
+    {
+        opaqueness : FOpaqueness,
+        light : L,
+        pos : Coordinate<I>,
+        /// Directions left to traverse, last one first
+        dirs : Vec<Direction>,
+        stack : Vec<Step<I, L>>,
+    }
+
+    impl<FOpaqueness, I, L> Iterator for Iter<FOpaqueness, I, L> where
+        I : hex2d::Integer,
+        I : std::ops::AddAssign,
+        L : Light,
+        FOpaqueness : FnMut(Coordinate<I>) -> L
+    {
+        type Item = (Coordinate<I>, L);
+
+        fn next(&mut self) -> Option<(Coordinate<I>, L)> {
+            loop {
+                let (light, pos, main_dir, dir, pdir) = match self.stack.pop() {
+                    Some(step) => step,
+                    None => (self.light, self.pos, self.dirs.pop()?, None, None),
+                };
+
+                let opaq = (self.opaqueness)(pos);
+                if opaq >= light {
+                    continue;
+                }
+                let light = light.dim(opaq);
+
+                // Pushed in reverse, so they are popped in the order `los_rec` visits them
+                let (neighbors, len) = next_dirs(main_dir, dir, pdir);
+                for &d in neighbors[..len].iter().rev() {
+                    self.stack.push(match dir {
+                        Some(_) => (light, pos + d, d, Some(d), dir),
+                        None => (light, pos + d, main_dir, Some(d), dir),
+                    });
+                }
+                return Some((pos, light));
+            }
+        }
+    }
+
+    /// Like `los`, but returns an iterator of visible Coordinates and their light
+    ///
+    /// Coordinates are reported in the same order, and as many times, as `los` would pass them
+    /// to `visible`. The traversal runs lazily, as the iterator is advanced, so stopping early
+    /// (eg. with `find` or `take`) skips the rest of it.
+    pub fn iter<FOpaqueness, I, L>(
+        opaqueness : FOpaqueness,
+        light: L,
+        pos : Coordinate<I>,
+        dirs : &[Direction],
+    ) -> impl Iterator<Item = (Coordinate<I>, L)> where
+        I : hex2d::Integer,
+        I : std::ops::AddAssign,
+        L : Light,
+        FOpaqueness : FnMut(Coordinate<I>) -> L
+        {
+            Iter {
+                opaqueness,
+                light,
+                pos,
+                dirs: dirs.iter().rev().cloned().collect(),
+                stack: vec!(),
+            }
+        }
+}
+
+/// Combination of tricky Los with straight line checking
+pub mod los2 {
+    use hex2d;
+    use hex2d::Angle::{Left, Right, Forward};
+    use hex2d::Direction;
+    use hex2d::Coordinate;
+    use hashing::{InternalHashMap, InternalHashSet};
+    use super::los::{self, Flow, Light};
+    use std::hash;
+
+    fn los_check_line<FOpaqueness, I, L>(
+        opaqueness : &mut FOpaqueness,
+        light: L,
+        start : Coordinate<I>,
+        pos : Coordinate<I>,
+        ) -> (bool, L)
+        where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : std::ops::AddAssign,
+        L : Light,
+        FOpaqueness : FnMut(Coordinate<I>) -> L
+    {
+
+        let mut opaq_sum1 = L::zero();
+        let mut last1 = start;
+
+        let mut opaq_sum2 = L::zero();
+        let mut last2 = start;
+
+        for (c1, c2) in start.line_to_with_edge_detection_iter(pos) {
+            if opaq_sum1 < light {
+                let opaq1 = opaqueness(c1);
+                opaq_sum1 = opaq_sum1 + opaq1;
+                last1 = c1;
+            }
+
+            if opaq_sum2 < light {
+                let opaq2 = opaqueness(c2);
+                opaq_sum2 = opaq_sum2 + opaq2;
+                last2 = c2;
+            }
+        };
+
+        match (last1 == pos, last2 == pos) {
+            (true, true) => (true, light.dim(los::min(opaq_sum1, opaq_sum2))),
+            (true, false) => (true, light.dim(opaq_sum1)),
+            (false, true) => (true, light.dim(opaq_sum2)),
+            (false, false) => (false, L::zero()),
+        }
+    }
+
+    /// Returns true if the traversal was stopped
+    fn los_rec<FOpaqueness, FVisible, R, I, L>(
+        opaqueness : &mut FOpaqueness,
+        visible : &mut FVisible,
+        light: L,
+        start : Coordinate<I>,
+        pos : Coordinate<I>,
+        dir : Direction,
+        visited : &mut InternalHashSet<Coordinate<I>>,
+    ) -> bool where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : std::ops::AddAssign,
+        L : Light,
+        FOpaqueness : FnMut(Coordinate<I>) -> L,
+        FVisible : FnMut(Coordinate<I>, L) -> R,
+        R : Flow
+        {
+            if !visited.insert(pos) {
+                return false;
+            }
+
+            let (directly_visible, v_light) = los_check_line(
+                opaqueness,
+                light,
+                start,
+                pos);
+
+            if directly_visible {
+                if visible(pos, v_light).is_break() {
+                    return true;
+                }
+            } else {
+                let dir_to = start.direction_to_cw(pos).unwrap_or(dir);
+                let neighbors = [Left, Right];
+                for npos in neighbors.iter()
+                    .map(|&rd| dir_to + rd)
+                        .map(|dir| pos + dir) {
+
+                            let (side_visible, v_light) = los_check_line(
+                                opaqueness,
+                                light,
+                                start,
+                                npos,);
+
+                            if side_visible && visible(pos, v_light).is_break() {
+                                return true;
+                            }
+                        }
+                return false;
+            }
+
+            let neighbors = [Forward, Left, Right];
+
+            for &a in neighbors.iter() {
+                let npos = pos + (dir + a);
+                if los_rec(opaqueness, visible, light, start, npos, dir, visited) {
+                    return true;
+                }
+            }
+            false
+        }
+
+
+    /// Starting from `pos` in direction `dir`, call `visible` for each visible Coordinate.
+    ///
+    /// Use `light` as starting range of the LoS. For each visible Coordinate, the value returned
+    /// by `opaqueness` will be subtracted from `light` to check if the LoS should finish due to
+    /// "lack of visibility". `opaqueness` should typically return 1 for fully transparent
+    /// Coordinates, and anything bigger than initial `light` for fully opaque Coordinates.
+    ///
+    /// Lines to different Coordinates cross the same Coordinates many times, so results of
+    /// `opaqueness` are cached: it's called at most once per Coordinate during a single call.
+    /// It can be `FnMut`, eg. to count queries. `visible` can stop the traversal early by
+    /// returning `ControlFlow::Break` (see `los::Flow`).
+    pub fn los<FOpaqueness, FVisible, R, I, L>(
+        opaqueness : FOpaqueness,
+        visible : &mut FVisible,
+        light: L,
+        pos : Coordinate<I>,
+        dirs : &[Direction],
+    ) where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : std::ops::AddAssign,
+        L : Light,
+        FOpaqueness : FnMut(Coordinate<I>) -> L,
+        FVisible : FnMut(Coordinate<I>, L) -> R,
+        R : Flow
+        {
+            los_with_capacity(opaqueness, visible, light, pos, dirs, 0)
+        }
+
+    /// Like `los`, with room for `expected_nodes` Coordinates in the internal sets
+    ///
+    /// Avoids rehashing when the number of Coordinates within reach is known upfront.
+    pub fn los_with_capacity<FOpaqueness, FVisible, R, I, L>(
+        mut opaqueness : FOpaqueness,
+        visible : &mut FVisible,
+        light: L,
+        pos : Coordinate<I>,
+        dirs : &[Direction],
+        expected_nodes : usize,
+    ) where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : std::ops::AddAssign,
+        L : Light,
+        FOpaqueness : FnMut(Coordinate<I>) -> L,
+        FVisible : FnMut(Coordinate<I>, L) -> R,
+        R : Flow
+        {
+            let mut cache = InternalHashMap::with_capacity_and_hasher(expected_nodes, Default::default());
+            let mut cached = |c| *cache.entry(c).or_insert_with(|| opaqueness(c));
+
+            let mut visited = InternalHashSet::with_capacity_and_hasher(expected_nodes, Default::default());
+            for dir in dirs.iter() {
+                visited.clear();
+                if los_rec(&mut cached, visible, light, pos, pos, *dir, &mut visited) {
+                    return;
+                }
+            }
+        }
+
+    /// Traversal of `los`, driven by `next` with an explicit stack instead of recursion
+    struct Iter<FOpaqueness, I, L> where
+        I : hex2d::Integer,
+        I : hash::Hash
+    {
+        opaqueness : FOpaqueness,
+        cache : InternalHashMap<Coordinate<I>, L>,
+        visited : InternalHashSet<Coordinate<I>>,
+        light : L,
+        start : Coordinate<I>,
+        /// Directions left to traverse, last one first
+        dirs : Vec<Direction>,
+        /// Pending `los_rec` calls: position and direction
+        stack : Vec<(Coordinate<I>, Direction)>,
+        /// Visible Coordinates found but not returned yet, last one first
+        found : Vec<(Coordinate<I>, L)>,
+    }
+
+    impl<FOpaqueness, I, L> Iterator for Iter<FOpaqueness, I, L> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : std::ops::AddAssign,
+        L : Light,
+        FOpaqueness : FnMut(Coordinate<I>) -> L
+    {
+        type Item = (Coordinate<I>, L);
+
+        fn next(&mut self) -> Option<(Coordinate<I>, L)> {
+            let cache = &mut self.cache;
+            let opaqueness = &mut self.opaqueness;
+            let mut cached = |c| *cache.entry(c).or_insert_with(|| opaqueness(c));
+
+            loop {
+                if let Some(item) = self.found.pop() {
+                    return Some(item);
+                }
+
+                let (pos, dir) = match self.stack.pop() {
+                    Some(step) => step,
+                    None => {
+                        let dir = self.dirs.pop()?;
+                        self.visited.clear();
+                        (self.start, dir)
+                    },
+                };
+                if !self.visited.insert(pos) {
+                    continue;
+                }
+
+                let (directly_visible, v_light) = los_check_line(&mut cached, self.light, self.start, pos);
+                if directly_visible {
+                    // Pushed in reverse, so they are popped in the order `los_rec` visits them
+                    for &a in [Forward, Left, Right].iter().rev() {
+                        self.stack.push((pos + (dir + a), dir));
+                    }
+                    return Some((pos, v_light));
+                }
+
+                let dir_to = self.start.direction_to_cw(pos).unwrap_or(dir);
+                for &rd in [Right, Left].iter() {
+                    let (side_visible, v_light) = los_check_line(&mut cached, self.light, self.start, pos + (dir_to + rd));
+                    if side_visible {
+                        self.found.push((pos, v_light));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Like `los`, but returns an iterator of visible Coordinates and their light
+    ///
+    /// Coordinates are reported in the same order, and as many times, as `los` would pass them
+    /// to `visible`. The traversal runs lazily, as the iterator is advanced, so stopping early
+    /// (eg. with `find` or `take`) skips the rest of it.
+    pub fn iter<FOpaqueness, I, L>(
+        opaqueness : FOpaqueness,
+        light: L,
+        pos : Coordinate<I>,
+        dirs : &[Direction],
+    ) -> impl Iterator<Item = (Coordinate<I>, L)> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : std::ops::AddAssign,
+        L : Light,
+        FOpaqueness : FnMut(Coordinate<I>) -> L
+        {
+            Iter {
+                opaqueness,
+                cache: InternalHashMap::default(),
+                visited: InternalHashSet::default(),
+                light,
+                start: pos,
+                dirs: dirs.iter().rev().cloned().collect(),
+                stack: vec!(),
+                found: vec!(),
+            }
+        }
+}
+
+/// Brute-force reference LoS
+///
+/// Checks a straight line to every single Coordinate in range. Slow, but obviously correct,
+/// so it's useful for validating (and measuring artifacts of) the faster LoS algorithms.
+pub mod los_ref {
+    use hex2d;
+    use hex2d::Coordinate;
+
+    use std::cmp;
+    use std::hash;
+
+    use hashing::InternalHashMap;
+
+    use super::los::{self, Light};
+
+    /// Call `visible` for every Coordinate up to `radius` steps from `pos` that is visible
+    ///
+    /// A Coordinate is visible if the `opaqueness` of all Coordinates on a straight line from
+    /// `pos` to it (excluding itself) sums up to less than `light`. Lines passing exactly between
+    /// two Coordinates are checked on both sides, and the more transparent side is used.
+    /// `visible` gets `light` minus the opaqueness of the whole line, the same way as in `los2`.
+    pub fn los<FOpaqueness, FVisible, I, L>(
+        mut opaqueness : FOpaqueness,
+        visible : &mut FVisible,
+        light : L,
+        pos : Coordinate<I>,
+        radius : I,
+    ) where
+        I : hex2d::Integer,
+        I : std::ops::AddAssign,
+        L : Light,
+        FOpaqueness : FnMut(Coordinate<I>) -> L,
+        FVisible : FnMut(Coordinate<I>, L)
+    {
+        let r = radius.to_i32().unwrap();
+        for dz in -r..=r {
+            for dx in cmp::max(-r, -dz - r)..=cmp::min(r, -dz + r) {
+                let dx = I::from_i32(dx).unwrap();
+                let dz = I::from_i32(dz).unwrap();
+                let c = Coordinate::new(pos.x + dx, pos.y - dx - dz);
+                if let Some(l) = check_line(&mut opaqueness, light, pos, c) {
+                    visible(c, l);
+                }
+            }
+        }
+    }
+
+    /// Can `to` be seen from `from`
+    ///
+    /// Checks a single straight line, the same way as `los`.
+    pub fn can_see<FOpaqueness, I, L>(
+        mut opaqueness : FOpaqueness,
+        light : L,
+        from : Coordinate<I>,
+        to : Coordinate<I>,
+    ) -> bool where
+        I : hex2d::Integer,
+        I : std::ops::AddAssign,
+        L : Light,
+        FOpaqueness : FnMut(Coordinate<I>) -> L
+    {
+        check_line(&mut opaqueness, light, from, to).is_some()
+    }
+
+    /// Which of `observers` can see each other
+    ///
+    /// Returns a matrix in which `res[i][j]` tells if `observers[i]` and `observers[j]` can
+    /// see each other. Much cheaper than `can_see` for every pair: visibility is treated as
+    /// symmetric, so a single line is checked per pair, and results of `opaqueness` are
+    /// cached, so it's called at most once per Coordinate.
+    pub fn visibility_matrix<FOpaqueness, I, L>(
+        mut opaqueness : FOpaqueness,
+        light : L,
+        observers : &[Coordinate<I>],
+    ) -> Vec<Vec<bool>> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : std::ops::AddAssign,
+        L : Light,
+        FOpaqueness : FnMut(Coordinate<I>) -> L
+    {
+        let mut cache = InternalHashMap::default();
+        let mut cached = |c| *cache.entry(c).or_insert_with(|| opaqueness(c));
+
+        let n = observers.len();
+        let mut res = vec!(vec!(true; n); n);
+        for i in 0..n {
+            for j in i + 1..n {
+                let visible = check_line(&mut cached, light, observers[i], observers[j]).is_some();
+                res[i][j] = visible;
+                res[j][i] = visible;
+            }
+        }
+        res
+    }
+
+    fn check_line<FOpaqueness, I, L>(
+        opaqueness : &mut FOpaqueness,
+        light : L,
+        start : Coordinate<I>,
+        dest : Coordinate<I>,
+    ) -> Option<L> where
+        I : hex2d::Integer,
+        I : std::ops::AddAssign,
+        L : Light,
+        FOpaqueness : FnMut(Coordinate<I>) -> L
+    {
+        let mut sum1 = L::zero();
+        let mut sum2 = L::zero();
+        let mut open1 = true;
+        let mut open2 = true;
+
+        for (c1, c2) in start.line_to_with_edge_detection_iter(dest) {
+            if open1 && sum1 >= light {
+                open1 = false;
+            }
+            if open2 && sum2 >= light {
+                open2 = false;
+            }
+            sum1 = sum1 + opaqueness(c1);
+            sum2 = sum2 + opaqueness(c2);
+        }
+
+        match (open1, open2) {
+            (true, true) => Some(light.dim(los::min(sum1, sum2))),
+            (true, false) => Some(light.dim(sum1)),
+            (false, true) => Some(light.dim(sum2)),
+            (false, false) => None,
+        }
+    }
+}
+
+/// Field of view results
+pub mod fov {
+    use hex2d;
+    use hex2d::Coordinate;
+    use hex2d::Direction;
+
+    use std::hash;
+    use std::collections::HashSet;
+    use hashing::{InternalHashMap, OutputHashSet};
+
+    #[cfg(feature = "rayon")]
+    use rayon::prelude::*;
+
+    use geom::Topology;
+    use map::MapRevision;
+
+    use super::los2;
+    use super::los::Light;
+
+    /// Coordinates visible from `origin`, with the light that reached them
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct FovMap<I = i32, L = I> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        L : Light
+    {
+        origin : Coordinate<I>,
+        visible : InternalHashMap<Coordinate<I>, L>,
+        version : u64,
+    }
+
+    impl<I, L> FovMap<I, L> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        L : Light
+    {
+        /// Create an empty FovMap of an observer at `origin`
+        pub fn new(origin : Coordinate<I>) -> FovMap<I, L> {
+            FovMap {
+                origin,
+                visible: InternalHashMap::default(),
+                version: 0,
+            }
+        }
+
+        /// Position of the observer
+        pub fn origin(&self) -> Coordinate<I> {
+            self.origin
+        }
+
+        /// Mark `c` as visible with `light`, keeping the brighter value if already visible
+        ///
+        /// Meant to be called from `visible` callbacks of the LoS algorithms.
+        pub fn insert(&mut self, c : Coordinate<I>, light : L) {
+            let entry = self.visible.entry(c).or_insert(light);
+            if *entry < light {
+                *entry = light;
+            }
+        }
+
+        /// Is `c` visible
+        pub fn is_visible(&self, c : Coordinate<I>) -> bool {
+            self.visible.contains_key(&c)
+        }
+
+        /// Light that reached `c`, or `None` if it's not visible
+        pub fn light(&self, c : Coordinate<I>) -> Option<L> {
+            self.visible.get(&c).cloned()
+        }
+
+        /// Number of visible Coordinates
+        pub fn len(&self) -> usize {
+            self.visible.len()
+        }
+
+        /// Is nothing visible
+        pub fn is_empty(&self) -> bool {
+            self.visible.is_empty()
+        }
+
+        /// Iterator over visible Coordinates and their light
+        pub fn iter(&self) -> impl Iterator<Item = (Coordinate<I>, L)> + '_ {
+            self.visible.iter().map(|(&c, &l)| (c, l))
+        }
+
+        /// Visible Coordinates and their light, ordered by distance from the observer
+        ///
+        /// Coordinates at the same distance are sorted, so the order is always the same. Good
+        /// for revealing vision gradually, one ring after another.
+        pub fn by_distance(&self) -> Vec<(Coordinate<I>, L)> {
+            let mut res : Vec<_> = self.iter().collect();
+            res.sort_by(|&(a, _), &(b, _)| {
+                (self.origin.distance(a), a).cmp(&(self.origin.distance(b), b))
+            });
+            res
+        }
+
+        /// Version of the map this field of view was computed at
+        ///
+        /// `0` (matching a fresh `MapRevision`) until set with `set_revision`.
+        pub fn revision(&self) -> u64 {
+            self.version
+        }
+
+        /// Record that this field of view matches the current version of `revision`
+        pub fn set_revision(&mut self, revision : &MapRevision<I>) {
+            self.version = revision.version();
+        }
+
+        /// Is this field of view out of date after edits recorded in `revision`
+        ///
+        /// Only edits of visible Coordinates (walls bounding the view included) can change
+        /// what's visible, so a cached FovMap stays valid until one of them is edited.
+        pub fn is_stale(&self, revision : &MapRevision<I>) -> bool {
+            revision.is_dirty_since(self.version) &&
+                revision.changed_since(self.version).any(|c| self.is_visible(c))
+        }
+
+        /// Coordinates that became visible and hidden when going from `self` to `other`
+        ///
+        /// Returns `(gained, lost)`: Coordinates visible only in `other`, and the ones visible
+        /// only in `self`. Handy for redrawing just what changed after the observer moved.
+        pub fn diff(&self, other : &FovMap<I, L>) -> (Vec<Coordinate<I>>, Vec<Coordinate<I>>) {
+            let gained = other.visible.keys().filter(|c| !self.visible.contains_key(c)).cloned().collect();
+            let lost = self.visible.keys().filter(|c| !other.visible.contains_key(c)).cloned().collect();
+            (gained, lost)
+        }
+    }
+
+    /// Compute the `los2` field of view of an observer at `pos`
+    pub fn fov<FOpaqueness, I, L>(
+        opaqueness : FOpaqueness,
+        light : L,
+        pos : Coordinate<I>,
+        dirs : &[Direction],
+        ) -> FovMap<I, L> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : ::std::ops::AddAssign,
+        L : Light,
+        FOpaqueness : FnMut(Coordinate<I>) -> L
+    {
+        let mut map = FovMap::new(pos);
+        los2::los(opaqueness, &mut |c, l| map.insert(c, l), light, pos, dirs);
+        map
+    }
+
+    /// Like `fov`, but in the world of `topology`
+    ///
+    /// Visibility crosses the seams of wrapping worlds, and the result holds canonical
+    /// Coordinates only. `light` should stay below half of the wrapping period.
+    pub fn fov_wrapped<FOpaqueness, I, L>(
+        topology : Topology<I>,
+        mut opaqueness : FOpaqueness,
+        light : L,
+        pos : Coordinate<I>,
+        dirs : &[Direction],
+        ) -> FovMap<I, L> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : ::std::ops::AddAssign,
+        L : Light,
+        FOpaqueness : FnMut(Coordinate<I>) -> L
+    {
+        let mut map = FovMap::new(topology.wrap(pos));
+        los2::los(|c| opaqueness(topology.wrap(c)), &mut |c, l| map.insert(topology.wrap(c), l), light, pos, dirs);
+        map
+    }
+
+    /// Coordinates within `radius` of a light at `pos` that are shadowed by `obstacles`
+    ///
+    /// Obstacles are fully opaque and everything else is fully transparent. Obstacles
+    /// themselves are never in the shadow, and neither is anything beyond `radius`.
+    ///
+    /// Panics if `radius` is negative.
+    pub fn shadow<I, S>(
+        pos : Coordinate<I>,
+        obstacles : &HashSet<Coordinate<I>, S>,
+        radius : I,
+        ) -> OutputHashSet<Coordinate<I>> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        S : hash::BuildHasher,
+        I : ::std::ops::AddAssign
+    {
+        let light = radius.to_u32().expect("shadow: radius out of range") + 1;
+        let lit = fov(|c| if obstacles.contains(&c) { light } else { 1 }, light, pos, Direction::all());
+
+        pos.range_iter(radius)
+            .filter(|c| !lit.is_visible(*c) && !obstacles.contains(c))
+            .collect()
+    }
+
+    /// Field of view of an observer at `pos` looking toward `facing`
+    ///
+    /// Like `fov`, but light is attenuated by the angle from `facing`: full within 30 degrees
+    /// (the sextant straight ahead), multiplied by `peripheral` up to 90 degrees to the sides,
+    /// and nothing is visible further behind. Coordinates whose light drops to zero are left out.
+    pub fn fov_facing<FOpaqueness, I>(
+        opaqueness : FOpaqueness,
+        light : f32,
+        pos : Coordinate<I>,
+        facing : Direction,
+        peripheral : f32,
+        ) -> FovMap<I, f32> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : ::std::ops::AddAssign,
+        FOpaqueness : FnMut(Coordinate<I>) -> f32
+    {
+        let cube = |c : Coordinate<I>| [c.x.to_f64().unwrap(), c.y.to_f64().unwrap(), c.z().to_f64().unwrap()];
+        let dot = |u : [f64; 3], v : [f64; 3]| u[0] * v[0] + u[1] * v[1] + u[2] * v[2];
+        let ahead = cube(Coordinate::from(facing));
+
+        let mut map = FovMap::new(pos);
+        for (c, l) in fov(opaqueness, light, pos, Direction::all()).iter() {
+            let factor = if c == pos {
+                1.0
+            } else {
+                let v = cube(c - pos);
+                let cos = dot(v, ahead) / (dot(v, v) * dot(ahead, ahead)).sqrt();
+                let deviation = cos.clamp(-1.0, 1.0).acos().to_degrees();
+                if deviation <= 30.0 + 1e-9 {
+                    1.0
+                } else if deviation <= 90.0 + 1e-9 {
+                    peripheral
+                } else {
+                    0.0
+                }
+            };
+            let l = l * factor;
+            if l > 0.0 {
+                map.insert(c, l);
+            }
+        }
+        map
+    }
+
+    /// Compute `fov` for every `(position, light, directions)` observer, in parallel
+    ///
+    /// Observers are spread over the global `rayon` thread pool. Results are in the same order
+    /// as `observers`.
+    #[cfg(feature = "rayon")]
+    pub fn fov_many<FOpaqueness, I, L>(
+        opaqueness : &FOpaqueness,
+        observers : &[(Coordinate<I>, L, &[Direction])],
+        ) -> Vec<FovMap<I, L>> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : ::std::ops::AddAssign,
+        I : Send + Sync,
+        L : Light + Send + Sync,
+        FOpaqueness : Fn(Coordinate<I>) -> L + Sync
+    {
+        observers.par_iter()
+            .map(|&(pos, light, dirs)| fov(opaqueness, light, pos, dirs))
+            .collect()
+    }
+}
+
+/// Colored lighting
+///
+/// Light sources carry a color of `N` channels (eg. RGB), cast with `fov`. Channels of
+/// overlapping sources are added independently, so a red torch next to a blue glow gives
+/// purple.
+pub mod lighting {
+    use hex2d;
+    use hex2d::Coordinate;
+    use hex2d::Direction;
+
+    use std::hash;
+    use std::ops;
+    use hashing::InternalHashMap;
+
+    use super::fov;
+
+    /// Per-Coordinate light color, accumulated from many sources
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct LightMap<I = i32, const N : usize = 3> where
+        I : hex2d::Integer,
+        I : hash::Hash
+    {
+        values : InternalHashMap<Coordinate<I>, [f32; N]>,
+    }
+
+    impl<I, const N : usize> LightMap<I, N> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : ops::AddAssign
+    {
+        /// Create a completely dark map
+        pub fn new() -> LightMap<I, N> {
+            LightMap {
+                values: InternalHashMap::default(),
+            }
+        }
+
+        /// Light color at `pos`, all zeros if dark
+        pub fn get(&self, pos : Coordinate<I>) -> [f32; N] {
+            self.values.get(&pos).cloned().unwrap_or([0.0; N])
+        }
+
+        /// Add a light source of `color` at `pos`
+        ///
+        /// The light reaches everything `fov` with `opaqueness` and `light` can see, with every
+        /// channel scaled by the fraction of `light` left when reaching the Coordinate.
+        pub fn add_source<FOpaqueness>(
+            &mut self,
+            pos : Coordinate<I>,
+            color : [f32; N],
+            light : u32,
+            opaqueness : FOpaqueness,
+        ) where
+            FOpaqueness : FnMut(Coordinate<I>) -> u32
+        {
+            let lit = fov::fov(opaqueness, light, pos, Direction::all());
+            for (c, left) in lit.iter() {
+                let scale = left as f32 / light as f32;
+                let value = self.values.entry(c).or_insert([0.0; N]);
+                for (v, channel) in value.iter_mut().zip(color.iter()) {
+                    *v += channel * scale;
+                }
+            }
+        }
+
+        /// Iterator over all lit Coordinates and their color
+        pub fn iter(&self) -> impl Iterator<Item = (Coordinate<I>, [f32; N])> + '_ {
+            self.values.iter().map(|(&c, &v)| (c, v))
+        }
+    }
+
+    impl<I, const N : usize> Default for LightMap<I, N> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : ops::AddAssign
+    {
+        fn default() -> LightMap<I, N> {
+            LightMap::new()
+        }
+    }
+
+    impl<'a, I, const N : usize> ops::AddAssign<&'a LightMap<I, N>> for LightMap<I, N> where
+        I : hex2d::Integer,
+        I : hash::Hash
+    {
+        fn add_assign(&mut self, other : &'a LightMap<I, N>) {
+            for (&c, other) in other.values.iter() {
+                let value = self.values.entry(c).or_insert([0.0; N]);
+                for (v, channel) in value.iter_mut().zip(other.iter()) {
+                    *v += channel;
+                }
+            }
+        }
+    }
+}
+
+/// Fog of war
+pub mod fog {
+    use hex2d;
+    use hex2d::Coordinate;
+
+    use std::hash;
+    use hashing::InternalHashMap;
+
+    use super::fov::FovMap;
+    use super::los::Light;
+
+    /// When remembered Coordinates are forgotten
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub enum Forget {
+        /// Remember everything ever seen
+        #[default]
+        Never,
+        /// Forget Coordinates not seen for more than this many turns
+        After(u64),
+    }
+
+    /// Memory of Coordinates seen so far, and the turn each was last seen in
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct Memory<I = i32> where
+        I : hex2d::Integer,
+        I : hash::Hash
+    {
+        seen : InternalHashMap<Coordinate<I>, u64>,
+        turn : u64,
+        forget : Forget,
+    }
+
+    impl<I> Memory<I> where
+        I : hex2d::Integer,
+        I : hash::Hash
+    {
+        /// Create an empty memory, starting at turn `0`
+        pub fn new(forget : Forget) -> Memory<I> {
+            Memory {
+                seen: InternalHashMap::default(),
+                turn: 0,
+                forget,
+            }
+        }
+
+        /// Current turn
+        pub fn turn(&self) -> u64 {
+            self.turn
+        }
+
+        /// Advance to the next turn, forgetting what's too old
+        pub fn tick(&mut self) {
+            self.turn += 1;
+            if let Forget::After(turns) = self.forget {
+                let turn = self.turn;
+                self.seen.retain(|_, &mut last| turn - last <= turns);
+            }
+        }
+
+        /// Mark `c` as seen in the current turn
+        pub fn see(&mut self, c : Coordinate<I>) {
+            self.seen.insert(c, self.turn);
+        }
+
+        /// Mark everything visible in `fov` as seen in the current turn
+        pub fn see_fov<L>(&mut self, fov : &FovMap<I, L>) where
+            L : Light
+        {
+            for (c, _) in fov.iter() {
+                self.see(c);
+            }
+        }
+
+        /// Turn in which `c` was last seen, or `None` if it's unknown
+        pub fn last_seen(&self, c : Coordinate<I>) -> Option<u64> {
+            self.seen.get(&c).cloned()
+        }
+
+        /// Turns since `c` was last seen, or `None` if it's unknown
+        ///
+        /// `0` means `c` was seen in the current turn.
+        pub fn age(&self, c : Coordinate<I>) -> Option<u64> {
+            self.last_seen(c).map(|last| self.turn - last)
+        }
+
+        /// Is `c` remembered at all
+        pub fn is_known(&self, c : Coordinate<I>) -> bool {
+            self.seen.contains_key(&c)
+        }
+
+        /// Coordinates seen at most `turns` turns ago
+        pub fn seen_within(&self, turns : u64) -> impl Iterator<Item = Coordinate<I>> + '_ {
+            self.iter().filter(move |&(_, age)| age <= turns).map(|(c, _)| c)
+        }
+
+        /// Iterator over remembered Coordinates and their age
+        pub fn iter(&self) -> impl Iterator<Item = (Coordinate<I>, u64)> + '_ {
+            self.seen.iter().map(move |(&c, &last)| (c, self.turn - last))
+        }
+
+        /// Number of remembered Coordinates
+        pub fn len(&self) -> usize {
+            self.seen.len()
+        }
+
+        /// Is nothing remembered
+        pub fn is_empty(&self) -> bool {
+            self.seen.is_empty()
+        }
+    }
+}
+
+/// Distance fields
+pub mod distance {
+    use hex2d;
+    use hex2d::Coordinate;
+
+    use std::hash;
+    use std::collections::VecDeque;
+
+    use hashing::{InternalHashSet, OutputHashMap};
+
+    /// Walk distance from every open Coordinate to the nearest blocked one
+    ///
+    /// `region` lists all the Coordinates to consider. Coordinates for which `can_pass` returns
+    /// false are blocked, and so is everything outside of `region`, so the edge of the map acts
+    /// like a wall.
+    ///
+    /// Every open Coordinate of `region` is mapped to the number of steps to the closest blocked
+    /// Coordinate: open Coordinates touching a wall get `1`, the ones next to them `2` and so on.
+    pub fn from_obstacles<FCanPass, R, I>(can_pass : FCanPass, region : R) -> OutputHashMap<Coordinate<I>, u32> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        FCanPass : Fn(Coordinate<I>) -> bool,
+        R : IntoIterator<Item = Coordinate<I>>
+    {
+        let open : InternalHashSet<Coordinate<I>> = region.into_iter().filter(|&c| can_pass(c)).collect();
+
+        let mut dist = OutputHashMap::with_capacity_and_hasher(open.len(), Default::default());
+        let mut to_traverse = VecDeque::new();
+
+        for &pos in open.iter() {
+            if pos.neighbors().iter().any(|npos| !open.contains(npos)) {
+                dist.insert(pos, 1);
+                to_traverse.push_back(pos);
+            }
+        }
+
+        while let Some(pos) = to_traverse.pop_front() {
+            let ndist = dist[&pos] + 1;
+
+            for &npos in pos.neighbors().iter() {
+                if open.contains(&npos) && !dist.contains_key(&npos) {
+                    dist.insert(npos, ndist);
+                    to_traverse.push_back(npos);
+                }
+            }
+        }
+
+        dist
+    }
+
+    /// Largest hexagon of open Coordinates in `region`, as its center and radius
+    ///
+    /// Uses `from_obstacles`, so everything outside of `region` is blocked. A radius of `0`
+    /// is a single open Coordinate. Equally large hexagons are resolved in favor of the
+    /// smaller center. Returns `None` if nothing is open.
+    pub fn largest_open_hexagon<FCanPass, R, I>(can_pass : FCanPass, region : R) -> Option<(Coordinate<I>, u32)> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        FCanPass : Fn(Coordinate<I>) -> bool,
+        R : IntoIterator<Item = Coordinate<I>>
+    {
+        from_obstacles(can_pass, region).into_iter()
+            .max_by(|&(a, da), &(b, db)| da.cmp(&db).then(b.cmp(&a)))
+            .map(|(c, d)| (c, d - 1))
+    }
+
+    /// Centers of all hexagons of `radius` that fit in open Coordinates of `region`
+    ///
+    /// Candidate spots for arenas and buildings of a given size. Sorted, so the order doesn't
+    /// depend on hashing.
+    pub fn open_hexagon_centers<FCanPass, R, I>(can_pass : FCanPass, region : R, radius : u32) -> Vec<Coordinate<I>> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        FCanPass : Fn(Coordinate<I>) -> bool,
+        R : IntoIterator<Item = Coordinate<I>>
+    {
+        let mut res : Vec<_> = from_obstacles(can_pass, region).into_iter()
+            .filter(|&(_, d)| d > radius)
+            .map(|(c, _)| c)
+            .collect();
+        res.sort();
+        res
+    }
+}
+
+/// Map metrics
+///
+/// Measures of how open or cramped a map is, eg. to bias spawns or to validate generators.
+pub mod metrics {
+    use hex2d;
+    use hex2d::Coordinate;
+
+    use std::hash;
+
+    use geom::Bounds;
+    use map::HexMap;
+
+    use super::distance;
+
+    /// Global statistics of a map
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct Stats {
+        /// Fraction of passable Coordinates
+        pub open_ratio : f32,
+        /// Average width of open areas, measured across their middle
+        pub average_width : f32,
+    }
+
+    /// Openness of every Coordinate of `bounds`: the number of passable Coordinates within
+    /// `radius`
+    ///
+    /// Only Coordinates of `bounds` count, so tiles near the edge of the map are less open.
+    pub fn openness<FCanPass, I>(can_pass : FCanPass, bounds : Bounds<I>, radius : I) -> HexMap<f32, I> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : ::std::ops::AddAssign,
+        FCanPass : Fn(Coordinate<I>) -> bool
+    {
+        let open = HexMap::from_fn(bounds, can_pass);
+        HexMap::from_fn(bounds, |c| {
+            c.range_iter(radius).filter(|&n| open.get(n).cloned().unwrap_or(false)).count() as f32
+        })
+    }
+
+    /// Global statistics of the passable Coordinates of `bounds`
+    ///
+    /// Width is measured along the middle of open areas (Coordinates at least as far from
+    /// walls as any of their neighbors), as twice the distance to the nearest wall minus one:
+    /// one-tile corridors have width `1`, three-tile ones `3`. Everything outside of `bounds`
+    /// counts as a wall.
+    pub fn stats<FCanPass, I>(can_pass : FCanPass, bounds : Bounds<I>) -> Stats where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        FCanPass : Fn(Coordinate<I>) -> bool
+    {
+        let dist = distance::from_obstacles(can_pass, bounds);
+
+        let mut middle = 0;
+        let mut width = 0.0;
+        for (c, &d) in dist.iter() {
+            if c.neighbors().iter().all(|n| dist.get(n).is_none_or(|&nd| nd <= d)) {
+                middle += 1;
+                width += (2 * d - 1) as f32;
+            }
+        }
+
+        Stats {
+            open_ratio: if bounds.is_empty() { 0.0 } else { dist.len() as f32 / bounds.len() as f32 },
+            average_width: if middle == 0 { 0.0 } else { width / middle as f32 },
+        }
+    }
+}
+
+/// Influence maps
+///
+/// Weighted sources (eg. friendly and enemy units) spread their influence over nearby
+/// Coordinates. Maps can be decayed over time and combined with each other.
+pub mod influence {
+    use hex2d;
+    use hex2d::Coordinate;
+
+    use std::hash;
+    use std::ops;
+    use std::collections::VecDeque;
+    use std::collections::hash_map::Entry::{Occupied,Vacant};
+
+    use hashing::InternalHashMap;
+
+    /// Values smaller than that are dropped from the map
+    const NEGLIGIBLE : f32 = 0.0001;
+
+    /// How influence weakens with every step from its source
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub enum Falloff {
+        /// Lose given fraction of the initial weight with each step
+        Linear(f32),
+        /// Multiply by given factor with each step
+        Exponential(f32),
+    }
+
+    impl Falloff {
+        fn at(&self, weight : f32, dist : u32) -> f32 {
+            match *self {
+                Falloff::Linear(step) => {
+                    let left = 1.0 - step * dist as f32;
+                    if left > 0.0 { weight * left } else { 0.0 }
+                },
+                Falloff::Exponential(factor) => weight * factor.powi(dist as i32),
+            }
+        }
+    }
+
+    /// Influence map
+    #[derive(Clone, Debug, Default)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct InfluenceMap<I = i32> where
+        I : hex2d::Integer,
+        I : hash::Hash
+    {
+        values : InternalHashMap<Coordinate<I>, f32>,
+    }
+
+    impl<I> InfluenceMap<I> where
+        I : hex2d::Integer,
+        I : hash::Hash
+    {
+        /// Create an empty influence map
+        pub fn new() -> InfluenceMap<I> {
+            InfluenceMap {
+                values: InternalHashMap::default(),
+            }
+        }
+
+        /// Influence at `pos`
+        pub fn get(&self, pos : Coordinate<I>) -> f32 {
+            self.values.get(&pos).cloned().unwrap_or(0.0)
+        }
+
+        /// Add influence of `weight` at `pos`, spreading it outward
+        ///
+        /// Influence spreads only through Coordinates for which `can_pass` returns true, up to
+        /// `max_dist` steps away from `pos`, weakening according to `falloff`. Negative
+        /// `weight` can be used for hostile sources.
+        pub fn add_source<FCanPass>(
+            &mut self,
+            pos : Coordinate<I>,
+            weight : f32,
+            falloff : Falloff,
+            max_dist : u32,
+            can_pass : FCanPass,
+        ) where
+            FCanPass : Fn(Coordinate<I>) -> bool
+        {
+            let mut visited = InternalHashMap::default();
+            let mut to_traverse = VecDeque::new();
+
+            visited.insert(pos, 0);
+            to_traverse.push_back(pos);
+
+            while let Some(pos) = to_traverse.pop_front() {
+                let dist = visited[&pos];
+                let value = falloff.at(weight, dist);
+
+                if value.abs() < NEGLIGIBLE {
+                    continue;
+                }
+
+                *self.values.entry(pos).or_insert(0.0) += value;
+
+                if dist >= max_dist {
+                    continue;
+                }
+
+                for &npos in pos.neighbors().iter() {
+                    match visited.entry(npos) {
+                        Occupied(_) => { /* already visited */ }
+                        Vacant(entry) => {
+                            if can_pass(npos) {
+                                entry.insert(dist + 1);
+                                to_traverse.push_back(npos);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        /// Multiply all the influence by `factor`
+        ///
+        /// Meant to be called once per tick, so old influence fades away.
+        pub fn decay(&mut self, factor : f32) {
+            for value in self.values.values_mut() {
+                *value *= factor;
+            }
+            self.values.retain(|_, value| value.abs() >= NEGLIGIBLE);
+        }
+
+        /// Coordinate with the highest influence
+        ///
+        /// Ties are resolved in favor of the smaller Coordinate, so the result doesn't depend
+        /// on internal ordering.
+        pub fn best(&self) -> Option<(Coordinate<I>, f32)> {
+            self.values.iter()
+                .map(|(&c, &v)| (c, v))
+                .fold(None, |best, (c, v)| match best {
+                    Some((bc, bv)) if bv > v || (bv == v && bc < c) => Some((bc, bv)),
+                    _ => Some((c, v)),
+                })
+        }
+
+        /// Iterator over all Coordinates with non-zero influence
+        pub fn iter(&self) -> impl Iterator<Item = (Coordinate<I>, f32)> + '_ {
+            self.values.iter().map(|(&c, &v)| (c, v))
+        }
+
+        /// Wrap a step `cost` (eg. for `dijkstra` or `astar`) to avoid hostile influence
+        ///
+        /// Entering a Coordinate with negative influence costs extra: the threat times
+        /// `1 - bravery`, rounded. With `bravery` of `1` threats are ignored, with `0` they
+        /// are avoided at any detour shorter than the threat. Give hostile sources weights in
+        /// units of step cost.
+        pub fn threat_cost<'a, F>(&'a self, mut cost : F, bravery : f32) -> impl FnMut(Coordinate<I>, Coordinate<I>) -> Option<u32> + 'a where
+            F : FnMut(Coordinate<I>, Coordinate<I>) -> Option<u32> + 'a
+        {
+            let fear = 1.0 - bravery.clamp(0.0, 1.0);
+            move |from, to| {
+                let threat = (-self.get(to)).max(0.0);
+                cost(from, to).map(|step| step.saturating_add((threat * fear).round() as u32))
+            }
+        }
+    }
+
+    impl<'a, I> ops::AddAssign<&'a InfluenceMap<I>> for InfluenceMap<I> where
+        I : hex2d::Integer,
+        I : hash::Hash
+    {
+        fn add_assign(&mut self, other : &'a InfluenceMap<I>) {
+            for (&c, &v) in other.values.iter() {
+                *self.values.entry(c).or_insert(0.0) += v;
+            }
+            self.values.retain(|_, value| value.abs() >= NEGLIGIBLE);
+        }
+    }
+
+    impl<'a, I> ops::SubAssign<&'a InfluenceMap<I>> for InfluenceMap<I> where
+        I : hex2d::Integer,
+        I : hash::Hash
+    {
+        fn sub_assign(&mut self, other : &'a InfluenceMap<I>) {
+            for (&c, &v) in other.values.iter() {
+                *self.values.entry(c).or_insert(0.0) -= v;
+            }
+            self.values.retain(|_, value| value.abs() >= NEGLIGIBLE);
+        }
+    }
+
+    impl<'a, I> ops::Add<&'a InfluenceMap<I>> for InfluenceMap<I> where
+        I : hex2d::Integer,
+        I : hash::Hash
+    {
+        type Output = InfluenceMap<I>;
+
+        fn add(mut self, other : &'a InfluenceMap<I>) -> InfluenceMap<I> {
+            self += other;
+            self
+        }
+    }
+
+    impl<'a, I> ops::Sub<&'a InfluenceMap<I>> for InfluenceMap<I> where
+        I : hex2d::Integer,
+        I : hash::Hash
+    {
+        type Output = InfluenceMap<I>;
+
+        fn sub(mut self, other : &'a InfluenceMap<I>) -> InfluenceMap<I> {
+            self -= other;
+            self
+        }
+    }
+}
+
+/// Noise propagation
+pub mod sound {
+    use hex2d;
+    use hex2d::Coordinate;
+
+    use std::hash;
+    use std::collections::BinaryHeap;
+    use num::Zero;
+
+    use hashing::InternalHashMap;
+
+    /// Starting from `pos`, call `hear` for each Coordinate the noise reaches
+    ///
+    /// Noise starts with `volume` and loses `step` with every step it takes. Entering a
+    /// Coordinate additionally costs whatever `attenuation` returns for it: typically `0` for
+    /// open space, something small for doors and something big for thick walls.
+    ///
+    /// Every Coordinate is reported once, with the loudest volume that reaches it, and only if
+    /// that volume is positive. Coordinates are reported from the loudest to the quietest.
+    pub fn propagate<FAttenuation, FHear, I>(
+        attenuation : &FAttenuation,
+        hear : &mut FHear,
+        volume : I,
+        step : I,
+        pos : Coordinate<I>,
+    ) where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        FAttenuation : Fn(Coordinate<I>) -> I,
+        FHear : FnMut(Coordinate<I>, I)
+    {
+        let mut heard = InternalHashMap::default();
+        let mut to_traverse = BinaryHeap::new();
+
+        to_traverse.push((volume, pos));
+
+        while let Some((volume, pos)) = to_traverse.pop() {
+            if heard.contains_key(&pos) {
+                continue;
+            }
+            heard.insert(pos, volume);
+            hear(pos, volume);
+
+            for &npos in pos.neighbors().iter() {
+                if heard.contains_key(&npos) {
+                    continue;
+                }
+
+                let nvolume = volume - step - attenuation(npos);
+                if nvolume > Zero::zero() {
+                    to_traverse.push((nvolume, npos));
+                }
+            }
+        }
+    }
+}
+
+/// Scent (stigmergy) maps
+///
+/// Agents deposit scent, which then spreads and fades with every tick. Trackers can follow
+/// the gradient to the source without pathfinding every turn.
+pub mod scent {
+    use hex2d;
+    use hex2d::Coordinate;
+    use hex2d::Direction;
+
+    use std::hash;
+    use hashing::InternalHashMap;
+
+    /// Values smaller than that are dropped from the map
+    const NEGLIGIBLE : f32 = 0.0001;
+
+    /// Scent map
+    #[derive(Clone, Debug, Default)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct ScentMap<I = i32> where
+        I : hex2d::Integer,
+        I : hash::Hash
+    {
+        values : InternalHashMap<Coordinate<I>, f32>,
+    }
+
+    impl<I> ScentMap<I> where
+        I : hex2d::Integer,
+        I : hash::Hash
+    {
+        /// Create an empty scent map
+        pub fn new() -> ScentMap<I> {
+            ScentMap {
+                values: InternalHashMap::default(),
+            }
+        }
+
+        /// Scent at `pos`
+        pub fn get(&self, pos : Coordinate<I>) -> f32 {
+            self.values.get(&pos).cloned().unwrap_or(0.0)
+        }
+
+        /// Leave `amount` of scent at `pos`
+        pub fn deposit(&mut self, pos : Coordinate<I>, amount : f32) {
+            *self.values.entry(pos).or_insert(0.0) += amount;
+        }
+
+        /// Advance the simulation by one tick
+        ///
+        /// Every Coordinate passes `diffusion` fraction of its scent, split evenly, to the
+        /// neighbors for which `can_pass` returns true; the share of blocked neighbors stays
+        /// in place. Then `decay` fraction of all the scent evaporates.
+        pub fn tick<FCanPass>(&mut self, diffusion : f32, decay : f32, can_pass : FCanPass) where
+            FCanPass : Fn(Coordinate<I>) -> bool
+        {
+            let mut next = InternalHashMap::with_capacity_and_hasher(self.values.len() * 2, Default::default());
+            let share = diffusion / 6.0;
+
+            for (&pos, &value) in self.values.iter() {
+                let mut kept = value * (1.0 - diffusion);
+
+                for &npos in pos.neighbors().iter() {
+                    if can_pass(npos) {
+                        *next.entry(npos).or_insert(0.0) += value * share;
+                    } else {
+                        kept += value * share;
+                    }
+                }
+
+                *next.entry(pos).or_insert(0.0) += kept;
+            }
+
+            for value in next.values_mut() {
+                *value *= 1.0 - decay;
+            }
+            next.retain(|_, value| *value >= NEGLIGIBLE);
+
+            self.values = next;
+        }
+
+        /// Direction in which the scent gets stronger
+        ///
+        /// Returns direction to the neighbor of `pos` with the strongest scent, or `None` if
+        /// no neighbor smells stronger than `pos` itself.
+        pub fn follow(&self, pos : Coordinate<I>) -> Option<Direction> {
+            let mut best = None;
+            let mut best_value = self.get(pos);
+
+            for &dir in Direction::all().iter() {
+                let value = self.get(pos + dir);
+                if value > best_value {
+                    best = Some(dir);
+                    best_value = value;
+                }
+            }
+
+            best
+        }
+
+        /// Iterator over all Coordinates with any scent
+        pub fn iter(&self) -> impl Iterator<Item = (Coordinate<I>, f32)> + '_ {
+            self.values.iter().map(|(&c, &v)| (c, v))
+        }
+    }
+}
+
+/// Ambush analysis
+pub mod ambush {
+    use hex2d;
+    use hex2d::Coordinate;
+    use hex2d::Direction;
+
+    use std::hash;
+    use hashing::InternalHashSet;
+
+    use super::fov;
+    use super::los::Light;
+
+    /// Good ambush spots along a predicted enemy `path`
+    ///
+    /// Returns Coordinates within `range` steps of the path (`1` for adjacent only) where
+    /// `can_stand` returns true, and which are not visible from any Coordinate of the path,
+    /// using `fov` with `opaqueness` and `light`. Spots are ordered by the first Coordinate of
+    /// the path they can strike.
+    pub fn spots<FOpaqueness, FCanStand, I, L>(
+        path : &[Coordinate<I>],
+        mut opaqueness : FOpaqueness,
+        mut can_stand : FCanStand,
+        light : L,
+        range : I,
+        ) -> Vec<Coordinate<I>> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : ::std::ops::AddAssign,
+        L : Light,
+        FOpaqueness : FnMut(Coordinate<I>) -> L,
+        FCanStand : FnMut(Coordinate<I>) -> bool
+    {
+        let mut seen = InternalHashSet::default();
+        seen.extend(path.iter().cloned());
+        for &pos in path {
+            let map = fov::fov(&mut opaqueness, light, pos, Direction::all());
+            seen.extend(map.iter().map(|(c, _)| c));
+        }
+
+        let mut res = vec!();
+        for &pos in path {
+            for c in pos.range_iter(range) {
+                if seen.insert(c) && can_stand(c) {
+                    res.push(c);
+                }
+            }
+        }
+        res
+    }
+}
+
+/// Cover and flanking, for tactics games
+pub mod cover {
+    use hex2d;
+    use hex2d::Angle::{Left, Right};
+    use hex2d::Coordinate;
+    use hex2d::Direction;
+
+    /// Protection of a target against an attack
+    #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub enum Cover {
+        /// Nothing in the way
+        None,
+        /// The attack grazes an obstacle
+        Partial,
+        /// An obstacle is right in the way
+        Full,
+    }
+
+    /// Cover of `target` against an attack coming from `dir`
+    ///
+    /// Full if the neighbor of `target` in `dir` `blocks`, partial if one of the two
+    /// neighbors next to it does.
+    pub fn from_direction<FBlocks, I>(target : Coordinate<I>, dir : Direction, mut blocks : FBlocks) -> Cover where
+        I : hex2d::Integer,
+        I : ::std::ops::AddAssign,
+        FBlocks : FnMut(Coordinate<I>) -> bool
+    {
+        if blocks(target + dir) {
+            Cover::Full
+        } else if blocks(target + (dir + Left)) || blocks(target + (dir + Right)) {
+            Cover::Partial
+        } else {
+            Cover::None
+        }
+    }
+
+    /// Cover of `target` against an attack from `attacker`
+    ///
+    /// Looks at the neighbors of `target` that the straight line from `attacker` passes
+    /// through. Full if the line crosses an obstacle, partial if it runs along the edge
+    /// of one (grazing it). Adjacent attackers always get `Cover::None`.
+    pub fn against<FBlocks, I>(target : Coordinate<I>, attacker : Coordinate<I>, mut blocks : FBlocks) -> Cover where
+        I : hex2d::Integer,
+        I : ::std::ops::AddAssign,
+        FBlocks : FnMut(Coordinate<I>) -> bool
+    {
+        if attacker.distance(target) <= I::one() {
+            return Cover::None;
+        }
+
+        let (c1, c2) = attacker.line_to_with_edge_detection_iter(target)
+            .take_while(|&(c1, _)| c1 != target)
+            .last()
+            .unwrap_or((attacker, attacker));
+
+        let (b1, b2) = if c1 == c2 {
+            let b = blocks(c1);
+            (b, b)
+        } else {
+            (blocks(c1), blocks(c2))
+        };
+
+        match (b1, b2) {
+            (true, true) => Cover::Full,
+            (false, false) => Cover::None,
+            _ => Cover::Partial,
+        }
+    }
+
+    /// Is `target` flanked by attackers at `a` and `b`
+    ///
+    /// Attackers flank when they are at least 120 degrees apart, as seen from `target`.
+    pub fn is_flanked<I>(target : Coordinate<I>, a : Coordinate<I>, b : Coordinate<I>) -> bool where
+        I : hex2d::Integer,
+        I : ::std::ops::AddAssign
+    {
+        let cube = |c : Coordinate<I>| {
+            let d = c - target;
+            [d.x.to_i64().unwrap(), d.y.to_i64().unwrap(), d.z().to_i64().unwrap()]
+        };
+        let dot = |u : [i64; 3], v : [i64; 3]| u[0] * v[0] + u[1] * v[1] + u[2] * v[2];
+        let (u, v) = (cube(a), cube(b));
+        let uv = dot(u, v);
+
+        // cos(angle) <= -1/2
+        uv < 0 && 4 * uv * uv >= dot(u, u) * dot(v, v)
+    }
+}
+
+/// Stealth and detection
+pub mod stealth {
+    use hex2d;
+    use hex2d::Coordinate;
+
+    use std::hash;
+
+    use hashing::OutputHashMap;
+
+    use super::cover::Cover;
+    use super::fov::FovMap;
+    use super::lighting::LightMap;
+
+    /// Probability of being spotted by an observer on every Coordinate it can see
+    ///
+    /// `observer` is the observer's field of view with `f32` light, eg. from `fov` or
+    /// `fov_facing`. The light reaching a Coordinate, relative to the light on the observer's own
+    /// Coordinate, accounts for distance and facing. It's multiplied by the brightness of the
+    /// Coordinate in `light_map` (average of the channels, capped at `1.0`) and by the exposure
+    /// left by `cover` against the observer: `1.0` for none, `0.5` for partial and `0.0` for full.
+    ///
+    /// Coordinates with zero probability are left out.
+    pub fn detection_map<FCover, I, const N : usize>(
+        observer : &FovMap<I, f32>,
+        light_map : &LightMap<I, N>,
+        mut cover : FCover,
+        ) -> OutputHashMap<Coordinate<I>, f32> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : ::std::ops::AddAssign,
+        FCover : FnMut(Coordinate<I>) -> Cover
+    {
+        let sight = match observer.light(observer.origin()) {
+            Some(sight) if sight > 0.0 => sight,
+            _ => return OutputHashMap::default(),
+        };
+
+        let mut res = OutputHashMap::default();
+        for (c, l) in observer.iter() {
+            let color = light_map.get(c);
+            let brightness = (color.iter().sum::<f32>() / N as f32).min(1.0);
+            let exposure = match cover(c) {
+                Cover::None => 1.0,
+                Cover::Partial => 0.5,
+                Cover::Full => 0.0,
+            };
+            let p = (l / sight).min(1.0) * brightness * exposure;
+            if p > 0.0 {
+                res.insert(c, p);
+            }
+        }
+        res
+    }
+}
+
+/// Explosions with destructible blockers
+pub mod blast {
+    use hex2d;
+    use hex2d::Coordinate;
+
+    use std::cmp::Ordering;
+    use std::hash;
+    use std::collections::BinaryHeap;
+
+    use hashing::OutputHashMap;
+    use map::HexMap;
+
+    use super::los::Light;
+
+    /// Outcome of an `explode`
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct Blast<I = i32, P = I> where
+        I : hex2d::Integer,
+        I : hash::Hash
+    {
+        /// Power that reached every Coordinate hit by the explosion
+        pub damage : OutputHashMap<Coordinate<I>, P>,
+        /// Coordinates whose blockers were destroyed, from the first to the last
+        pub destroyed : Vec<Coordinate<I>>,
+    }
+
+    /// Power reaching a Coordinate, ordered by power (then Coordinate) for the queue
+    struct Front<I, P>(P, Coordinate<I>) where
+        I : hex2d::Integer;
+
+    impl<I, P> PartialEq for Front<I, P> where
+        I : hex2d::Integer,
+        P : Light
+    {
+        fn eq(&self, other : &Self) -> bool {
+            self.cmp(other) == Ordering::Equal
+        }
+    }
+
+    impl<I, P> Eq for Front<I, P> where
+        I : hex2d::Integer,
+        P : Light
+    {}
+
+    impl<I, P> PartialOrd for Front<I, P> where
+        I : hex2d::Integer,
+        P : Light
+    {
+        fn partial_cmp(&self, other : &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl<I, P> Ord for Front<I, P> where
+        I : hex2d::Integer,
+        P : Light
+    {
+        fn cmp(&self, other : &Self) -> Ordering {
+            self.0.partial_cmp(&other.0).unwrap_or(Ordering::Equal).then(self.1.cmp(&other.1))
+        }
+    }
+
+    /// Explode with `power` at `pos`, destroying blockers of `resistance` that can't hold it
+    ///
+    /// Power drops by `falloff` with every step. A Coordinate reached with power `p` takes
+    /// `p` damage. If `p` is bigger than its resistance, its blocker (if any) is destroyed:
+    /// resistance is set to zero in `resistance`, and the rest of the power spreads further.
+    /// Otherwise the blocker absorbs all of it. Nothing spreads outside of `resistance`.
+    ///
+    /// Power can be of any `Light` type, independent of the Coordinate integer type.
+    pub fn explode<I, P>(
+        resistance : &mut HexMap<P, I>,
+        power : P,
+        falloff : P,
+        pos : Coordinate<I>,
+        ) -> Blast<I, P> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        P : Light
+    {
+        let mut blast = Blast { damage: OutputHashMap::default(), destroyed: vec!() };
+        let mut to_traverse = BinaryHeap::new();
+
+        if resistance.contains(pos) && power > P::zero() {
+            to_traverse.push(Front(power, pos));
+        }
+
+        while let Some(Front(power, pos)) = to_traverse.pop() {
+            if blast.damage.contains_key(&pos) {
+                continue;
+            }
+            blast.damage.insert(pos, power);
+
+            let r = resistance[pos];
+            if power <= r {
+                continue;
+            }
+            if r > P::zero() {
+                resistance[pos] = P::zero();
+                blast.destroyed.push(pos);
+            }
+
+            let npower = power.dim(r).dim(falloff);
+            if npower <= P::zero() {
+                continue;
+            }
+            for &npos in pos.neighbors().iter() {
+                if resistance.contains(npos) && !blast.damage.contains_key(&npos) {
+                    to_traverse.push(Front(npower, npos));
+                }
+            }
+        }
+
+        blast
+    }
+}
+
+/// Traffic through the map
+pub mod traffic {
+    use hex2d;
+    use hex2d::Coordinate;
+
+    use geom::Bounds;
+    use map::HexMap;
+
+    use std::hash;
+
+    use super::dijkstra;
+    use super::path::Path;
+
+    /// How often Coordinates were walked through
+    ///
+    /// Accumulate paths of agents to find the busiest corridors, and feed them back to
+    /// weighted searches as congestion cost (see `penalty`).
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct Traffic<I = i32> where
+        I : hex2d::Integer
+    {
+        counts : HexMap<u32, I>,
+    }
+
+    impl<I> Traffic<I> where
+        I : hex2d::Integer
+    {
+        /// Create Traffic of `bounds` with no paths yet
+        pub fn new(bounds : Bounds<I>) -> Traffic<I> {
+            Traffic {
+                counts: HexMap::new(bounds, 0),
+            }
+        }
+
+        /// Count of `c`; zero outside of the bounds
+        pub fn get(&self, c : Coordinate<I>) -> u32 {
+            self.counts.get(c).cloned().unwrap_or(0)
+        }
+
+        /// Counts of all Coordinates
+        pub fn counts(&self) -> &HexMap<u32, I> {
+            &self.counts
+        }
+
+        /// Count every Coordinate of `path`, except the starting one
+        ///
+        /// Coordinates outside of the bounds are skipped.
+        pub fn add_path(&mut self, path : &Path<I>) {
+            for &c in path.coordinates()[1..].iter() {
+                if let Some(count) = self.counts.get_mut(c) {
+                    *count = count.saturating_add(1);
+                }
+            }
+        }
+
+        /// Multiply all counts by `factor`, rounding down
+        ///
+        /// Use regularly with `factor` below `1.0` so old traffic fades away.
+        pub fn decay(&mut self, factor : f32) {
+            for (_, count) in self.counts.iter_mut() {
+                *count = (*count as f32 * factor) as u32;
+            }
+        }
+
+        /// Busiest Coordinate and its count, if anything was counted
+        pub fn busiest(&self) -> Option<(Coordinate<I>, u32)> {
+            self.counts.iter()
+                .map(|(c, &count)| (c, count))
+                .filter(|&(_, count)| count > 0)
+                .fold(None, |best : Option<(Coordinate<I>, u32)>, (c, count)| {
+                    match best {
+                        Some((_, best_count)) if best_count >= count => best,
+                        _ => Some((c, count)),
+                    }
+                })
+        }
+
+        /// Extra cost of entering `c`: its count times `weight`, rounded down
+        ///
+        /// Add to the step cost of weighted searches to steer agents away from busy
+        /// corridors.
+        pub fn penalty(&self, c : Coordinate<I>, weight : f32) -> u32 {
+            (self.get(c) as f32 * weight) as u32
+        }
+    }
+
+    /// Plan paths of a crowd of agents, one after another
+    ///
+    /// Every agent walks from the first to the second Coordinate of its pair, along the
+    /// cheapest path by `cost` (see `dijkstra::Traverser`), plus `penalty` for every earlier
+    /// agent planned to pass each entered Coordinate of `bounds`. With a big enough
+    /// `penalty`, crowds spread over parallel corridors instead of single-filing through
+    /// one door.
+    ///
+    /// Returns paths in the order of `agents`, `None` for agents that can't reach their
+    /// destination. Bound the map with `cost`, or the search never ends in that case.
+    pub fn plan_crowd<FCost, I>(
+        bounds : Bounds<I>,
+        agents : &[(Coordinate<I>, Coordinate<I>)],
+        mut cost : FCost,
+        penalty : u32,
+        ) -> Vec<Option<Path<I>>> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : ::std::ops::AddAssign,
+        FCost : FnMut(Coordinate<I>, Coordinate<I>) -> Option<u32>
+    {
+        let mut traffic = Traffic::new(bounds);
+
+        agents.iter().map(|&(start, dest)| {
+            let path = {
+                let traffic = &traffic;
+                let cost = |from, to| cost(from, to).map(|c| c.saturating_add(traffic.get(to).saturating_mul(penalty)));
+                let mut search = dijkstra::Traverser::new(cost, |c| c == dest, start);
+                search.find().map(|dest| Path::new(search.path(dest).unwrap()))
+            };
+            if let Some(ref path) = path {
+                traffic.add_path(path);
+            }
+            path
+        }).collect()
+    }
+}
+
+/// Cheap local movement, without pathfinding
+pub mod steering {
+    use hex2d;
+    use hex2d::Coordinate;
+
+    /// Squared straight-line distance between `a` and `b`, in cube units
+    fn straight_sq<I>(a : Coordinate<I>, b : Coordinate<I>) -> i64 where
+        I : hex2d::Integer
+    {
+        let (dx, dy) = ((a.x - b.x).to_i64().unwrap(), (a.y - b.y).to_i64().unwrap());
+        dx * dx + dy * dy + (dx + dy) * (dx + dy)
+    }
+
+    /// Best single step from `from` toward `toward`
+    ///
+    /// Only neighbors for which `can_pass` returns true and `avoid` returns false (eg.
+    /// occupied by other units) are considered. Takes the one closest to `toward`, preferring
+    /// the one closest to the straight line on ties. If no step gets closer, side-steps to a
+    /// neighbor just as far, to get around the obstacle; if there is none, waits by returning
+    /// `from`.
+    ///
+    /// Looks only one step ahead, so it can get stuck in dead ends, or side-step back and
+    /// forth in front of walls. Use a search when that matters.
+    pub fn next_step<FCanPass, FAvoid, I>(
+        from : Coordinate<I>,
+        toward : Coordinate<I>,
+        mut can_pass : FCanPass,
+        mut avoid : FAvoid,
+        ) -> Coordinate<I> where
+        I : hex2d::Integer,
+        FCanPass : FnMut(Coordinate<I>) -> bool,
+        FAvoid : FnMut(Coordinate<I>) -> bool
+    {
+        if from == toward {
+            return from;
+        }
+
+        let best = from.neighbors().iter()
+            .cloned()
+            .filter(|&c| can_pass(c) && !avoid(c))
+            .min_by_key(|&c| (c.distance(toward), straight_sq(c, toward)));
+
+        match best {
+            Some(c) if c.distance(toward) <= from.distance(toward) => c,
+            _ => from,
+        }
+    }
+}
+
+/// Groups moving in formation
+pub mod formation {
+    use hex2d;
+    use hex2d::Coordinate;
+    use hex2d::Direction;
+
+    use std::hash;
+    use std::collections::HashSet;
+
+    use super::bfs;
+    use super::path::Path;
+
+    /// Plan moves of units following a `leader` walking along `path`
+    ///
+    /// Every unit is given as its starting Coordinate and its offset from the leader, for a
+    /// leader facing `Direction::YZ`. Offsets turn with the leader, which faces the direction
+    /// of its last step. Units head for their slot in the formation when it can be passed,
+    /// and otherwise fall in line behind the leader (the `n`th unit `n` steps back along the
+    /// path), so the group squeezes through choke points and spreads out again after.
+    ///
+    /// Units move at most one step per tick and never share a Coordinate or swap places
+    /// with each other or the leader; blocked units wait. Planning goes on after the leader
+    /// stops, until the units stop moving.
+    ///
+    /// Returns positions of every unit at every tick, starting with its starting
+    /// Coordinate. All have the same length, at least that of `path`; the leader stays at
+    /// the end of `path` afterwards.
+    pub fn plan<FCanPass, I>(
+        path : &Path<I>,
+        units : &[(Coordinate<I>, Coordinate<I>)],
+        mut can_pass : FCanPass,
+        ) -> Vec<Vec<Coordinate<I>>> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : ::std::ops::AddAssign,
+        FCanPass : FnMut(Coordinate<I>) -> bool
+    {
+        let trail = path.coordinates();
+        let leader_at = |t : usize| trail[t.min(trail.len() - 1)];
+        let spread = units.iter()
+            .map(|&(_, offset)| offset.distance(Coordinate::new(I::zero(), I::zero())))
+            .max()
+            .map_or(0, |d| d.to_u32().unwrap());
+
+        let mut facing = trail.windows(2)
+            .filter_map(|w| w[0].direction_to_cw(w[1]))
+            .next()
+            .unwrap_or(Direction::YZ);
+        let mut plans : Vec<Vec<Coordinate<I>>> = units.iter().map(|&(start, _)| vec!(start)).collect();
+
+        for t in 1.. {
+            let leader = leader_at(t);
+            if let Some(dir) = leader_at(t - 1).direction_to_cw(leader) {
+                facing = dir;
+            }
+
+            let mut taken : HashSet<Coordinate<I>> = HashSet::new();
+            taken.insert(leader);
+            // Units not moved yet this tick still stand where they were
+            taken.extend(plans.iter().map(|plan| plan[t - 1]));
+            let mut moved = false;
+
+            for (i, &(_, offset)) in units.iter().enumerate() {
+                let pos = plans[i][t - 1];
+                taken.remove(&pos);
+
+                let slot = leader + offset.rotate_around_zero(facing - Direction::YZ);
+                let behind = trail[t.min(trail.len() - 1).saturating_sub(i + 1)];
+                let mut step = pos;
+                for &goal in [slot, behind].iter() {
+                    if goal != pos && !can_pass(goal) {
+                        continue;
+                    }
+                    let mut search = bfs::Traverser::builder(pos)
+                        .can_pass(&mut can_pass)
+                        .is_dest(|c| c == goal)
+                        .max_distance(pos.distance(goal).to_u32().unwrap() + 2 * spread + 2)
+                        .build();
+                    if let Some(first) = search.find().and_then(|goal| search.backtrace_last(goal)) {
+                        step = first;
+                        break;
+                    }
+                }
+
+                let swaps = |from : Coordinate<I>, to : Coordinate<I>| from == step && to == pos;
+                let swapping = swaps(leader_at(t - 1), leader) ||
+                    plans.iter().any(|other| other.len() > t && swaps(other[t - 1], other[t]));
+                let next = if step != pos && !taken.contains(&step) && !swapping {
+                    step
+                } else {
+                    pos
+                };
+
+                moved |= next != pos;
+                taken.insert(next);
+                plans[i].push(next);
+            }
+
+            if t + 1 >= trail.len() && !moved {
+                break;
+            }
+        }
+
+        plans
+    }
+}
+
+/// Graphs over Coordinate sets
+///
+/// `metric` gives the distance between two points, eg. hex distance, or walk distance from a
+/// search. `None` means the points can't be connected.
+pub mod graph {
+    use hex2d;
+    use hex2d::Coordinate;
+
+    use std::hash;
+
+    use super::dijkstra;
+
+    /// Walk distance metric, using `dijkstra` with step `cost`
+    ///
+    /// Runs a search for every query, so it's best for small point sets. Bound the map with
+    /// `cost`, or searches between disconnected points never end.
+    pub fn walk_metric<FCost, I>(mut cost : FCost) -> impl FnMut(Coordinate<I>, Coordinate<I>) -> Option<u32> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : ::std::ops::AddAssign,
+        FCost : FnMut(Coordinate<I>, Coordinate<I>) -> Option<u32>
+    {
+        move |a, b| {
+            let mut search = dijkstra::Traverser::new(&mut cost, |c| c == b, a);
+            search.find().and_then(|c| search.distance(c))
+        }
+    }
+
+    /// Edges of a minimum spanning tree of `points`
+    ///
+    /// The standard tool for connecting rooms with as few corridors as possible. If some
+    /// points can't be connected, the result is a spanning forest. Edges are listed in the
+    /// order they were added, starting from `points[0]`.
+    pub fn mst<FMetric, I>(points : &[Coordinate<I>], mut metric : FMetric) -> Vec<(Coordinate<I>, Coordinate<I>)> where
+        I : hex2d::Integer,
+        FMetric : FnMut(Coordinate<I>, Coordinate<I>) -> Option<u32>
+    {
+        let n = points.len();
+        let mut in_tree = vec!(false; n);
+        let mut best : Vec<Option<(u32, usize)>> = vec!(None; n);
+        let mut edges = vec!();
+
+        // Prim's algorithm, restarted for every disconnected component
+        while let Some(root) = in_tree.iter().position(|&t| !t) {
+            let mut next = Some(root);
+            while let Some(i) = next {
+                in_tree[i] = true;
+                if let Some((_, from)) = best[i] {
+                    edges.push((points[from], points[i]));
+                }
+
+                next = None;
+                for j in 0..n {
+                    if in_tree[j] {
+                        continue;
+                    }
+                    if let Some(d) = metric(points[i], points[j]) {
+                        if best[j].is_none_or(|(bd, _)| d < bd) {
+                            best[j] = Some((d, i));
+                        }
+                    }
+                    if let Some((d, _)) = best[j] {
+                        if next.is_none_or(|k : usize| d < best[k].unwrap().0) {
+                            next = Some(j);
+                        }
+                    }
+                }
+            }
+        }
+        edges
+    }
+
+    /// Limit of 2-opt passes over the whole route in `patrol_route`
+    pub const PATROL_PASSES : u32 = 64;
+
+    /// Order `waypoints` into a short patrol loop
+    ///
+    /// Builds a route with the nearest-neighbor heuristic starting from `waypoints[0]`, then
+    /// improves it with 2-opt until no reversal of a section makes the loop shorter, or after
+    /// `PATROL_PASSES` passes. The loop returns from the last waypoint to the first. Pairs
+    /// that `metric` can't connect count as very long, so they are avoided where possible.
+    ///
+    /// `metric` doesn't have to be symmetric: reversals are judged by the length of the whole
+    /// loop, walked in its direction.
+    pub fn patrol_route<FMetric, I>(waypoints : &[Coordinate<I>], mut metric : FMetric) -> Vec<Coordinate<I>> where
+        I : hex2d::Integer,
+        FMetric : FnMut(Coordinate<I>, Coordinate<I>) -> Option<u32>
+    {
+        let n = waypoints.len();
+        if n == 0 {
+            return vec!();
+        }
+        let dist : Vec<Vec<u64>> = waypoints.iter().map(|&a| {
+            waypoints.iter().map(|&b| {
+                if a == b { 0 } else { metric(a, b).map_or(u32::MAX as u64, |d| d as u64) }
+            }).collect()
+        }).collect();
+        let length = |route : &[usize]| -> u64 {
+            (0..n).map(|i| dist[route[i]][route[(i + 1) % n]]).sum()
+        };
+
+        let mut route = vec!(0);
+        let mut left : Vec<usize> = (1..n).collect();
+        while !left.is_empty() {
+            let last = *route.last().unwrap();
+            let (i, _) = left.iter().enumerate().min_by_key(|&(_, &j)| dist[last][j]).unwrap();
+            route.push(left.swap_remove(i));
+        }
+
+        // Every accepted reversal makes the loop strictly shorter, so this ends on its own;
+        // the cap only bounds the time spent on big inputs
+        let mut current = length(&route);
+        let mut improved = n > 3;
+        let mut passes = 0;
+        while improved && passes < PATROL_PASSES {
+            improved = false;
+            passes += 1;
+            for i in 0..n - 1 {
+                for j in i + 2..n {
+                    route[i + 1..j + 1].reverse();
+                    let reversed = length(&route);
+                    if reversed < current {
+                        current = reversed;
+                        improved = true;
+                    } else {
+                        route[i + 1..j + 1].reverse();
+                    }
+                }
+            }
+        }
+
+        route.into_iter().map(|i| waypoints[i]).collect()
+    }
 }
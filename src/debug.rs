@@ -0,0 +1,257 @@
+// Copyright 2014 Dawid Ciężarkiewicz
+// See LICENSE file for more information
+
+/// ASCII diagrams of map regions
+///
+/// Rows of the diagram are rows of `Bounds` (Coordinates with the same `z`), shifted by half
+/// a tile each, so the diagram keeps the shape of a pointy-top hex grid:
+///
+/// ```text
+///  # . . #
+/// # . * . #
+///  # S * D
+/// ```
+pub mod render {
+    use hex2d;
+    use hex2d::Coordinate;
+
+    use std::hash;
+    use std::collections::HashSet;
+
+    use geom::Bounds;
+
+    /// Render every Coordinate of `bounds` as the character returned by `tile`
+    pub fn render<F, I>(bounds : Bounds<I>, tile : F) -> String where
+        I : hex2d::Integer,
+        F : Fn(Coordinate<I>) -> char
+    {
+        let cells : Vec<(i64, i64, char)> = bounds.iter().map(|c| {
+            let x = c.x.to_i64().unwrap();
+            let z = c.z().to_i64().unwrap();
+            (z, 2 * x + z, tile(c))
+        }).collect();
+
+        let min_col = match cells.iter().map(|&(_, col, _)| col).min() {
+            Some(min_col) => min_col,
+            None => return String::new(),
+        };
+
+        let mut res = String::new();
+        let mut row = cells[0].0;
+        let mut col = min_col;
+        for (z, c_col, ch) in cells {
+            if z != row {
+                res.push('\n');
+                row = z;
+                col = min_col;
+            }
+            while col < c_col {
+                res.push(' ');
+                col += 1;
+            }
+            res.push(ch);
+            col += 1;
+        }
+        res.push('\n');
+        res
+    }
+
+    /// Things to draw on top of a map region
+    ///
+    /// Characters, from the most important: `S` start, `D` destination, `*` path, `#` blocked,
+    /// `.` visible, `-` anything else.
+    #[derive(Clone, Debug, Default)]
+    pub struct Overlay<I = i32> where
+        I : hex2d::Integer,
+        I : hash::Hash
+    {
+        /// Blocked Coordinates
+        pub blocked : HashSet<Coordinate<I>>,
+        /// Visible Coordinates
+        pub visible : HashSet<Coordinate<I>>,
+        /// Path
+        pub path : Vec<Coordinate<I>>,
+        /// Start marker
+        pub start : Option<Coordinate<I>>,
+        /// Destination marker
+        pub dest : Option<Coordinate<I>>,
+    }
+
+    impl<I> Overlay<I> where
+        I : hex2d::Integer,
+        I : hash::Hash
+    {
+        /// Create an empty overlay
+        pub fn new() -> Overlay<I> {
+            Overlay {
+                blocked: HashSet::new(),
+                visible: HashSet::new(),
+                path: vec!(),
+                start: None,
+                dest: None,
+            }
+        }
+
+        /// Character of `c`
+        pub fn tile(&self, c : Coordinate<I>) -> char {
+            if self.start == Some(c) {
+                'S'
+            } else if self.dest == Some(c) {
+                'D'
+            } else if self.path.contains(&c) {
+                '*'
+            } else if self.blocked.contains(&c) {
+                '#'
+            } else if self.visible.contains(&c) {
+                '.'
+            } else {
+                '-'
+            }
+        }
+
+        /// Render the overlay over `bounds`
+        pub fn render(&self, bounds : Bounds<I>) -> String {
+            render(bounds, |c| self.tile(c))
+        }
+    }
+}
+
+/// PNG heatmaps of values over map regions
+///
+/// Draws every Coordinate of a region as a hexagon colored from blue (smallest value) to red
+/// (biggest value). The PNG is written without compression, which keeps the encoder tiny;
+/// debug images don't need to be small.
+#[cfg(feature = "image")]
+pub mod image {
+    use hex2d;
+    use hex2d::Coordinate;
+    use hex2d::Spacing;
+
+    use geom::Bounds;
+    use map::HexMap;
+
+    /// Color of Coordinates without a value, and of the background
+    const BACKGROUND : [u8; 3] = [32, 32, 32];
+
+    fn color(t : f32) -> [u8; 3] {
+        let t = t.clamp(0.0, 1.0);
+        [
+            (255.0 * t) as u8,
+            (255.0 * (1.0 - (2.0 * t - 1.0).abs())) as u8,
+            (255.0 * (1.0 - t)) as u8,
+        ]
+    }
+
+    /// Render `value` of every Coordinate of `bounds` into a PNG image
+    ///
+    /// `spacing` selects flat-top or pointy-top hexagons and their size in pixels. Coordinates
+    /// for which `value` returns `None` (eg. not visible, or unreachable) are left blank.
+    pub fn heatmap<F, I>(bounds : Bounds<I>, value : F, spacing : Spacing<f32>) -> Vec<u8> where
+        I : hex2d::Integer,
+        F : Fn(Coordinate<I>) -> Option<f32>
+    {
+        let size = match spacing {
+            Spacing::FlatTop(size) | Spacing::PointyTop(size) => size,
+        };
+
+        let values : Vec<Option<f32>> = bounds.iter().map(&value).collect();
+        let (min, max) = values.iter().filter_map(|&v| v).fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), v| {
+            (min.min(v), max.max(v))
+        });
+        let range = if max > min { max - min } else { 1.0 };
+
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (0f32, 0f32, 0f32, 0f32);
+        for (i, c) in bounds.iter().enumerate() {
+            let (x, y) = c.to_pixel(spacing);
+            if i == 0 {
+                min_x = x; max_x = x; min_y = y; max_y = y;
+            }
+            min_x = min_x.min(x); max_x = max_x.max(x);
+            min_y = min_y.min(y); max_y = max_y.max(y);
+        }
+        let width = (max_x - min_x + 2.0 * size).ceil().max(1.0) as u32;
+        let height = (max_y - min_y + 2.0 * size).ceil().max(1.0) as u32;
+
+        let mut pixels = Vec::with_capacity((width * height * 3) as usize);
+        for py in 0..height {
+            for px in 0..width {
+                let x = min_x - size + px as f32 + 0.5;
+                let y = min_y - size + py as f32 + 0.5;
+                let c = Coordinate::from_pixel(x, y, spacing);
+                let rgb = match bounds.index(c).and_then(|i| values[i]) {
+                    Some(v) => color((v - min) / range),
+                    None => BACKGROUND,
+                };
+                pixels.extend_from_slice(&rgb);
+            }
+        }
+
+        png(width, height, &pixels)
+    }
+
+    /// Render values of `map` into a PNG image
+    pub fn hexmap<I>(map : &HexMap<f32, I>, spacing : Spacing<f32>) -> Vec<u8> where
+        I : hex2d::Integer
+    {
+        heatmap(map.bounds(), |c| map.get(c).cloned(), spacing)
+    }
+
+    fn crc32(data : &[u8]) -> u32 {
+        let mut crc = !0u32;
+        for &byte in data {
+            crc ^= byte as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+            }
+        }
+        !crc
+    }
+
+    fn adler32(data : &[u8]) -> u32 {
+        let (mut a, mut b) = (1u32, 0u32);
+        for &byte in data {
+            a = (a + byte as u32) % 65521;
+            b = (b + a) % 65521;
+        }
+        (b << 16) | a
+    }
+
+    fn chunk(out : &mut Vec<u8>, kind : &[u8; 4], data : &[u8]) {
+        out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        let start = out.len();
+        out.extend_from_slice(kind);
+        out.extend_from_slice(data);
+        let crc = crc32(&out[start..]);
+        out.extend_from_slice(&crc.to_be_bytes());
+    }
+
+    /// Encode RGB `pixels` as a PNG, using stored (uncompressed) deflate blocks
+    fn png(width : u32, height : u32, pixels : &[u8]) -> Vec<u8> {
+        let mut raw = Vec::with_capacity(pixels.len() + height as usize);
+        for row in pixels.chunks(width as usize * 3) {
+            raw.push(0);
+            raw.extend_from_slice(row);
+        }
+
+        let mut zlib = vec!(0x78, 0x01);
+        let blocks : Vec<&[u8]> = raw.chunks(0xffff).collect();
+        for (i, block) in blocks.iter().enumerate() {
+            zlib.push((i + 1 == blocks.len()) as u8);
+            zlib.extend_from_slice(&(block.len() as u16).to_le_bytes());
+            zlib.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+            zlib.extend_from_slice(block);
+        }
+        zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+        let mut ihdr = vec!();
+        ihdr.extend_from_slice(&width.to_be_bytes());
+        ihdr.extend_from_slice(&height.to_be_bytes());
+        ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+        let mut out = vec!(0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a);
+        chunk(&mut out, b"IHDR", &ihdr);
+        chunk(&mut out, b"IDAT", &zlib);
+        chunk(&mut out, b"IEND", &[]);
+        out
+    }
+}
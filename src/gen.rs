@@ -0,0 +1,2057 @@
+// Copyright 2014 Dawid Ciężarkiewicz
+// See LICENSE file for more information
+
+/// Cellular automata cave generator
+pub mod cellular {
+    use hex2d;
+    use hex2d::Coordinate;
+
+    use rand::Rng;
+
+    use std::hash;
+
+    use hashing::{InternalHashSet, OutputHashSet};
+    use algo::bfs;
+    use geom::Bounds;
+
+    /// Generator configuration
+    #[derive(Clone, Debug)]
+    pub struct Config {
+        /// Chance of every Coordinate to start open
+        pub open_chance : f64,
+        /// Numbers of open neighbors that make a wall open
+        pub birth : Vec<usize>,
+        /// Numbers of open neighbors that keep an open Coordinate open
+        pub survival : Vec<usize>,
+        /// Number of automaton steps
+        pub iterations : u32,
+        /// Carve tunnels between disconnected caverns
+        pub connect : bool,
+    }
+
+    impl Default for Config {
+        fn default() -> Config {
+            Config {
+                open_chance: 0.55,
+                birth: vec!(4, 5, 6),
+                survival: vec!(3, 4, 5, 6),
+                iterations: 4,
+                connect: true,
+            }
+        }
+    }
+
+    /// Generate a cave within `bounds`
+    ///
+    /// Returns the set of open Coordinates. Everything outside of `bounds` is considered a wall.
+    pub fn generate<I, R>(bounds : Bounds<I>, config : &Config, rng : &mut R) -> OutputHashSet<Coordinate<I>> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : ::std::ops::AddAssign,
+        R : Rng
+    {
+
+        let mut open : OutputHashSet<Coordinate<I>> = bounds.iter()
+            .filter(|_| rng.gen_bool(config.open_chance))
+            .collect();
+
+        for _ in 0..config.iterations {
+            open = bounds.iter().filter(|&c| {
+                let count = c.neighbors().iter().filter(|n| open.contains(n)).count();
+                if open.contains(&c) {
+                    config.survival.contains(&count)
+                } else {
+                    config.birth.contains(&count)
+                }
+            }).collect();
+        }
+
+        if config.connect {
+            connect(bounds, &mut open);
+        }
+
+        open
+    }
+
+    /// All Coordinates of `open` connected with `start`
+    fn cavern<I>(open : &OutputHashSet<Coordinate<I>>, start : Coordinate<I>) -> Vec<Coordinate<I>> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : ::std::ops::AddAssign
+    {
+        let mut traverser = bfs::Traverser::new(|c| open.contains(&c), |c| open.contains(&c), start);
+        let mut res = vec!();
+        while let Some(c) = traverser.find() {
+            res.push(c);
+        }
+        res
+    }
+
+    /// Carve tunnels, so all the caverns are connected with the biggest one
+    fn connect<I>(bounds : Bounds<I>, open : &mut OutputHashSet<Coordinate<I>>) where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : ::std::ops::AddAssign
+    {
+        let mut caverns = vec!();
+        let mut assigned = InternalHashSet::default();
+        for c in bounds.iter() {
+            if open.contains(&c) && !assigned.contains(&c) {
+                let cavern = cavern(open, c);
+                assigned.extend(cavern.iter().cloned());
+                caverns.push(cavern);
+            }
+        }
+
+        let main = match (0..caverns.len()).max_by_key(|&i| (caverns[i].len(), caverns.len() - i)) {
+            Some(i) => caverns.swap_remove(i),
+            None => return,
+        };
+        let mut main : InternalHashSet<_> = main.into_iter().collect();
+
+        for cavern in caverns {
+            let start = cavern[0];
+            let tunnel = {
+                let mut traverser = bfs::Traverser::new(
+                    |c| bounds.contains(c),
+                    |c| main.contains(&c),
+                    start
+                    );
+                let mut pos = traverser.find().expect("cellular: caverns within bounds are always connectable");
+
+                let mut tunnel = vec!();
+                while pos != start {
+                    pos = traverser.backtrace(pos).unwrap();
+                    tunnel.push(pos);
+                }
+                tunnel
+            };
+            main.extend(tunnel);
+            main.extend(cavern);
+        }
+
+        open.extend(main);
+    }
+}
+
+/// Drunkard's walk tunneler
+///
+/// Random walks starting from seed points carve an organic open area.
+pub mod drunkard {
+    use hex2d;
+    use hex2d::Coordinate;
+    use hex2d::Direction;
+
+    use rand::Rng;
+
+    use std::hash;
+
+    use hashing::OutputHashSet;
+    use geom::Bounds;
+
+    /// Number of walks in a row that carve nothing new, after which `generate` gives up
+    pub const STALL_WALKS : u32 = 64;
+
+    /// Generator configuration
+    #[derive(Clone, Debug)]
+    pub struct Config {
+        /// Fraction of `bounds` to carve before stopping
+        pub open_ratio : f64,
+        /// Chance of a walker to keep going in the same direction instead of picking a random one
+        ///
+        /// `0.0` gives compact, blobby areas, values close to `1.0` long, straight tunnels.
+        pub momentum : f64,
+        /// Number of steps of a single walk
+        pub walk_length : u32,
+    }
+
+    impl Default for Config {
+        fn default() -> Config {
+            Config {
+                open_ratio: 0.4,
+                momentum: 0.3,
+                walk_length: 100,
+            }
+        }
+    }
+
+    /// Carve an area within `bounds` with walks starting from `seeds`
+    ///
+    /// Walks start from `seeds` in turns until `open_ratio` of `bounds` is carved, or until
+    /// `STALL_WALKS` walks in a row carve nothing new (eg. with `momentum` of `1.0` every walk
+    /// is a straight ray, and the rays from a seed run out quickly). Returns the set of floor
+    /// Coordinates. `seeds` outside of `bounds` are ignored.
+    ///
+    /// Panics if `config.walk_length` is `0`.
+    pub fn generate<I, R>(bounds : Bounds<I>, seeds : &[Coordinate<I>], config : &Config, rng : &mut R) -> OutputHashSet<Coordinate<I>> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        R : Rng
+    {
+        assert!(config.walk_length > 0, "drunkard: walk_length must be positive");
+
+        let seeds : Vec<_> = seeds.iter().cloned().filter(|&c| bounds.contains(c)).collect();
+        let mut floor : OutputHashSet<_> = seeds.iter().cloned().collect();
+
+        if seeds.is_empty() {
+            return floor;
+        }
+
+        let target = ((bounds.len() as f64 * config.open_ratio).ceil() as usize).min(bounds.len());
+
+        let mut stalled = 0;
+        for &start in seeds.iter().cycle() {
+            if floor.len() >= target || stalled >= STALL_WALKS {
+                break;
+            }
+
+            let carved = floor.len();
+            let mut pos = start;
+            let mut dir = Direction::all()[rng.gen_range(0..6)];
+
+            for _ in 0..config.walk_length {
+                if !rng.gen_bool(config.momentum) {
+                    dir = Direction::all()[rng.gen_range(0..6)];
+                }
+
+                let npos = pos + dir;
+                if !bounds.contains(npos) {
+                    continue;
+                }
+
+                pos = npos;
+                floor.insert(pos);
+                if floor.len() >= target {
+                    break;
+                }
+            }
+
+            stalled = if floor.len() == carved { stalled + 1 } else { 0 };
+        }
+
+        floor
+    }
+}
+
+/// Rooms and corridors dungeon generator
+pub mod rooms {
+    use hex2d;
+    use hex2d::Coordinate;
+
+    use rand::Rng;
+
+    use std::hash;
+
+    use hashing::{InternalHashSet, OutputHashSet};
+    use algo::bfs;
+    use geom::Bounds;
+
+    /// Generator configuration
+    #[derive(Clone, Debug)]
+    pub struct Config {
+        /// Maximum number of rooms to place
+        pub room_count : usize,
+        /// Minimum room radius
+        pub min_radius : u32,
+        /// Maximum room radius
+        pub max_radius : u32,
+        /// Minimum number of wall Coordinates between any two rooms
+        pub margin : u32,
+        /// Chance of every Coordinate on the edge of a room to be cut off
+        ///
+        /// `0.0` gives perfectly hexagonal rooms, bigger values more blob-like ones.
+        pub roughness : f64,
+        /// Number of placement attempts before giving up on reaching `room_count`
+        pub attempts : u32,
+    }
+
+    impl Default for Config {
+        fn default() -> Config {
+            Config {
+                room_count: 8,
+                min_radius: 2,
+                max_radius: 4,
+                margin: 2,
+                roughness: 0.0,
+                attempts: 200,
+            }
+        }
+    }
+
+    /// A room
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct Room<I = i32> where
+        I : hex2d::Integer
+    {
+        /// Center of the room
+        pub center : Coordinate<I>,
+        /// Radius of the room
+        pub radius : u32,
+        /// Floor Coordinates of the room
+        pub floor : Vec<Coordinate<I>>,
+    }
+
+    /// Generated dungeon
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct Dungeon<I = i32> where
+        I : hex2d::Integer
+    {
+        /// Placed rooms
+        pub rooms : Vec<Room<I>>,
+        /// Corridors connecting the rooms, each running from one room to another
+        pub corridors : Vec<Vec<Coordinate<I>>>,
+        /// Corridor Coordinates adjacent to a room, where doors could be placed
+        pub doors : Vec<Coordinate<I>>,
+    }
+
+    impl<I> Dungeon<I> where
+        I : hex2d::Integer,
+        I : hash::Hash
+    {
+        /// All floor Coordinates: rooms and corridors
+        pub fn floor(&self) -> OutputHashSet<Coordinate<I>> {
+            self.rooms.iter().flat_map(|r| r.floor.iter())
+                .chain(self.corridors.iter().flat_map(|c| c.iter()))
+                .cloned()
+                .collect()
+        }
+    }
+
+    /// Generate a dungeon within `bounds`
+    ///
+    /// Rooms never overlap and are kept at least one Coordinate away from the edge of
+    /// `bounds`. Each room is connected with the closest room placed before it, with
+    /// a corridor carved along the shortest path that doesn't cross other rooms (if there is one).
+    pub fn generate<I, R>(bounds : Bounds<I>, config : &Config, rng : &mut R) -> Dungeon<I> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : ::std::ops::AddAssign,
+        R : Rng
+    {
+        let all : Vec<_> = bounds.iter().collect();
+
+        let mut rooms : Vec<Room<I>> = vec!();
+
+        for _ in 0..config.attempts {
+            if rooms.len() >= config.room_count || all.is_empty() {
+                break;
+            }
+
+            let center = all[rng.gen_range(0..all.len())];
+            let radius = rng.gen_range(config.min_radius..config.max_radius + 1);
+            let r = I::from_u32(radius).unwrap();
+
+            if !center.ring_iter(radius as i32 + 1, hex2d::Spin::CW(hex2d::YZ)).all(|c| bounds.contains(c)) {
+                continue;
+            }
+
+            let overlaps = rooms.iter().any(|room| {
+                let dist = center.distance(room.center).to_u32().unwrap();
+                dist <= radius + room.radius + config.margin
+            });
+            if overlaps {
+                continue;
+            }
+
+            let floor = center.range_iter(r)
+                .filter(|&c| c == center || center.distance(c) < r || !rng.gen_bool(config.roughness))
+                .collect();
+
+            rooms.push(Room { center, radius, floor });
+        }
+
+        let room_floor : Vec<InternalHashSet<_>> = rooms.iter()
+            .map(|room| room.floor.iter().cloned().collect())
+            .collect();
+
+        let mut corridors = vec!();
+        for (i, room) in rooms.iter().enumerate().skip(1) {
+            let j = (0..i).min_by_key(|&j| room.center.distance(rooms[j].center)).unwrap();
+
+            let other_room = |c| room_floor.iter().enumerate()
+                .any(|(k, floor)| k != i && k != j && floor.contains(&c));
+
+            let path = carve(bounds, room.center, &room_floor[j], |c| !other_room(c))
+                .or_else(|| carve(bounds, room.center, &room_floor[j], |_| true))
+                .expect("rooms: rooms within bounds are always connectable");
+
+            let corridor : Vec<_> = path.into_iter()
+                .filter(|c| !room_floor.iter().any(|floor| floor.contains(c)))
+                .collect();
+            if !corridor.is_empty() {
+                corridors.push(corridor);
+            }
+        }
+
+        let mut doors = vec!();
+        for corridor in corridors.iter() {
+            for &c in corridor.iter() {
+                if c.neighbors().iter().any(|n| room_floor.iter().any(|floor| floor.contains(n))) {
+                    doors.push(c);
+                }
+            }
+        }
+
+        Dungeon { rooms, corridors, doors }
+    }
+
+    /// Shortest path from `start` to any of `dest` within `bounds`
+    fn carve<I, FCanPass>(
+        bounds : Bounds<I>,
+        start : Coordinate<I>,
+        dest : &InternalHashSet<Coordinate<I>>,
+        can_pass : FCanPass
+        ) -> Option<Vec<Coordinate<I>>> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : ::std::ops::AddAssign,
+        FCanPass : Fn(Coordinate<I>) -> bool
+    {
+        let mut traverser = bfs::Traverser::new(
+            |c| bounds.contains(c) && (can_pass(c) || dest.contains(&c)),
+            |c| dest.contains(&c),
+            start
+            );
+
+        let mut pos = traverser.find()?;
+        let mut path = vec!(pos);
+        while pos != start {
+            pos = traverser.backtrace(pos).unwrap();
+            path.push(pos);
+        }
+        path.reverse();
+        Some(path)
+    }
+}
+
+/// Maze generators
+///
+/// Mazes are made of cells separated by edge walls. They can be used directly, with
+/// `Maze::can_cross` as an edge passability check, or converted to a regular passability map
+/// with `Maze::cells`.
+pub mod maze {
+    use hex2d;
+    use hex2d::Coordinate;
+    use hex2d::Direction;
+
+    use rand::Rng;
+    use rand::seq::SliceRandom;
+
+    use std::hash;
+
+    use hashing::{InternalHashSet, OutputHashSet};
+    use geom::Bounds;
+
+    /// Maze generation algorithm
+    #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub enum Algorithm {
+        /// Recursive backtracker: long, winding corridors with few dead ends
+        Backtracker,
+        /// Randomized Kruskal: many short dead ends
+        Kruskal,
+    }
+
+    /// Generated maze
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct Maze<I = i32> where
+        I : hex2d::Integer,
+        I : hash::Hash
+    {
+        bounds : Bounds<I>,
+        passages : InternalHashSet<(Coordinate<I>, Direction)>,
+    }
+
+    /// Every edge has two names; pick one of them
+    fn edge<I>(c : Coordinate<I>, dir : Direction) -> (Coordinate<I>, Direction) where
+        I : hex2d::Integer
+    {
+        if dir.to_int::<i8>() < 3 {
+            (c, dir)
+        } else {
+            (c + dir, -dir)
+        }
+    }
+
+    impl<I> Maze<I> where
+        I : hex2d::Integer,
+        I : hash::Hash
+    {
+        /// Region covered by the maze
+        pub fn bounds(&self) -> Bounds<I> {
+            self.bounds
+        }
+
+        /// Is there a passage from `pos` in direction `dir`
+        pub fn can_cross(&self, pos : Coordinate<I>, dir : Direction) -> bool {
+            self.passages.contains(&edge(pos, dir))
+        }
+
+        /// All walls between the cells of the maze
+        ///
+        /// Every wall is listed once, as a cell and Direction of the wall from that cell. Walls
+        /// on the outer edge of `bounds` are not listed.
+        pub fn walls(&self) -> Vec<(Coordinate<I>, Direction)> {
+            let mut res = vec!();
+            for c in self.bounds.iter() {
+                for &dir in Direction::all()[..3].iter() {
+                    if self.bounds.contains(c + dir) && !self.passages.contains(&(c, dir)) {
+                        res.push((c, dir));
+                    }
+                }
+            }
+            res
+        }
+
+        /// Maze converted to a set of open Coordinates
+        ///
+        /// Every cell `c` becomes `c.scale(2)` and every passage between two cells becomes the
+        /// Coordinate between them. Everything else is a wall.
+        pub fn cells(&self) -> OutputHashSet<Coordinate<I>> {
+            let two = I::from_i8(2).unwrap();
+            let mut res = OutputHashSet::default();
+            for c in self.bounds.iter() {
+                res.insert(c.scale(two));
+                for &dir in Direction::all()[..3].iter() {
+                    if self.passages.contains(&(c, dir)) {
+                        res.insert(c.scale(two) + dir);
+                    }
+                }
+            }
+            res
+        }
+    }
+
+    /// Generate a perfect maze over cells of `bounds`
+    pub fn generate<I, R>(bounds : Bounds<I>, algorithm : Algorithm, rng : &mut R) -> Maze<I> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        R : Rng
+    {
+        let passages = match algorithm {
+            Algorithm::Backtracker => backtracker(bounds, rng),
+            Algorithm::Kruskal => kruskal(bounds, rng),
+        };
+
+        Maze { bounds, passages }
+    }
+
+    fn backtracker<I, R>(bounds : Bounds<I>, rng : &mut R) -> InternalHashSet<(Coordinate<I>, Direction)> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        R : Rng
+    {
+        let mut passages = InternalHashSet::default();
+        let mut visited = vec!(false; bounds.len());
+
+        let start = match bounds.iter().next() {
+            Some(c) => c,
+            None => return passages,
+        };
+        visited[0] = true;
+        let mut stack = vec!(start);
+
+        while let Some(&pos) = stack.last() {
+            let dirs : Vec<_> = Direction::all().iter().cloned()
+                .filter(|&dir| bounds.index(pos + dir).map(|i| !visited[i]).unwrap_or(false))
+                .collect();
+
+            match dirs.choose(rng) {
+                Some(&dir) => {
+                    let npos = pos + dir;
+                    visited[bounds.index(npos).unwrap()] = true;
+                    passages.insert(edge(pos, dir));
+                    stack.push(npos);
+                },
+                None => {
+                    stack.pop();
+                }
+            }
+        }
+
+        passages
+    }
+
+    fn find(parents : &mut [usize], i : usize) -> usize {
+        let mut root = i;
+        while parents[root] != root {
+            root = parents[root];
+        }
+
+        let mut i = i;
+        while parents[i] != root {
+            let next = parents[i];
+            parents[i] = root;
+            i = next;
+        }
+
+        root
+    }
+
+    fn kruskal<I, R>(bounds : Bounds<I>, rng : &mut R) -> InternalHashSet<(Coordinate<I>, Direction)> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        R : Rng
+    {
+        let mut edges = vec!();
+        for c in bounds.iter() {
+            for &dir in Direction::all()[..3].iter() {
+                if bounds.contains(c + dir) {
+                    edges.push((c, dir));
+                }
+            }
+        }
+        edges.shuffle(rng);
+
+        let mut parents : Vec<_> = (0..bounds.len()).collect();
+        let mut passages = InternalHashSet::default();
+
+        for (c, dir) in edges {
+            let a = find(&mut parents, bounds.index(c).unwrap());
+            let b = find(&mut parents, bounds.index(c + dir).unwrap());
+            if a != b {
+                parents[a] = b;
+                passages.insert((c, dir));
+            }
+        }
+
+        passages
+    }
+}
+
+/// Wave Function Collapse tile solver
+///
+/// Fills a region with tiles, so every pair of neighbors is allowed by the adjacency rules.
+pub mod wfc {
+    use hex2d;
+    use hex2d::Coordinate;
+    use hex2d::Direction;
+
+    use rand::Rng;
+
+    use std::hash;
+    use std::collections::VecDeque;
+
+    use hashing::OutputHashMap;
+    use geom::Bounds;
+
+    /// Tiles: their weights and allowed adjacency
+    ///
+    /// Tiles are identified by their index in `[0, len())` range.
+    #[derive(Clone, Debug)]
+    pub struct Rules {
+        weights : Vec<f64>,
+        allowed : Vec<[Vec<bool>; 6]>,
+    }
+
+    impl Rules {
+        /// Create rules for tiles with given `weights`, with no adjacency allowed
+        ///
+        /// Tiles with bigger weights are picked more often.
+        pub fn new(weights : Vec<f64>) -> Rules {
+            let n = weights.len();
+            Rules {
+                allowed: (0..n).map(|_| [
+                    vec!(false; n), vec!(false; n), vec!(false; n),
+                    vec!(false; n), vec!(false; n), vec!(false; n),
+                ]).collect(),
+                weights,
+            }
+        }
+
+        /// Number of tiles
+        pub fn len(&self) -> usize {
+            self.weights.len()
+        }
+
+        /// Are there no tiles
+        pub fn is_empty(&self) -> bool {
+            self.weights.is_empty()
+        }
+
+        /// Allow tile `b` to be next to tile `a` in direction `dir`
+        ///
+        /// This also allows `a` to be next to `b` in the opposite direction.
+        pub fn allow(&mut self, a : usize, dir : Direction, b : usize) {
+            self.allowed[a][dir.to_int::<i8>() as usize][b] = true;
+            self.allowed[b][(-dir).to_int::<i8>() as usize][a] = true;
+        }
+
+        /// Allow tiles `a` and `b` to be next to each other in any direction
+        pub fn allow_all(&mut self, a : usize, b : usize) {
+            for &dir in Direction::all().iter() {
+                self.allow(a, dir, b);
+            }
+        }
+    }
+
+    /// Solver configuration
+    #[derive(Clone, Debug)]
+    pub struct Config {
+        /// Number of times the solver can undo its choice after a contradiction before giving up
+        pub max_backtracks : u32,
+    }
+
+    impl Default for Config {
+        fn default() -> Config {
+            Config {
+                max_backtracks: 1000,
+            }
+        }
+    }
+
+    struct Wave<'a, I> where
+        I : hex2d::Integer
+    {
+        bounds : Bounds<I>,
+        coords : Vec<Coordinate<I>>,
+        rules : &'a Rules,
+        possible : Vec<bool>,
+    }
+
+    impl<'a, I> Wave<'a, I> where
+        I : hex2d::Integer
+    {
+        fn tiles(&self, cell : usize) -> &[bool] {
+            let n = self.rules.len();
+            &self.possible[cell * n..(cell + 1) * n]
+        }
+
+        fn count(&self, cell : usize) -> usize {
+            self.tiles(cell).iter().filter(|&&p| p).count()
+        }
+
+        fn ban(&mut self, cell : usize, tile : usize) {
+            let n = self.rules.len();
+            self.possible[cell * n + tile] = false;
+        }
+
+        /// Make neighbors of `changed` cells consistent with them
+        ///
+        /// Returns false on contradiction.
+        fn propagate(&mut self, mut changed : VecDeque<usize>) -> bool {
+            let n = self.rules.len();
+
+            while let Some(cell) = changed.pop_front() {
+                let pos = self.coords[cell];
+
+                for &dir in Direction::all().iter() {
+                    let ncell = match self.bounds.index(pos + dir) {
+                        Some(ncell) => ncell,
+                        None => continue,
+                    };
+                    let d = dir.to_int::<i8>() as usize;
+
+                    let mut modified = false;
+                    for b in 0..n {
+                        if !self.possible[ncell * n + b] {
+                            continue;
+                        }
+                        let supported = (0..n).any(|a|
+                            self.possible[cell * n + a] && self.rules.allowed[a][d][b]
+                        );
+                        if !supported {
+                            self.possible[ncell * n + b] = false;
+                            modified = true;
+                        }
+                    }
+
+                    if modified {
+                        if self.count(ncell) == 0 {
+                            return false;
+                        }
+                        changed.push_back(ncell);
+                    }
+                }
+            }
+
+            true
+        }
+
+        /// Undecided cell with the lowest entropy
+        fn lowest_entropy<R : Rng>(&self, rng : &mut R) -> Option<usize> {
+            let mut best = None;
+            let mut best_entropy = f64::INFINITY;
+
+            for cell in 0..self.coords.len() {
+                if self.count(cell) < 2 {
+                    continue;
+                }
+
+                let (sum, sum_log) = self.tiles(cell).iter().enumerate()
+                    .filter(|&(_, &p)| p)
+                    .map(|(t, _)| self.rules.weights[t])
+                    .fold((0.0, 0.0), |(s, sl), w| (s + w, sl + w * w.ln()));
+                let entropy = sum.ln() - sum_log / sum + rng.gen::<f64>() * 1e-6;
+
+                if entropy < best_entropy {
+                    best = Some(cell);
+                    best_entropy = entropy;
+                }
+            }
+
+            best
+        }
+
+        /// Pick a tile for `cell`, randomly by weight
+        fn pick<R : Rng>(&self, cell : usize, rng : &mut R) -> usize {
+            let candidates : Vec<_> = self.tiles(cell).iter().enumerate()
+                .filter(|&(_, &p)| p)
+                .map(|(t, _)| t)
+                .collect();
+            let total : f64 = candidates.iter().map(|&t| self.rules.weights[t]).sum();
+
+            let mut x = rng.gen::<f64>() * total;
+            for &t in candidates.iter() {
+                x -= self.rules.weights[t];
+                if x <= 0.0 {
+                    return t;
+                }
+            }
+            *candidates.last().unwrap()
+        }
+    }
+
+    /// Assign a tile to every Coordinate of `bounds`
+    ///
+    /// Returns `None` if the rules can't be satisfied, or the solver had to backtrack more than
+    /// `config.max_backtracks` times.
+    pub fn solve<I, R>(bounds : Bounds<I>, rules : &Rules, config : &Config, rng : &mut R) -> Option<OutputHashMap<Coordinate<I>, usize>> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        R : Rng
+    {
+        let coords : Vec<_> = bounds.iter().collect();
+
+        let mut wave = Wave {
+            bounds,
+            possible: vec!(true; coords.len() * rules.len()),
+            coords,
+            rules,
+        };
+
+        if rules.is_empty() || !wave.propagate((0..wave.coords.len()).collect()) {
+            return None;
+        }
+
+        // Choices made so far, with the state from before each of them
+        let mut decisions : Vec<(Vec<bool>, usize, usize)> = vec!();
+        let mut backtracks = 0;
+
+        while let Some(cell) = wave.lowest_entropy(rng) {
+            let tile = wave.pick(cell, rng);
+            decisions.push((wave.possible.clone(), cell, tile));
+
+            for t in 0..rules.len() {
+                if t != tile {
+                    wave.ban(cell, t);
+                }
+            }
+
+            let mut consistent = wave.propagate(vec!(cell).into_iter().collect());
+
+            while !consistent {
+                backtracks += 1;
+                if backtracks > config.max_backtracks {
+                    return None;
+                }
+
+                let (possible, cell, tile) = decisions.pop()?;
+                wave.possible = possible;
+                wave.ban(cell, tile);
+                consistent = wave.count(cell) > 0 && wave.propagate(vec!(cell).into_iter().collect());
+            }
+        }
+
+        let n = rules.len();
+        Some(wave.coords.iter().enumerate().map(|(cell, &c)| {
+            (c, (0..n).find(|&t| wave.possible[cell * n + t]).unwrap())
+        }).collect())
+    }
+}
+
+/// Heightmap erosion and smoothing
+///
+/// Neighbors outside of the heightmap are ignored.
+pub mod erosion {
+    use hex2d;
+    use hex2d::Coordinate;
+
+    use rand::Rng;
+
+    use map::HexMap;
+
+    /// Move every height `strength` fraction of the way towards the average of its neighbors
+    pub fn smooth<I>(heights : &mut HexMap<f32, I>, strength : f32) where
+        I : hex2d::Integer
+    {
+        let smoothed = heights.map(|c, &h| {
+            let (sum, count) = c.neighbors().iter()
+                .filter_map(|&n| heights.get(n))
+                .fold((0.0, 0), |(sum, count), &nh| (sum + nh, count + 1));
+
+            if count == 0 {
+                h
+            } else {
+                h + (sum / count as f32 - h) * strength
+            }
+        });
+
+        *heights = smoothed;
+    }
+
+    /// Thermal erosion: slopes steeper than `talus` crumble down
+    ///
+    /// In each of `iterations`, every Coordinate passes `rate` fraction of the height
+    /// difference exceeding `talus` to each of its lower neighbors.
+    pub fn thermal<I>(heights : &mut HexMap<f32, I>, talus : f32, rate : f32, iterations : u32) where
+        I : hex2d::Integer
+    {
+        for _ in 0..iterations {
+            let mut delta = HexMap::new(heights.bounds(), 0.0);
+
+            for (c, &h) in heights.iter() {
+                for &n in c.neighbors().iter() {
+                    if let Some(&nh) = heights.get(n) {
+                        let diff = h - nh;
+                        if diff > talus {
+                            let moved = (diff - talus) * rate / 6.0;
+                            delta[c] -= moved;
+                            delta[n] += moved;
+                        }
+                    }
+                }
+            }
+
+            for (c, h) in heights.iter_mut() {
+                *h += delta[c];
+            }
+        }
+    }
+
+    /// Hydraulic erosion configuration
+    #[derive(Clone, Debug)]
+    pub struct Hydraulic {
+        /// Number of simulated water droplets
+        pub droplets : u32,
+        /// Maximum number of steps of a single droplet
+        pub max_steps : u32,
+        /// Sediment a droplet can carry, relative to the slope and its water
+        pub capacity : f32,
+        /// Fraction of missing capacity picked up from the ground with every step
+        pub erosion : f32,
+        /// Fraction of excess sediment dropped with every step
+        pub deposition : f32,
+        /// Fraction of water evaporating with every step
+        pub evaporation : f32,
+    }
+
+    impl Default for Hydraulic {
+        fn default() -> Hydraulic {
+            Hydraulic {
+                droplets: 1000,
+                max_steps: 64,
+                capacity: 4.0,
+                erosion: 0.3,
+                deposition: 0.3,
+                evaporation: 0.05,
+            }
+        }
+    }
+
+    /// Hydraulic erosion: droplets flowing downhill carve valleys
+    ///
+    /// Every droplet starts at a random Coordinate and flows to the lowest neighbor, picking up
+    /// sediment on steep slopes and dropping it where the slope flattens out.
+    pub fn hydraulic<I, R>(heights : &mut HexMap<f32, I>, config : &Hydraulic, rng : &mut R) where
+        I : hex2d::Integer,
+        R : Rng
+    {
+        let coords : Vec<_> = heights.bounds().iter().collect();
+        if coords.is_empty() {
+            return;
+        }
+
+        for _ in 0..config.droplets {
+            let mut pos = coords[rng.gen_range(0..coords.len())];
+            let mut water = 1.0;
+            let mut sediment = 0.0;
+
+            for _ in 0..config.max_steps {
+                let h = heights[pos];
+                let lowest = pos.neighbors().iter()
+                    .filter_map(|&n| heights.get(n).map(|&nh| (n, nh)))
+                    .fold(None, |best : Option<(Coordinate<I>, f32)>, (n, nh)| match best {
+                        Some((_, bh)) if bh <= nh => best,
+                        _ => Some((n, nh)),
+                    });
+
+                let (npos, nh) = match lowest {
+                    Some((npos, nh)) if nh < h => (npos, nh),
+                    _ => break,
+                };
+
+                let slope = h - nh;
+                let capacity = slope * water * config.capacity;
+
+                if sediment > capacity {
+                    let dropped = (sediment - capacity) * config.deposition;
+                    sediment -= dropped;
+                    heights[pos] += dropped;
+                } else {
+                    let taken = ((capacity - sediment) * config.erosion).min(slope);
+                    sediment += taken;
+                    heights[pos] -= taken;
+                }
+
+                pos = npos;
+                water *= 1.0 - config.evaporation;
+            }
+
+            heights[pos] += sediment;
+        }
+    }
+}
+
+/// River carving along downhill gradients
+pub mod rivers {
+    use hex2d;
+    use hex2d::Coordinate;
+
+    use rand::Rng;
+
+    use std::hash;
+
+    use hashing::InternalHashMap;
+    use map::HexMap;
+
+    /// A river
+    #[derive(Clone, Debug, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct River<I = i32> where
+        I : hex2d::Integer
+    {
+        /// Coordinates from the spring down to the mouth
+        pub course : Vec<Coordinate<I>>,
+        /// Width at each Coordinate of `course`: number of rivers flowing through it
+        pub widths : Vec<u32>,
+        /// River this one flows into, and index of the joining point in its `course`
+        pub joins : Option<(usize, usize)>,
+    }
+
+    /// Generator configuration
+    #[derive(Clone, Debug)]
+    pub struct Config {
+        /// Random noise added to neighbor heights when picking the way down
+        pub jitter : f32,
+        /// Maximum length of a river
+        pub max_length : usize,
+    }
+
+    impl Default for Config {
+        fn default() -> Config {
+            Config {
+                jitter: 0.0,
+                max_length: 1000,
+            }
+        }
+    }
+
+    /// Trace rivers from `springs` downhill over `heights`
+    ///
+    /// Each river flows to the lowest neighbor (after adding `jitter`) that is lower than its
+    /// current Coordinate, until it reaches a sink, leaves the heightmap, joins a river traced
+    /// before it, or reaches `max_length`. Springs outside of `heights` are skipped.
+    pub fn trace<I, R>(heights : &HexMap<f32, I>, springs : &[Coordinate<I>], config : &Config, rng : &mut R) -> Vec<River<I>> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        R : Rng
+    {
+
+        let mut rivers : Vec<River<I>> = vec!();
+        // Which river (and where) flows through given Coordinate
+        let mut owner : InternalHashMap<Coordinate<I>, (usize, usize)> = InternalHashMap::default();
+
+        for &spring in springs.iter() {
+            if !heights.contains(spring) {
+                continue;
+            }
+
+            let mut course = vec!();
+            let mut joins = None;
+            let mut pos = spring;
+
+            loop {
+                if let Some(&join) = owner.get(&pos) {
+                    joins = Some(join);
+                    break;
+                }
+                course.push(pos);
+                if course.len() >= config.max_length {
+                    break;
+                }
+
+                let h = heights[pos];
+                let mut next = None;
+                let mut next_h = h;
+                for n in pos.neighbors().iter() {
+                    if let Some(&nh) = heights.get(*n) {
+                        if nh >= h {
+                            continue;
+                        }
+                        let nh = nh + rng.gen::<f32>() * config.jitter;
+                        if next.is_none() || nh < next_h {
+                            next = Some(*n);
+                            next_h = nh;
+                        }
+                    }
+                }
+
+                match next {
+                    Some(n) => pos = n,
+                    None => break,
+                }
+            }
+
+            if course.is_empty() {
+                continue;
+            }
+
+            let i = rivers.len();
+            for (j, &c) in course.iter().enumerate() {
+                owner.insert(c, (i, j));
+            }
+            rivers.push(River { course, widths: vec!(), joins });
+        }
+
+        let mut flow : InternalHashMap<Coordinate<I>, u32> = InternalHashMap::default();
+        for i in 0..rivers.len() {
+            for &c in rivers[i].course.iter() {
+                *flow.entry(c).or_insert(0) += 1;
+            }
+
+            let mut joins = rivers[i].joins;
+            while let Some((k, j)) = joins {
+                for &c in rivers[k].course[j..].iter() {
+                    *flow.entry(c).or_insert(0) += 1;
+                }
+                joins = rivers[k].joins;
+            }
+        }
+
+        for river in rivers.iter_mut() {
+            river.widths = river.course.iter().map(|c| flow[c]).collect();
+        }
+
+        rivers
+    }
+}
+
+/// Biome assignment
+///
+/// Combines a heightmap, a moisture layer and Voronoi regions around seed Coordinates. Height
+/// is taken per Coordinate, while moisture is averaged over each region, so climate forms
+/// coherent patches and terrain features (mountains, lakes) keep their exact shape.
+pub mod biomes {
+    use hex2d;
+    use hex2d::Coordinate;
+
+    use std::collections::BTreeSet;
+
+    use map::HexMap;
+
+    /// Biome identifier; the meaning of the values is up to the user
+    #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Ord, PartialOrd, Default)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct BiomeId(pub u16);
+
+    /// Result of biome assignment
+    #[derive(Clone, Debug, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct Biomes<I = i32> where
+        I : hex2d::Integer
+    {
+        /// Biome of every Coordinate
+        pub biomes : HexMap<BiomeId, I>,
+        /// Region (index of the closest seed) of every Coordinate
+        pub regions : HexMap<usize, I>,
+        /// Average moisture of every region
+        pub moisture : Vec<f32>,
+        /// Pairs of neighboring regions, smaller index first
+        pub adjacency : BTreeSet<(usize, usize)>,
+    }
+
+    /// Assign biomes to all Coordinates of `heights`
+    ///
+    /// Every Coordinate belongs to the region of the closest of `seeds` (ties go to the seed
+    /// listed first). `classify` gets height of the Coordinate and average moisture of its
+    /// region. Coordinates missing from `moisture` count as `0.0`.
+    ///
+    /// Panics if `seeds` is empty.
+    pub fn assign<I, FClassify>(
+        heights : &HexMap<f32, I>,
+        moisture : &HexMap<f32, I>,
+        seeds : &[Coordinate<I>],
+        classify : FClassify,
+        ) -> Biomes<I> where
+        I : hex2d::Integer,
+        FClassify : Fn(f32, f32) -> BiomeId
+    {
+        assert!(!seeds.is_empty(), "biomes: at least one seed is required");
+
+        let regions = heights.map(|c, _| {
+            (0..seeds.len()).min_by_key(|&i| seeds[i].distance(c)).unwrap()
+        });
+
+        let mut sums = vec!((0.0, 0); seeds.len());
+        for (c, &i) in regions.iter() {
+            sums[i].0 += moisture.get(c).cloned().unwrap_or(0.0);
+            sums[i].1 += 1;
+        }
+        let region_moisture : Vec<f32> = sums.iter()
+            .map(|&(sum, count)| if count == 0 { 0.0 } else { sum / count as f32 })
+            .collect();
+
+        let biomes = regions.map(|c, &i| classify(heights[c], region_moisture[i]));
+
+        let mut adjacency = BTreeSet::new();
+        for (c, &i) in regions.iter() {
+            for &n in c.neighbors().iter() {
+                if let Some(&j) = regions.get(n) {
+                    if i < j {
+                        adjacency.insert((i, j));
+                    }
+                }
+            }
+        }
+
+        Biomes {
+            biomes,
+            regions,
+            moisture: region_moisture,
+            adjacency,
+        }
+    }
+}
+
+/// Blob generator for organic areas: lakes, forests, spell areas
+pub mod blob {
+    use hex2d;
+    use hex2d::Coordinate;
+
+    use rand::Rng;
+
+    use std::hash;
+
+    use hashing::OutputHashSet;
+    use algo::bfs;
+
+    /// Generator configuration
+    #[derive(Clone, Debug)]
+    pub struct Config {
+        /// Number of Coordinates in the blob
+        pub size : usize,
+        /// How compact the blob is, in `[0.0, 1.0]` range
+        ///
+        /// `1.0` gives a perfect hexagon (as far as `can_grow` allows), smaller values more
+        /// ragged shapes.
+        pub compactness : f64,
+    }
+
+    impl Default for Config {
+        fn default() -> Config {
+            Config {
+                size: 20,
+                compactness: 0.5,
+            }
+        }
+    }
+
+    /// Grow a single connected blob from `center`
+    ///
+    /// Candidates are discovered with BFS over Coordinates for which `can_grow` returns true.
+    /// With every step, the blob takes one of the closest candidates touching it; the lower
+    /// the `compactness`, the more candidates there are to choose from. Returns fewer than
+    /// `size` Coordinates only if the blob can't grow any further.
+    pub fn generate<I, FCanGrow, R>(center : Coordinate<I>, can_grow : FCanGrow, config : &Config, rng : &mut R) -> OutputHashSet<Coordinate<I>> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : ::std::ops::AddAssign,
+        FCanGrow : Fn(Coordinate<I>) -> bool,
+        R : Rng
+    {
+        let mut blob = OutputHashSet::default();
+
+        if config.size == 0 || !can_grow(center) {
+            return blob;
+        }
+
+        let mut traverser = bfs::Traverser::new(&can_grow, &can_grow, center);
+        let window = 1 + ((1.0 - config.compactness.clamp(0.0, 1.0)) * config.size as f64) as usize;
+
+        // Candidates in BFS order
+        let mut pending = vec!();
+        let mut exhausted = false;
+        // Candidates further than that can't touch the blob
+        let mut reach = 0;
+
+        while blob.len() < config.size {
+            let touching = |c : &Coordinate<I>, blob : &OutputHashSet<Coordinate<I>>|
+                blob.is_empty() || c.neighbors().iter().any(|n| blob.contains(n));
+
+            let mut eligible : Vec<_> = (0..pending.len())
+                .filter(|&i| touching(&pending[i], &blob))
+                .take(window)
+                .collect();
+
+            while eligible.len() < window && !exhausted {
+                if pending.last().map(|&c| traverser.distance(c).unwrap() > reach).unwrap_or(false) {
+                    break;
+                }
+
+                match traverser.find() {
+                    Some(c) => {
+                        pending.push(c);
+                        if touching(&c, &blob) {
+                            eligible.push(pending.len() - 1);
+                        }
+                    },
+                    None => exhausted = true,
+                }
+            }
+
+            if eligible.is_empty() {
+                break;
+            }
+
+            let i = eligible[rng.gen_range(0..eligible.len())];
+            let c = pending.remove(i);
+            reach = reach.max(traverser.distance(c).unwrap() + 1);
+            blob.insert(c);
+        }
+
+        blob
+    }
+}
+
+/// Hand-authored rooms (prefabs, vaults) stamped into maps
+///
+/// Prefabs are usually drawn as ASCII layouts (see `interop::ascii`), with letters and digits
+/// marking connection points, eg. doors to hook corridors up to.
+pub mod prefab {
+    use hex2d;
+    use hex2d::Coordinate;
+
+    use hashing::OutputHashMap;
+    use geom::Transform;
+    use interop::ascii;
+    use map::HexMap;
+
+    /// Room template with labeled connection points
+    ///
+    /// Coordinates are relative to the template origin, which stays in place when the
+    /// template is rotated or mirrored.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct Prefab<T, I = i32> where
+        I : hex2d::Integer
+    {
+        tiles : HexMap<T, I>,
+        connections : OutputHashMap<char, Coordinate<I>>,
+    }
+
+    impl Prefab<char> {
+        /// Parse a prefab from an ASCII layout
+        ///
+        /// Markers become connection points, with a floor (`.`) under them. See
+        /// `interop::ascii::parse` for the format, and the panics.
+        pub fn from_ascii(layout : &str) -> Prefab<char> {
+            let (tiles, connections) = ascii::parse(layout);
+            Prefab::new(tiles, connections)
+        }
+    }
+
+    impl<T, I> Prefab<T, I> where
+        I : hex2d::Integer
+    {
+        /// Create a prefab of `tiles` with `connections`
+        pub fn new(tiles : HexMap<T, I>, connections : OutputHashMap<char, Coordinate<I>>) -> Prefab<T, I> {
+            Prefab { tiles, connections }
+        }
+
+        /// Tiles of the template
+        pub fn tiles(&self) -> &HexMap<T, I> {
+            &self.tiles
+        }
+
+        /// Connection points of the template, by label
+        pub fn connections(&self) -> &OutputHashMap<char, Coordinate<I>> {
+            &self.connections
+        }
+
+        /// Coordinates covered by the prefab placed at `origin` with `transform`
+        pub fn footprint(&self, origin : Coordinate<I>, transform : Transform) -> impl Iterator<Item = Coordinate<I>> + '_ {
+            self.tiles.iter().map(move |(c, _)| origin + transform.apply(c))
+        }
+
+        /// Copy the prefab into `map`, with its origin at `origin`, oriented by `transform`
+        ///
+        /// Returns world Coordinates of the connection points, by label. Returns `None` and
+        /// leaves `map` untouched if the prefab doesn't fit within the map.
+        pub fn stamp(&self, map : &mut HexMap<T, I>, origin : Coordinate<I>, transform : Transform) -> Option<OutputHashMap<char, Coordinate<I>>> where
+            T : Clone
+        {
+            if !self.footprint(origin, transform).all(|c| map.contains(c)) {
+                return None;
+            }
+
+            for (c, tile) in self.tiles.iter() {
+                map[origin + transform.apply(c)] = tile.clone();
+            }
+            Some(self.connections.iter().map(|(&label, &c)| (label, origin + transform.apply(c))).collect())
+        }
+    }
+}
+
+/// Rotational symmetry for fair multiplayer maps
+pub mod symmetry {
+    use hex2d;
+    use hex2d::{Angle, Coordinate};
+
+    use std::hash;
+
+    use algo::bfs;
+    use map::HexMap;
+
+    /// Rotation between consecutive copies for `folds`-fold symmetry
+    fn step(folds : u32) -> Angle {
+        assert!(folds > 0 && 6 % folds == 0, "symmetry: {}-fold symmetry is not possible on a hex grid", folds);
+        Angle::from_int(6 / folds as i8)
+    }
+
+    /// All `folds` rotations of `c` around `center`, starting with `c` itself
+    ///
+    /// Eg. spawn points of all players from the one of the first player.
+    ///
+    /// Panics if `folds` is not 1, 2, 3 or 6.
+    pub fn orbit<I>(c : Coordinate<I>, center : Coordinate<I>, folds : u32) -> Vec<Coordinate<I>> where
+        I : hex2d::Integer
+    {
+        let step = step(folds);
+        let mut orbit = vec!(c);
+        for _ in 1..folds {
+            let last = *orbit.last().unwrap();
+            orbit.push(last.rotate_around(center, step));
+        }
+        orbit
+    }
+
+    /// Make `map` `folds`-fold rotationally symmetric around `center`
+    ///
+    /// Every set of Coordinates that rotate into each other takes the tile of one of them,
+    /// the same for all the set, so each of the `folds` sectors around `center` ends up a copy
+    /// of the first one. Best used on maps with symmetric bounds, eg. a hexagon around
+    /// `center`; Coordinates that rotate outside of the map keep their tiles.
+    ///
+    /// Panics if `folds` is not 1, 2, 3 or 6.
+    pub fn symmetrize<T, I>(map : &mut HexMap<T, I>, center : Coordinate<I>, folds : u32) where
+        T : Clone,
+        I : hex2d::Integer
+    {
+        let sector = |c : Coordinate<I>| (c - center).direction_from_center_cw().map_or(0, |d| d.to_int::<i8>());
+        let coords : Vec<_> = map.iter().map(|(c, _)| c).collect();
+        for c in coords {
+            let source = orbit(c, center, folds).into_iter()
+                .filter(|&o| map.contains(o))
+                .min_by_key(|&o| (sector(o), o))
+                .unwrap();
+            if source != c {
+                map[c] = map[source].clone();
+            }
+        }
+    }
+
+    /// Are all `spawns` the same walk distance away from `goal`
+    ///
+    /// Verifies that a symmetric map is actually fair, eg. that no spawn got cut off. Uses
+    /// `bfs` through Coordinates for which `can_pass` returns true; unreachable spawns make
+    /// it false. Bound the map with `can_pass`, or the search never ends in that case.
+    pub fn equidistant<FCanPass, I>(spawns : &[Coordinate<I>], goal : Coordinate<I>, can_pass : FCanPass) -> bool where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : ::std::ops::AddAssign,
+        FCanPass : FnMut(Coordinate<I>) -> bool
+    {
+        let mut traverser = bfs::Traverser::new(can_pass, |c| spawns.contains(&c), goal);
+        let mut found = 0;
+        let mut distance = None;
+        while found < spawns.len() {
+            match traverser.find() {
+                Some(c) => {
+                    let d = traverser.distance(c);
+                    if distance.is_some_and(|distance| Some(distance) != d) {
+                        return false;
+                    }
+                    distance = d;
+                    found += spawns.iter().filter(|&&s| s == c).count();
+                },
+                None => return false,
+            }
+        }
+        true
+    }
+}
+
+/// Validation of generated maps
+///
+/// Generators are random, and every now and then produce something unplayable. `check` a
+/// candidate map, and generate another one if it fails; `retry` does it automatically.
+pub mod validate {
+    use hex2d;
+    use hex2d::Coordinate;
+
+    use std::hash;
+    use std::collections::HashSet;
+
+    use hashing::InternalHashSet;
+    use algo::bfs;
+    use geom::Bounds;
+
+    /// Validation thresholds
+    #[derive(Clone, Debug)]
+    pub struct Config {
+        /// Minimum fraction of `bounds` that has to be open
+        pub min_open_ratio : f32,
+        /// Maximum length of a corridor
+        ///
+        /// Corridor is a run of open Coordinates with exactly two open neighbors each.
+        pub max_corridor : usize,
+    }
+
+    impl Default for Config {
+        fn default() -> Config {
+            Config {
+                min_open_ratio: 0.3,
+                max_corridor: 20,
+            }
+        }
+    }
+
+    /// Failed check
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub enum Problem {
+        /// Some spawns can't be reached from the first one
+        DisconnectedSpawns,
+        /// Open area is smaller than `Config::min_open_ratio`
+        TooLittleOpen,
+        /// Some items can't be reached from the first spawn
+        UnreachableItems,
+        /// A corridor is longer than `Config::max_corridor`
+        LongCorridor,
+    }
+
+    /// Result of validation
+    #[derive(Clone, Debug, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct Report<I = i32> where
+        I : hex2d::Integer
+    {
+        /// Fraction of `bounds` that is open
+        pub open_ratio : f32,
+        /// Spawns that can't be reached from the first one
+        pub disconnected_spawns : Vec<Coordinate<I>>,
+        /// Items that can't be reached from the first spawn
+        pub unreachable_items : Vec<Coordinate<I>>,
+        /// Length of the longest corridor
+        pub longest_corridor : usize,
+        /// Failed checks; empty if the map is valid
+        pub problems : Vec<Problem>,
+    }
+
+    impl<I> Report<I> where
+        I : hex2d::Integer
+    {
+        /// Did the map pass all the checks
+        pub fn is_valid(&self) -> bool {
+            self.problems.is_empty()
+        }
+    }
+
+    /// Validate a map with `open` Coordinates within `bounds`
+    ///
+    /// Spawns and items are connected through `open` Coordinates inside of `bounds`. Spawns and
+    /// items that are not open themselves are never reachable. Connectivity is not checked
+    /// if `spawns` are empty.
+    pub fn check<I, S>(
+        bounds : Bounds<I>,
+        open : &HashSet<Coordinate<I>, S>,
+        spawns : &[Coordinate<I>],
+        items : &[Coordinate<I>],
+        config : &Config
+        ) -> Report<I> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : ::std::ops::AddAssign,
+        S : hash::BuildHasher
+    {
+        let is_open = |c : Coordinate<I>| bounds.contains(c) && open.contains(&c);
+
+        let open_count = bounds.iter().filter(|&c| open.contains(&c)).count();
+        let open_ratio = if bounds.is_empty() { 0.0 } else { open_count as f32 / bounds.len() as f32 };
+
+        let (disconnected_spawns, unreachable_items) = match spawns.first() {
+            Some(&first) if is_open(first) => {
+                let mut traverser = bfs::Traverser::new(is_open, |_| false, first);
+                while traverser.find().is_some() {}
+                let unreachable = |cs : &[Coordinate<I>]| -> Vec<_> {
+                    cs.iter().cloned().filter(|&c| !is_open(c) || traverser.distance(c).is_none()).collect()
+                };
+                (unreachable(spawns), unreachable(items))
+            },
+            Some(_) => (spawns.to_vec(), items.to_vec()),
+            None => (vec!(), vec!()),
+        };
+
+        let longest_corridor = longest_corridor(&is_open, bounds);
+
+        let mut problems = vec!();
+        if !disconnected_spawns.is_empty() {
+            problems.push(Problem::DisconnectedSpawns);
+        }
+        if open_ratio < config.min_open_ratio {
+            problems.push(Problem::TooLittleOpen);
+        }
+        if !unreachable_items.is_empty() {
+            problems.push(Problem::UnreachableItems);
+        }
+        if longest_corridor > config.max_corridor {
+            problems.push(Problem::LongCorridor);
+        }
+
+        Report {
+            open_ratio,
+            disconnected_spawns,
+            unreachable_items,
+            longest_corridor,
+            problems,
+        }
+    }
+
+    /// Length of the longest run of open Coordinates with exactly two open neighbors
+    fn longest_corridor<I, FOpen>(is_open : &FOpen, bounds : Bounds<I>) -> usize where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : ::std::ops::AddAssign,
+        FOpen : Fn(Coordinate<I>) -> bool
+    {
+        let is_corridor = |c : Coordinate<I>|
+            is_open(c) && c.neighbors().iter().filter(|&&n| is_open(n)).count() == 2;
+
+        let mut seen = InternalHashSet::default();
+        let mut longest = 0;
+        for c in bounds.iter() {
+            if seen.contains(&c) || !is_corridor(c) {
+                continue;
+            }
+            let mut traverser = bfs::Traverser::new(is_corridor, is_corridor, c);
+            let mut len = 0;
+            while let Some(c) = traverser.find() {
+                seen.insert(c);
+                len += 1;
+            }
+            longest = longest.max(len);
+        }
+        longest
+    }
+
+    /// Generate maps until one passes validation
+    ///
+    /// Calls `generate` up to `attempts` times and returns the first map for which `check`
+    /// gives a valid `Report`. If none does, returns the `Report` of the last one.
+    ///
+    /// Panics if `attempts` is `0`.
+    pub fn retry<T, I, FGenerate, FCheck>(attempts : u32, mut generate : FGenerate, mut check : FCheck) -> Result<T, Report<I>> where
+        I : hex2d::Integer,
+        FGenerate : FnMut() -> T,
+        FCheck : FnMut(&T) -> Report<I>
+    {
+        assert!(attempts > 0, "validate: at least one attempt is needed");
+        let mut last = None;
+        for _ in 0..attempts {
+            let map = generate();
+            let report = check(&map);
+            if report.is_valid() {
+                return Ok(map);
+            }
+            last = Some(report);
+        }
+        Err(last.unwrap())
+    }
+}
+
+/// Connecting disconnected regions of generated maps
+pub mod connect {
+    use hex2d;
+    use hex2d::Coordinate;
+
+    use std::hash;
+
+    use hashing::{InternalHashMap, InternalHashSet};
+    use algo::{bfs, dijkstra};
+    use map::HexMap;
+
+    /// Connected regions of passable tiles of `map`, biggest first
+    ///
+    /// Regions of the same size are ordered by their first Coordinate in `map` order.
+    pub fn regions<T, I, FCanPass>(map : &HexMap<T, I>, can_pass : FCanPass) -> Vec<Vec<Coordinate<I>>> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : ::std::ops::AddAssign,
+        FCanPass : Fn(&T) -> bool
+    {
+        let is_open = |c| map.get(c).is_some_and(&can_pass);
+        let mut assigned = InternalHashSet::default();
+        let mut regions = vec!();
+        for (c, _) in map.iter() {
+            if assigned.contains(&c) || !is_open(c) {
+                continue;
+            }
+            let mut traverser = bfs::Traverser::new(is_open, is_open, c);
+            let mut region = vec!();
+            while let Some(c) = traverser.find() {
+                region.push(c);
+            }
+            assigned.extend(region.iter().cloned());
+            regions.push(region);
+        }
+        regions.sort_by_key(|r| ::std::cmp::Reverse(r.len()));
+        regions
+    }
+
+    /// Carve corridors until all passable tiles of `map` are connected
+    ///
+    /// Starting with the biggest region, every other region still disconnected from it gets
+    /// joined along the cheapest path between them, where walking through passable tiles costs
+    /// `1` and digging through a wall `dig_cost`. Walls on the path are turned passable with
+    /// `carve`. Returns the carved Coordinates, in order of carving.
+    pub fn connect<T, I, FCanPass, FCarve>(map : &mut HexMap<T, I>, dig_cost : u32, can_pass : FCanPass, mut carve : FCarve) -> Vec<Coordinate<I>> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : ::std::ops::AddAssign,
+        FCanPass : Fn(&T) -> bool,
+        FCarve : FnMut(&mut T)
+    {
+        let regions = regions(map, &can_pass);
+        let region_of : InternalHashMap<_, _> = regions.iter().enumerate()
+            .flat_map(|(i, r)| r.iter().map(move |&c| (c, i)))
+            .collect();
+
+        let mut connected : InternalHashSet<_> = regions.first().into_iter().flatten().cloned().collect();
+        let mut joined : InternalHashSet<_> = vec!(0).into_iter().collect();
+        let mut carved = vec!();
+
+        for (i, region) in regions.iter().enumerate().skip(1) {
+            if joined.contains(&i) {
+                continue;
+            }
+
+            let path = {
+                let map = &*map;
+                let own = |c : &Coordinate<I>| region_of.get(c) == Some(&i);
+                let cost = |_, to : Coordinate<I>| match map.get(to) {
+                    None => None,
+                    Some(_) if own(&to) => Some(0),
+                    Some(t) if can_pass(t) => Some(1),
+                    Some(_) => Some(dig_cost),
+                };
+                let mut traverser = dijkstra::Traverser::new(cost, |c| connected.contains(&c), region[0]);
+                let dest = traverser.find().expect("connect: all tiles of a map are reachable by digging");
+                traverser.path(dest).unwrap()
+            };
+
+            for c in path {
+                if !can_pass(&map[c]) {
+                    carve(&mut map[c]);
+                    carved.push(c);
+                    connected.insert(c);
+                } else if let Some(&j) = region_of.get(&c) {
+                    if joined.insert(j) {
+                        connected.extend(regions[j].iter().cloned());
+                    }
+                }
+            }
+        }
+
+        carved
+    }
+}
+
+/// Spawn point placement
+pub mod spawn {
+    use hex2d;
+    use hex2d::Coordinate;
+
+    use rand::Rng;
+    use rand::seq::SliceRandom;
+
+    use std::hash;
+
+    use algo::{bfs, metrics};
+    use geom::Bounds;
+    use map::HexMap;
+
+    /// Placement configuration
+    #[derive(Clone, Debug)]
+    pub struct Config {
+        /// Number of spawns to place
+        pub count : usize,
+        /// Minimum walk distance between every two spawns
+        pub min_distance : u32,
+        /// Minimum distance from every hazard
+        pub hazard_distance : u32,
+        /// Radius around a spawn in which openness is measured
+        pub open_radius : u32,
+        /// Minimum number of passable Coordinates within `open_radius` of a spawn
+        pub min_open : usize,
+        /// Number of random orders in which candidates are tried
+        pub attempts : u32,
+    }
+
+    impl Default for Config {
+        fn default() -> Config {
+            Config {
+                count: 2,
+                min_distance: 10,
+                hazard_distance: 3,
+                open_radius: 2,
+                min_open: 12,
+                attempts: 10,
+            }
+        }
+    }
+
+    /// Place `config.count` spawns on passable Coordinates of `bounds`
+    ///
+    /// Candidates are Coordinates at least `config.hazard_distance` away from all `hazards`,
+    /// with enough passable Coordinates around them (see `metrics::openness`). They are tried
+    /// in random order, and one is taken if its walk distance through passable Coordinates of
+    /// `bounds` to every spawn taken before is at least `config.min_distance`; spawns are
+    /// always connected with each other. Returns `None` if no order out of `config.attempts`
+    /// works.
+    pub fn place<I, FCanPass, R>(
+        bounds : Bounds<I>,
+        can_pass : FCanPass,
+        hazards : &[Coordinate<I>],
+        config : &Config,
+        rng : &mut R
+        ) -> Option<Vec<Coordinate<I>>> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        I : ::std::ops::AddAssign,
+        FCanPass : Fn(Coordinate<I>) -> bool,
+        R : Rng
+    {
+        let is_open = |c| bounds.contains(c) && can_pass(c);
+        let openness = metrics::openness(&can_pass, bounds, I::from_u32(config.open_radius).unwrap());
+        let hazard_distance = I::from_u32(config.hazard_distance).unwrap();
+
+        let mut candidates : Vec<_> = bounds.iter()
+            .filter(|&c| is_open(c) && openness[c] >= config.min_open as f32)
+            .filter(|&c| hazards.iter().all(|&h| c.distance(h) >= hazard_distance))
+            .collect();
+
+        for _ in 0..config.attempts {
+            candidates.shuffle(rng);
+
+            let mut spawns = vec!();
+            let mut distances : Vec<HexMap<Option<u32>, I>> = vec!();
+            for &c in &candidates {
+                if spawns.len() == config.count {
+                    break;
+                }
+                if !distances.iter().all(|d| d[c].is_some_and(|d| d >= config.min_distance)) {
+                    continue;
+                }
+
+                let mut walk = HexMap::new(bounds, None);
+                let mut traverser = bfs::Traverser::new(is_open, is_open, c);
+                while let Some(n) = traverser.find() {
+                    walk[n] = traverser.distance(n);
+                }
+                spawns.push(c);
+                distances.push(walk);
+            }
+
+            if spawns.len() == config.count {
+                return Some(spawns);
+            }
+        }
+        None
+    }
+}
+
+/// Item and monster distribution
+///
+/// Things are scattered by a desirability field: a `HexMap<f32>` built by the caller, eg.
+/// from walk distance to the entrance (treasure far away) or the number of walls around
+/// (monsters in dead ends).
+pub mod scatter {
+    use hex2d;
+    use hex2d::Coordinate;
+
+    use rand::Rng;
+
+    use map::HexMap;
+
+    /// Category of things to place
+    #[derive(Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct Category {
+        /// Number of things to place
+        pub count : usize,
+        /// Nothing else is placed within this distance of a thing of this category
+        pub exclusion : u32,
+    }
+
+    /// Place things of all `categories` on Coordinates of `desirability`
+    ///
+    /// Categories are placed in order, one thing at a time. Every Coordinate is picked with
+    /// chance proportional to its desirability; ones with desirability `0.0` or less never
+    /// are. A placed thing takes its Coordinate and the ones within its category's `exclusion`
+    /// radius away from all things placed after it.
+    ///
+    /// Returns the placed Coordinates of every category. Categories placed when there was no
+    /// room left get fewer than `count`.
+    pub fn scatter<I, R>(desirability : &HexMap<f32, I>, categories : &[Category], rng : &mut R) -> Vec<Vec<Coordinate<I>>> where
+        I : hex2d::Integer,
+        R : Rng
+    {
+        let mut candidates : Vec<_> = desirability.iter()
+            .filter(|&(_, &w)| w > 0.0)
+            .map(|(c, &w)| (c, w))
+            .collect();
+
+        categories.iter().map(|category| {
+            let exclusion = I::from_u32(category.exclusion).unwrap();
+            let mut placed = vec!();
+            while placed.len() < category.count && !candidates.is_empty() {
+                let total : f32 = candidates.iter().map(|&(_, w)| w).sum();
+                let mut x = rng.gen::<f32>() * total;
+                let mut pick = candidates.last().unwrap().0;
+                for &(c, w) in candidates.iter() {
+                    x -= w;
+                    if x <= 0.0 {
+                        pick = c;
+                        break;
+                    }
+                }
+
+                candidates.retain(|&(c, _)| c.distance(pick) > exclusion);
+                placed.push(pick);
+            }
+            placed
+        }).collect()
+    }
+}
+
+/// Dead-end detection and pruning
+///
+/// Dead end is a corridor leading nowhere: a chain of passable tiles starting at one with
+/// a single passable neighbor.
+pub mod dead_ends {
+    use hex2d;
+    use hex2d::Coordinate;
+
+    use std::hash;
+
+    use hashing::InternalHashSet;
+    use map::HexMap;
+
+    /// Passable neighbors of `c`
+    fn open_neighbors<I>(open : &InternalHashSet<Coordinate<I>>, c : Coordinate<I>) -> Vec<Coordinate<I>> where
+        I : hex2d::Integer,
+        I : hash::Hash
+    {
+        c.neighbors().iter().cloned().filter(|n| open.contains(n)).collect()
+    }
+
+    /// All dead ends of `map`
+    ///
+    /// Every dead end runs from its tip inwards, through tiles with exactly two passable
+    /// neighbors, and stops before the tile where it joins a wider area. A corridor with
+    /// both ends dead is listed once, whole.
+    pub fn find<T, I, FCanPass>(map : &HexMap<T, I>, can_pass : FCanPass) -> Vec<Vec<Coordinate<I>>> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        FCanPass : Fn(&T) -> bool
+    {
+        let open : InternalHashSet<_> = map.iter().filter(|&(_, t)| can_pass(t)).map(|(c, _)| c).collect();
+
+        let mut seen = InternalHashSet::default();
+        let mut res = vec!();
+        for (tip, _) in map.iter() {
+            if seen.contains(&tip) || !open.contains(&tip) || open_neighbors(&open, tip).len() != 1 {
+                continue;
+            }
+
+            let mut chain = vec!(tip);
+            let mut prev = tip;
+            let mut cur = open_neighbors(&open, tip)[0];
+            loop {
+                let neighbors = open_neighbors(&open, cur);
+                match neighbors.len() {
+                    1 => {
+                        chain.push(cur);
+                        break;
+                    },
+                    2 => {
+                        chain.push(cur);
+                        let next = if neighbors[0] == prev { neighbors[1] } else { neighbors[0] };
+                        prev = cur;
+                        cur = next;
+                    },
+                    _ => break,
+                }
+            }
+
+            seen.extend(chain.iter().cloned());
+            res.push(chain);
+        }
+        res
+    }
+
+    /// Fill dead ends of `map` in, up to `depth` tiles deep
+    ///
+    /// In each of `depth` rounds, all tips of dead ends are turned impassable with `fill`, so
+    /// dead ends get shorter by one tile per round and the ones shorter than `depth` disappear.
+    /// Returns the filled Coordinates, in order of filling.
+    pub fn prune<T, I, FCanPass, FFill>(map : &mut HexMap<T, I>, depth : u32, can_pass : FCanPass, mut fill : FFill) -> Vec<Coordinate<I>> where
+        I : hex2d::Integer,
+        I : hash::Hash,
+        FCanPass : Fn(&T) -> bool,
+        FFill : FnMut(&mut T)
+    {
+        let mut open : InternalHashSet<_> = map.iter().filter(|&(_, t)| can_pass(t)).map(|(c, _)| c).collect();
+
+        let mut filled = vec!();
+        for _ in 0..depth {
+            let tips : Vec<_> = map.iter()
+                .map(|(c, _)| c)
+                .filter(|&c| open.contains(&c) && open_neighbors(&open, c).len() == 1)
+                .collect();
+            if tips.is_empty() {
+                break;
+            }
+
+            for c in tips {
+                open.remove(&c);
+                fill(&mut map[c]);
+                filled.push(c);
+            }
+        }
+        filled
+    }
+}
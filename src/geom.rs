@@ -0,0 +1,776 @@
+// Copyright 2014 Dawid Ciężarkiewicz
+// See LICENSE file for more information
+
+use hex2d;
+use hex2d::Angle;
+use hex2d::Angle::{Left,Right};
+use hex2d::Coordinate;
+use hex2d::Direction;
+
+use std::cmp;
+use std::hash;
+use std::hash::BuildHasher;
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use hashing::OutputHashSet;
+
+/// Grow `set` by one step: add all neighbors of its members
+pub fn dilate<I, S>(set : &HashSet<Coordinate<I>, S>) -> HashSet<Coordinate<I>, S> where
+    I : hex2d::Integer,
+    I : hash::Hash,
+    S : BuildHasher + Clone
+{
+    let mut res = set.clone();
+    for c in set.iter() {
+        res.extend(c.neighbors().iter().cloned());
+    }
+    res
+}
+
+/// Shrink `set` by one step: keep only members with all neighbors in `set`
+pub fn erode<I, S>(set : &HashSet<Coordinate<I>, S>) -> HashSet<Coordinate<I>, S> where
+    I : hex2d::Integer,
+    I : hash::Hash,
+    S : BuildHasher + Default
+{
+    set.iter()
+        .filter(|c| c.neighbors().iter().all(|n| set.contains(n)))
+        .cloned()
+        .collect()
+}
+
+/// Morphological opening: `erode` followed by `dilate`
+///
+/// Removes thin protrusions and members that are too narrow.
+pub fn open<I, S>(set : &HashSet<Coordinate<I>, S>) -> HashSet<Coordinate<I>, S> where
+    I : hex2d::Integer,
+    I : hash::Hash,
+    S : BuildHasher + Clone + Default
+{
+    dilate(&erode(set))
+}
+
+/// Morphological closing: `dilate` followed by `erode`
+///
+/// Fills small holes and narrow gaps.
+pub fn close<I, S>(set : &HashSet<Coordinate<I>, S>) -> HashSet<Coordinate<I>, S> where
+    I : hex2d::Integer,
+    I : hash::Hash,
+    S : BuildHasher + Clone + Default
+{
+    erode(&dilate(set))
+}
+
+/// Boundary of `set`: members paired with the Direction of their outward-facing edges
+///
+/// Edges are returned in order, walking around the outline edge after edge, so they can be
+/// directly used to draw it. A set with holes or disconnected parts has multiple outlines;
+/// each of them is listed whole before the next one starts.
+pub fn perimeter<I, S>(set : &HashSet<Coordinate<I>, S>) -> Vec<(Coordinate<I>, Direction)> where
+    I : hex2d::Integer,
+    I : hash::Hash,
+    S : BuildHasher
+{
+    let mut edges : Vec<_> = set.iter()
+        .flat_map(|&c| Direction::all().iter()
+                  .filter(move |&&d| !set.contains(&(c + d)))
+                  .map(move |&d| (c, d)))
+        .collect();
+    // Sort, so the walk starts in the same place every time
+    edges.sort();
+
+    let mut walked = HashSet::with_capacity(edges.len());
+    let mut res = Vec::with_capacity(edges.len());
+
+    for &start in edges.iter() {
+        if walked.contains(&start) {
+            continue;
+        }
+
+        let mut edge = start;
+        loop {
+            walked.insert(edge);
+            res.push(edge);
+
+            // Next edge shares the clockwise corner of the current one: it's either
+            // the next edge of the same member, or an edge of the member beyond that corner
+            let (c, d) = edge;
+            let n = c + (d + Right);
+            edge = if set.contains(&n) {
+                (n, d + Left)
+            } else {
+                (c, d + Right)
+            };
+
+            if edge == start {
+                break;
+            }
+        }
+    }
+
+    res
+}
+
+/// Coordinates within range of all `(center, range)` pairs of `ranges`
+///
+/// Eg. tiles within 3 of the caster and 2 of the target, for "valid summon location" queries.
+/// Empty if `ranges` is. See `algo::bfs::walk_ranges_intersection` for one that respects
+/// obstacles.
+pub fn ranges_intersection<I>(ranges : &[(Coordinate<I>, I)]) -> OutputHashSet<Coordinate<I>> where
+    I : hex2d::Integer,
+    I : hash::Hash,
+    I : ::std::ops::AddAssign
+{
+    let smallest = match ranges.iter().min_by_key(|&&(_, r)| r) {
+        Some(&smallest) => smallest,
+        None => return OutputHashSet::default(),
+    };
+    smallest.0.range_iter(smallest.1)
+        .filter(|&c| ranges.iter().all(|&(center, r)| center.distance(c) <= r))
+        .collect()
+}
+
+/// Mirroring axis
+///
+/// Mirroring across an axis keeps the respective cube coordinate and swaps the other two.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Axis {
+    /// Keep `x`, swap `y` and `z`
+    X,
+    /// Keep `y`, swap `x` and `z`
+    Y,
+    /// Keep `z`, swap `x` and `y`
+    Z,
+}
+
+/// Mirror `c` across `axis` going through `(0, 0)`
+pub fn mirror<I>(c : Coordinate<I>, axis : Axis) -> Coordinate<I> where
+    I : hex2d::Integer
+{
+    match axis {
+        Axis::X => Coordinate::new(c.x, c.z()),
+        Axis::Y => Coordinate::new(c.z(), c.y),
+        Axis::Z => Coordinate::new(c.y, c.x),
+    }
+}
+
+/// Orientation change of a pattern of relative Coordinates
+///
+/// Mirroring (if any) is applied first, then rotation around `(0, 0)`. Useful for AoE
+/// templates, prefab rooms and unit footprints that are defined once and oriented at use time.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Transform {
+    /// Axis to mirror across
+    pub mirror : Option<Axis>,
+    /// Rotation to apply
+    pub rotation : Angle,
+}
+
+impl Transform {
+    /// Rotation by `rotation`, without mirroring
+    pub fn new(rotation : Angle) -> Transform {
+        Transform {
+            mirror: None,
+            rotation,
+        }
+    }
+
+    /// Rotation turning a pattern defined facing `from` to face `to`
+    pub fn facing(from : Direction, to : Direction) -> Transform {
+        Transform::new(to - from)
+    }
+
+    /// Same transformation, but mirrored across `axis` first
+    pub fn mirrored(self, axis : Axis) -> Transform {
+        Transform {
+            mirror: Some(axis),
+            rotation: self.rotation,
+        }
+    }
+
+    /// All 12 distinct transformations: 6 rotations, with and without mirroring
+    pub fn all() -> Vec<Transform> {
+        let rotations = Angle::all().iter().map(|&a| Transform::new(a));
+        let mirrored = Angle::all().iter().map(|&a| Transform::new(a).mirrored(Axis::X));
+        rotations.chain(mirrored).collect()
+    }
+
+    /// Transform a single relative Coordinate
+    pub fn apply<I>(&self, c : Coordinate<I>) -> Coordinate<I> where
+        I : hex2d::Integer
+    {
+        let c = match self.mirror {
+            Some(axis) => mirror(c, axis),
+            None => c,
+        };
+        c.rotate_around_zero(self.rotation)
+    }
+
+    /// Transform a Direction
+    pub fn apply_dir(&self, dir : Direction) -> Direction {
+        let c : Coordinate<i8> = self.apply(Coordinate::from(dir));
+        Coordinate::new(0, 0).direction_to_cw(c).expect("Transform: direction can't become zero")
+    }
+
+    /// Transform all Coordinates of a `pattern`
+    pub fn apply_all<I>(&self, pattern : &[Coordinate<I>]) -> Vec<Coordinate<I>> where
+        I : hex2d::Integer
+    {
+        pattern.iter().map(|&c| self.apply(c)).collect()
+    }
+}
+
+/// Finite region of the map
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Bounds<I = i32> where
+    I : hex2d::Integer
+{
+    /// All Coordinates within `radius` from `center`
+    Hexagon {
+        /// Center of the hexagon
+        center : Coordinate<I>,
+        /// Radius of the hexagon
+        radius : I,
+    },
+    /// `height` rows of `width` Coordinates each, starting at `origin`
+    ///
+    /// Rows go along `z` axis and are shifted, so they form a rectangle on a pointy-top
+    /// layout.
+    Rectangle {
+        /// First Coordinate of the first row
+        origin : Coordinate<I>,
+        /// Number of Coordinates in a row
+        width : I,
+        /// Number of rows
+        height : I,
+    },
+    /// All Coordinates from `inner` to `outer` (inclusive) steps away from `center`
+    Ring {
+        /// Center of the ring
+        center : Coordinate<I>,
+        /// Distance of the innermost Coordinates
+        inner : I,
+        /// Distance of the outermost Coordinates
+        outer : I,
+    },
+}
+
+impl<I> Bounds<I> where
+    I : hex2d::Integer
+{
+    /// Hexagon of `radius` around `center`
+    pub fn hexagon(center : Coordinate<I>, radius : I) -> Bounds<I> {
+        Bounds::Hexagon { center, radius }
+    }
+
+    /// Rectangle of `width` x `height` starting at `origin`
+    pub fn rectangle(origin : Coordinate<I>, width : I, height : I) -> Bounds<I> {
+        Bounds::Rectangle { origin, width, height }
+    }
+
+    /// Ring of Coordinates from `inner` to `outer` steps away from `center`
+    pub fn ring(center : Coordinate<I>, inner : I, outer : I) -> Bounds<I> {
+        Bounds::Ring { center, inner, outer }
+    }
+
+    /// Hexagon enclosing a ring
+    fn outer(center : Coordinate<I>, outer : I) -> Bounds<I> {
+        Bounds::Hexagon { center, radius: outer }
+    }
+
+    /// Hexagon cut out from the middle of a ring, and its offset in rows
+    fn hole(&self) -> Option<(Bounds<I>, i64)> {
+        match *self {
+            Bounds::Ring { center, inner, outer } if inner > I::zero() => {
+                let offset = (outer - inner).to_i64().unwrap() + 1;
+                Some((Bounds::Hexagon { center, radius: inner - I::one() }, offset))
+            },
+            _ => None,
+        }
+    }
+
+    /// Number of Coordinates of the hole of a ring in rows before the `j`-th row, and before
+    /// `x` in the `j`-th row
+    fn hole_before(&self, j : i64, x : Option<i64>) -> i64 {
+        match self.hole() {
+            Some((hole, offset)) => {
+                let jh = cmp::min(cmp::max(j - offset, 0), hole.rows());
+                let in_row = match x {
+                    Some(x) if jh == j - offset && jh < hole.rows() => {
+                        let (hx, _, hlen) = hole.row(jh);
+                        cmp::min(cmp::max(x - hx, 0), hlen)
+                    },
+                    _ => 0,
+                };
+                hole.row_start(jh) + in_row
+            },
+            None => 0,
+        }
+    }
+
+    /// Number of rows
+    ///
+    /// Regions with negative dimensions have none, so they are empty.
+    fn rows(&self) -> i64 {
+        match *self {
+            Bounds::Hexagon { radius, .. } => cmp::max(2 * radius.to_i64().unwrap() + 1, 0),
+            Bounds::Rectangle { width, .. } if width <= I::zero() => 0,
+            Bounds::Rectangle { height, .. } => cmp::max(height.to_i64().unwrap(), 0),
+            Bounds::Ring { inner, outer, .. } if inner > outer => 0,
+            Bounds::Ring { center, outer, .. } => Bounds::outer(center, outer).rows(),
+        }
+    }
+
+    /// `x` of the first Coordinate, `z`, and length of the `j`-th row
+    fn row(&self, j : i64) -> (i64, i64, i64) {
+        match *self {
+            Bounds::Hexagon { center, radius } => {
+                let r = radius.to_i64().unwrap();
+                let dz = j - r;
+                let x = center.x.to_i64().unwrap() + cmp::max(-r, -dz - r);
+                let z = center.z().to_i64().unwrap() + dz;
+                (x, z, 2 * r + 1 - dz.abs())
+            },
+            Bounds::Rectangle { origin, width, .. } => {
+                let x = origin.x.to_i64().unwrap() - j / 2;
+                let z = origin.z().to_i64().unwrap() + j;
+                (x, z, width.to_i64().unwrap())
+            },
+            Bounds::Ring { center, outer, .. } => Bounds::outer(center, outer).row(j),
+        }
+    }
+
+    /// Number of Coordinates before the `j`-th row
+    fn row_start(&self, j : i64) -> i64 {
+        match *self {
+            Bounds::Hexagon { radius, .. } => {
+                let r = radius.to_i64().unwrap();
+                if j <= r {
+                    j * (r + 1) + j * (j - 1) / 2
+                } else {
+                    let m = j - r;
+                    r * (r + 1) + r * (r - 1) / 2 + m * (2 * r + 1) - m * (m - 1) / 2
+                }
+            },
+            Bounds::Rectangle { width, .. } => j * width.to_i64().unwrap(),
+            Bounds::Ring { center, outer, .. } => {
+                Bounds::outer(center, outer).row_start(j) - self.hole_before(j, None)
+            },
+        }
+    }
+
+    fn first_z(&self) -> i64 {
+        self.row(0).1
+    }
+
+    /// Number of Coordinates in the region
+    ///
+    /// Regions with a negative dimension (radius, width, height) are empty.
+    pub fn len(&self) -> usize {
+        let rows = self.rows();
+        if rows <= 0 {
+            0
+        } else {
+            self.row_start(rows) as usize
+        }
+    }
+
+    /// Is the region empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Position of `c` in the iteration order of the region
+    ///
+    /// Returns a unique index in `[0, len())` range for every Coordinate of the region, and
+    /// `None` for Coordinates outside of it. Useful for storing data about the region in a `Vec`.
+    pub fn index(&self, c : Coordinate<I>) -> Option<usize> {
+        let j = c.z().to_i64().unwrap() - self.first_z();
+        if j < 0 || j >= self.rows() {
+            return None;
+        }
+
+        let (x, _, len) = self.row(j);
+        let cx = c.x.to_i64().unwrap();
+        let i = cx - x;
+        if i < 0 || i >= len {
+            return None;
+        }
+
+        if let Some((hole, _)) = self.hole() {
+            if hole.contains(c) {
+                return None;
+            }
+        }
+        let hole_in_row = self.hole_before(j, Some(cx)) - self.hole_before(j, None);
+
+        Some((self.row_start(j) + i - hole_in_row) as usize)
+    }
+
+    /// Does the region contain `c`
+    pub fn contains(&self, c : Coordinate<I>) -> bool {
+        self.index(c).is_some()
+    }
+
+    /// The same region moved by `offset`
+    ///
+    /// Iteration order is preserved, so `index` of every moved Coordinate stays the same.
+    pub fn translate(&self, offset : Coordinate<I>) -> Bounds<I> {
+        match *self {
+            Bounds::Hexagon { center, radius } => Bounds::Hexagon { center: center + offset, radius },
+            Bounds::Rectangle { origin, width, height } => Bounds::Rectangle { origin: origin + offset, width, height },
+            Bounds::Ring { center, inner, outer } => Bounds::Ring { center: center + offset, inner, outer },
+        }
+    }
+
+    /// Clamp `can_pass` to the region
+    ///
+    /// Searches and flood fills using the returned closure never wander outside of the
+    /// region, no matter how permissive `can_pass` is.
+    pub fn passable<FCanPass>(self, can_pass : FCanPass) -> impl Fn(Coordinate<I>) -> bool where
+        FCanPass : Fn(Coordinate<I>) -> bool
+    {
+        move |c| self.contains(c) && can_pass(c)
+    }
+
+    /// Clamp `opaqueness` to the region, returning `outside` for Coordinates outside of it
+    ///
+    /// With `outside` bigger than the initial light, LoS never leaks outside of the region.
+    pub fn opaqueness<FOpaqueness, L>(self, opaqueness : FOpaqueness, outside : L) -> impl Fn(Coordinate<I>) -> L where
+        FOpaqueness : Fn(Coordinate<I>) -> L,
+        L : Copy
+    {
+        move |c| if self.contains(c) { opaqueness(c) } else { outside }
+    }
+
+    /// Iterator over all Coordinates of the region
+    pub fn iter(&self) -> BoundsIter<I> {
+        BoundsIter {
+            bounds: *self,
+            hole: self.hole().map(|(hole, _)| hole),
+            j: 0,
+            i: 0,
+        }
+    }
+}
+
+/// Iterator over Coordinates of `Bounds`
+#[derive(Clone, Debug)]
+pub struct BoundsIter<I = i32> where
+    I : hex2d::Integer
+{
+    bounds : Bounds<I>,
+    hole : Option<Bounds<I>>,
+    j : i64,
+    i : i64,
+}
+
+impl<I> Iterator for BoundsIter<I> where
+    I : hex2d::Integer
+{
+    type Item = Coordinate<I>;
+
+    fn next(&mut self) -> Option<Coordinate<I>> {
+        loop {
+            if self.j >= self.bounds.rows() {
+                return None;
+            }
+
+            let (x, z, len) = self.bounds.row(self.j);
+            if self.i < len {
+                let x = x + self.i;
+                self.i += 1;
+                let c = Coordinate::new(
+                        I::from_i64(x).unwrap(),
+                        I::from_i64(-x - z).unwrap()
+                        );
+                if self.hole.is_some_and(|hole| hole.contains(c)) {
+                    continue;
+                }
+                return Some(c);
+            }
+
+            self.j += 1;
+            self.i = 0;
+        }
+    }
+}
+
+impl<I> IntoIterator for Bounds<I> where
+    I : hex2d::Integer
+{
+    type Item = Coordinate<I>;
+    type IntoIter = BoundsIter<I>;
+
+    fn into_iter(self) -> BoundsIter<I> {
+        self.iter()
+    }
+}
+
+/// Shape of the world: how Coordinates connect to each other
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Topology<I = i32> where
+    I : hex2d::Integer
+{
+    /// Infinite flat plane
+    #[default]
+    Flat,
+    /// Rows (along `z` axis) wrap around every `period` Coordinates
+    ///
+    /// Matches `Bounds::rectangle(origin, period, height)`: walking east from the last
+    /// Coordinate of a row enters the first one, so an east-west wrapping world map can
+    /// be stored in a `HexMap` over such a rectangle.
+    Cylinder {
+        /// First Coordinate of the first row
+        origin : Coordinate<I>,
+        /// Number of Coordinates in a row
+        period : I,
+    },
+}
+
+impl<I> Topology<I> where
+    I : hex2d::Integer
+{
+    /// Rows wrapping around every `period` Coordinates, starting at `origin`
+    pub fn cylinder(origin : Coordinate<I>, period : I) -> Topology<I> {
+        Topology::Cylinder { origin, period }
+    }
+
+    /// Canonical Coordinate for `c`
+    ///
+    /// All Coordinates denoting the same place map to one of them, that is inside the
+    /// matching `Bounds::rectangle` (if its rows are high enough).
+    pub fn wrap(&self, c : Coordinate<I>) -> Coordinate<I> {
+        match *self {
+            Topology::Flat => c,
+            Topology::Cylinder { origin, period } => {
+                let two = I::one() + I::one();
+                let start = origin.x - (c.z() - origin.z()).div_floor(&two);
+                let x = start + (c.x - start).mod_floor(&period);
+                Coordinate::new(x, -x - c.z())
+            },
+        }
+    }
+
+    /// Canonical neighbors of `c`, in `Direction::all()` order
+    pub fn neighbors(&self, c : Coordinate<I>) -> [Coordinate<I>; 6] {
+        c.neighbors().map(|n| self.wrap(n))
+    }
+
+    /// Number of steps between `a` and `b`, taking the shortest way around
+    pub fn distance(&self, a : Coordinate<I>, b : Coordinate<I>) -> I {
+        match *self {
+            Topology::Flat => a.distance(b),
+            Topology::Cylinder { period, .. } => {
+                let (a, b) = (self.wrap(a), self.wrap(b));
+                let shift = Coordinate::new(period, I::zero() - period);
+                cmp::min(a.distance(b), cmp::min(a.distance(b + shift), a.distance(b - shift)))
+            },
+        }
+    }
+
+    /// Make `can_pass` see only canonical Coordinates
+    pub fn passable<FCanPass>(self, can_pass : FCanPass) -> impl Fn(Coordinate<I>) -> bool where
+        FCanPass : Fn(Coordinate<I>) -> bool
+    {
+        move |c| can_pass(self.wrap(c))
+    }
+
+    /// Make `opaqueness` see only canonical Coordinates
+    ///
+    /// LoS algorithms walk the unwrapped plane, so with this they see across the seam. Wrap
+    /// Coordinates passed to `visible` as well, and keep `light` below half of the period, or
+    /// the LoS will meet itself on the other side.
+    pub fn opaqueness<FOpaqueness, L>(self, opaqueness : FOpaqueness) -> impl Fn(Coordinate<I>) -> L where
+        FOpaqueness : Fn(Coordinate<I>) -> L
+    {
+        move |c| opaqueness(self.wrap(c))
+    }
+}
+
+/// Run-length encoded set of Coordinates
+///
+/// Stores every row (Coordinates of the same `z`) as a sorted list of `x` runs, so large
+/// continuous regions like continents or explored areas take little memory, no matter
+/// how many Coordinates they cover.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RleRegion<I = i32> where
+    I : hex2d::Integer
+{
+    /// Half-open `[start, end)` runs of `x`, by row `z`
+    rows : BTreeMap<i64, Vec<(i64, i64)>>,
+    _coordinate : PhantomData<I>,
+}
+
+/// Is `x` inside one of sorted, disjoint `runs`
+fn run_contains(runs : &[(i64, i64)], x : i64) -> bool {
+    let i = runs.partition_point(|&(_, e)| e <= x);
+    i < runs.len() && runs[i].0 <= x
+}
+
+/// Combine two sorted lists of disjoint runs with a boolean operation
+fn combine_runs<F>(a : &[(i64, i64)], b : &[(i64, i64)], op : F) -> Vec<(i64, i64)> where
+    F : Fn(bool, bool) -> bool
+{
+    let mut points : Vec<i64> = a.iter().chain(b.iter()).flat_map(|&(s, e)| [s, e]).collect();
+    points.sort();
+    points.dedup();
+
+    let mut res : Vec<(i64, i64)> = vec!();
+    for w in points.windows(2) {
+        let (s, e) = (w[0], w[1]);
+        if !op(run_contains(a, s), run_contains(b, s)) {
+            continue;
+        }
+        match res.last_mut() {
+            Some(last) if last.1 == s => last.1 = e,
+            _ => res.push((s, e)),
+        }
+    }
+    res
+}
+
+impl<I> RleRegion<I> where
+    I : hex2d::Integer
+{
+    /// Create an empty region
+    pub fn new() -> RleRegion<I> {
+        RleRegion {
+            rows: BTreeMap::new(),
+            _coordinate: PhantomData,
+        }
+    }
+
+    fn key(c : Coordinate<I>) -> (i64, i64) {
+        (c.z().to_i64().unwrap(), c.x.to_i64().unwrap())
+    }
+
+    /// Number of Coordinates in the region
+    ///
+    /// Regions with a negative dimension (radius, width, height) are empty.
+    pub fn len(&self) -> usize {
+        self.rows.values().flat_map(|runs| runs.iter()).map(|&(s, e)| (e - s) as usize).sum()
+    }
+
+    /// Is the region empty
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// Number of runs used to store the region
+    pub fn runs(&self) -> usize {
+        self.rows.values().map(|runs| runs.len()).sum()
+    }
+
+    /// Does the region contain `c`
+    pub fn contains(&self, c : Coordinate<I>) -> bool {
+        let (z, x) = Self::key(c);
+        self.rows.get(&z).is_some_and(|runs| run_contains(runs, x))
+    }
+
+    fn combine_row<F>(&mut self, z : i64, other : &[(i64, i64)], op : F) where
+        F : Fn(bool, bool) -> bool
+    {
+        let runs = combine_runs(self.rows.get(&z).map_or(&[][..], |r| &r[..]), other, op);
+        if runs.is_empty() {
+            self.rows.remove(&z);
+        } else {
+            self.rows.insert(z, runs);
+        }
+    }
+
+    /// Add `c` to the region
+    pub fn insert(&mut self, c : Coordinate<I>) {
+        let (z, x) = Self::key(c);
+        self.combine_row(z, &[(x, x + 1)], |a, b| a || b);
+    }
+
+    /// Remove `c` from the region
+    pub fn remove(&mut self, c : Coordinate<I>) {
+        let (z, x) = Self::key(c);
+        if self.rows.contains_key(&z) {
+            self.combine_row(z, &[(x, x + 1)], |a, b| a && !b);
+        }
+    }
+
+    fn combine<F>(&self, other : &RleRegion<I>, op : F) -> RleRegion<I> where
+        F : Fn(bool, bool) -> bool + Copy
+    {
+        let mut res = self.clone();
+        let zs : BTreeSet<i64> = self.rows.keys().chain(other.rows.keys()).cloned().collect();
+        for z in zs {
+            res.combine_row(z, other.rows.get(&z).map_or(&[][..], |r| &r[..]), op);
+        }
+        res
+    }
+
+    /// Coordinates in either region
+    pub fn union(&self, other : &RleRegion<I>) -> RleRegion<I> {
+        self.combine(other, |a, b| a || b)
+    }
+
+    /// Coordinates in both regions
+    pub fn intersection(&self, other : &RleRegion<I>) -> RleRegion<I> {
+        self.combine(other, |a, b| a && b)
+    }
+
+    /// Coordinates in `self`, but not in `other`
+    pub fn difference(&self, other : &RleRegion<I>) -> RleRegion<I> {
+        self.combine(other, |a, b| a && !b)
+    }
+
+    /// Iterator over all Coordinates of the region, row by row
+    pub fn iter(&self) -> impl Iterator<Item = Coordinate<I>> + '_ {
+        self.rows.iter().flat_map(|(&z, runs)| {
+            runs.iter().flat_map(move |&(s, e)| (s..e).map(move |x| {
+                Coordinate::new(I::from_i64(x).unwrap(), I::from_i64(-x - z).unwrap())
+            }))
+        })
+    }
+}
+
+impl<I> From<Bounds<I>> for RleRegion<I> where
+    I : hex2d::Integer
+{
+    fn from(bounds : Bounds<I>) -> RleRegion<I> {
+        if let Bounds::Ring { .. } = bounds {
+            return bounds.iter().collect();
+        }
+
+        let mut res = RleRegion::new();
+        for j in 0..bounds.rows() {
+            let (x, z, len) = bounds.row(j);
+            if len > 0 {
+                res.rows.insert(z, vec!((x, x + len)));
+            }
+        }
+        res
+    }
+}
+
+impl<I> FromIterator<Coordinate<I>> for RleRegion<I> where
+    I : hex2d::Integer
+{
+    fn from_iter<It : IntoIterator<Item = Coordinate<I>>>(iter : It) -> RleRegion<I> {
+        let mut keys : Vec<(i64, i64)> = iter.into_iter().map(Self::key).collect();
+        keys.sort();
+        keys.dedup();
+
+        let mut res = RleRegion::new();
+        for (z, x) in keys {
+            let runs = res.rows.entry(z).or_insert_with(Vec::new);
+            match runs.last_mut() {
+                Some(last) if last.1 == x => last.1 = x + 1,
+                _ => runs.push((x, x + 1)),
+            }
+        }
+        res
+    }
+}
@@ -0,0 +1,99 @@
+// Copyright 2014 Dawid Ciężarkiewicz
+// See LICENSE file for more information
+
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasherDefault, Hasher};
+
+const SEED : u64 = 0x51_7c_c1_b7_27_22_0a_95;
+
+/// Fast, non-cryptographic hasher (the one used by `rustc`)
+///
+/// Much faster than the default SipHash for small keys like Coordinates, but gives no
+/// protection against deliberately colliding keys.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FxHasher {
+    hash : u64,
+}
+
+impl FxHasher {
+    fn add(&mut self, word : u64) {
+        self.hash = (self.hash.rotate_left(5) ^ word).wrapping_mul(SEED);
+    }
+}
+
+impl Hasher for FxHasher {
+    fn write(&mut self, bytes : &[u8]) {
+        let mut chunks = bytes.chunks_exact(8);
+        for chunk in &mut chunks {
+            let mut word = [0u8; 8];
+            word.copy_from_slice(chunk);
+            self.add(u64::from_le_bytes(word));
+        }
+        for &byte in chunks.remainder() {
+            self.add(byte as u64);
+        }
+    }
+
+    fn write_u8(&mut self, i : u8) {
+        self.add(i as u64);
+    }
+
+    fn write_u16(&mut self, i : u16) {
+        self.add(i as u64);
+    }
+
+    fn write_u32(&mut self, i : u32) {
+        self.add(i as u64);
+    }
+
+    fn write_u64(&mut self, i : u64) {
+        self.add(i);
+    }
+
+    fn write_usize(&mut self, i : usize) {
+        self.add(i as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+/// `BuildHasher` for `FxHasher`, eg. `HashMap<Coordinate, T, FxBuildHasher>`
+pub type FxBuildHasher = BuildHasherDefault<FxHasher>;
+
+/// Hasher of the internal collections of the algorithms
+#[cfg(any(feature = "fxhash", feature = "deterministic"))]
+pub(crate) type Internal = FxBuildHasher;
+
+/// Hasher of the internal collections of the algorithms
+#[cfg(not(any(feature = "fxhash", feature = "deterministic")))]
+pub(crate) type Internal = ::std::collections::hash_map::RandomState;
+
+/// `HashMap` used internally by the algorithms
+pub(crate) type InternalHashMap<K, V> = HashMap<K, V, Internal>;
+
+/// `HashSet` used internally by the algorithms
+pub(crate) type InternalHashSet<K> = HashSet<K, Internal>;
+
+/// Hasher of the collections returned by the crate
+///
+/// The standard, randomly seeded `RandomState` by default. With the `deterministic` feature
+/// it's `FxBuildHasher`, so iteration over returned collections gives the same order in
+/// every run.
+#[cfg(feature = "deterministic")]
+pub type Output = FxBuildHasher;
+
+/// Hasher of the collections returned by the crate
+///
+/// The standard, randomly seeded `RandomState` by default. With the `deterministic` feature
+/// it's `FxBuildHasher`, so iteration over returned collections gives the same order in
+/// every run.
+#[cfg(not(feature = "deterministic"))]
+pub type Output = ::std::collections::hash_map::RandomState;
+
+/// `HashMap` returned by the crate; see `Output`
+pub type OutputHashMap<K, V> = HashMap<K, V, Output>;
+
+/// `HashSet` returned by the crate; see `Output`
+pub type OutputHashSet<K> = HashSet<K, Output>;
@@ -0,0 +1,402 @@
+// Copyright 2014 Dawid Ciężarkiewicz
+// See LICENSE file for more information
+
+/// Export of map regions as generic graphs
+///
+/// Turns a region into a list of nodes and weighted edges, so algorithms this crate doesn't
+/// provide (max-flow, matching...) can run on hex maps. Node indices map back to Coordinates
+/// with `Graph::coordinate`.
+///
+/// With the `petgraph` feature, graphs convert directly to `petgraph` ones, and results of
+/// `petgraph` algorithms map back to `HexMap`s with `to_map`.
+pub mod graph {
+    use hex2d;
+    use hex2d::Coordinate;
+    use hex2d::Direction;
+
+    use std::hash;
+    use std::collections::HashMap;
+
+    use geom::Bounds;
+    #[cfg(feature = "petgraph")]
+    use map::HexMap;
+    #[cfg(feature = "petgraph")]
+    use petgraph;
+    #[cfg(feature = "petgraph")]
+    use petgraph::graph::NodeIndex;
+
+    /// Graph of passable Coordinates of a region
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Graph<W, I = i32> where
+        I : hex2d::Integer,
+        I : hash::Hash
+    {
+        nodes : Vec<Coordinate<I>>,
+        index : HashMap<Coordinate<I>, usize>,
+        edges : Vec<(usize, usize, W)>,
+    }
+
+    impl<W, I> Graph<W, I> where
+        I : hex2d::Integer,
+        I : hash::Hash
+    {
+        /// Build a graph of Coordinates of `bounds` for which `can_pass` returns true
+        ///
+        /// Every pair of neighboring passable Coordinates gets an edge in both directions,
+        /// weighted with `cost(from, to)`. Nodes are numbered in `Bounds` iteration order.
+        pub fn from_region<FCanPass, FCost>(
+            bounds : Bounds<I>,
+            can_pass : FCanPass,
+            mut cost : FCost,
+            ) -> Graph<W, I> where
+            FCanPass : Fn(Coordinate<I>) -> bool,
+            FCost : FnMut(Coordinate<I>, Coordinate<I>) -> W
+        {
+            let nodes : Vec<_> = bounds.iter().filter(|&c| can_pass(c)).collect();
+            let index : HashMap<_, _> = nodes.iter().enumerate().map(|(i, &c)| (c, i)).collect();
+
+            let mut edges = vec!();
+            for (i, &c) in nodes.iter().enumerate() {
+                for &dir in Direction::all().iter() {
+                    if let Some(&j) = index.get(&(c + dir)) {
+                        edges.push((i, j, cost(c, c + dir)));
+                    }
+                }
+            }
+
+            Graph { nodes, index, edges }
+        }
+
+        /// Number of nodes
+        pub fn node_count(&self) -> usize {
+            self.nodes.len()
+        }
+
+        /// Node of `c`, if it's in the graph
+        pub fn node(&self, c : Coordinate<I>) -> Option<usize> {
+            self.index.get(&c).cloned()
+        }
+
+        /// Coordinate of `node`
+        ///
+        /// Panics if `node` is not in the graph.
+        pub fn coordinate(&self, node : usize) -> Coordinate<I> {
+            self.nodes[node]
+        }
+
+        /// All Coordinates, indexed by node
+        pub fn nodes(&self) -> &[Coordinate<I>] {
+            &self.nodes
+        }
+
+        /// All `(from, to, weight)` edges
+        pub fn edges(&self) -> &[(usize, usize, W)] {
+            &self.edges
+        }
+    }
+
+    #[cfg(feature = "petgraph")]
+    impl<W, I> Graph<W, I> where
+        W : Clone,
+        I : hex2d::Integer,
+        I : hash::Hash
+    {
+        /// `petgraph` node of `c`, if it's in the graph
+        ///
+        /// Valid for graphs made by `to_petgraph` and `to_petgraph_undirected`.
+        pub fn node_index(&self, c : Coordinate<I>) -> Option<NodeIndex> {
+            self.node(c).map(NodeIndex::new)
+        }
+
+        /// Directed `petgraph::Graph` with Coordinates as node weights
+        ///
+        /// Nodes keep their indices, and every edge of this graph becomes a `petgraph` edge.
+        pub fn to_petgraph(&self) -> petgraph::Graph<Coordinate<I>, W> {
+            let mut graph = petgraph::Graph::with_capacity(self.nodes.len(), self.edges.len());
+            for &c in &self.nodes {
+                graph.add_node(c);
+            }
+            for &(from, to, ref w) in &self.edges {
+                graph.add_edge(NodeIndex::new(from), NodeIndex::new(to), w.clone());
+            }
+            graph
+        }
+
+        /// Undirected `petgraph::UnGraph` with Coordinates as node weights
+        ///
+        /// Nodes keep their indices. Every pair of neighbors gets a single edge, weighted like
+        /// the step from the node with the lower index.
+        pub fn to_petgraph_undirected(&self) -> petgraph::graph::UnGraph<Coordinate<I>, W> {
+            let mut graph = petgraph::graph::UnGraph::with_capacity(self.nodes.len(), self.edges.len() / 2);
+            for &c in &self.nodes {
+                graph.add_node(c);
+            }
+            for &(from, to, ref w) in self.edges.iter().filter(|&&(from, to, _)| from < to) {
+                graph.add_edge(NodeIndex::new(from), NodeIndex::new(to), w.clone());
+            }
+            graph
+        }
+    }
+
+    /// Map per-node `values` of a `petgraph` graph with Coordinate nodes back to a map
+    ///
+    /// Takes results of `petgraph` algorithms, eg. distances from `petgraph::algo::dijkstra`.
+    /// Coordinates of `bounds` without a value get `default`; values of nodes outside of
+    /// `bounds` are skipped.
+    #[cfg(feature = "petgraph")]
+    pub fn to_map<T, E, Ty, It, I>(
+        graph : &petgraph::Graph<Coordinate<I>, E, Ty>,
+        values : It,
+        bounds : Bounds<I>,
+        default : T
+        ) -> HexMap<T, I> where
+        T : Clone,
+        Ty : petgraph::EdgeType,
+        It : IntoIterator<Item = (NodeIndex, T)>,
+        I : hex2d::Integer
+    {
+        let mut map = HexMap::new(bounds, default);
+        for (node, value) in values {
+            if let Some(tile) = map.get_mut(graph[node]) {
+                *tile = value;
+            }
+        }
+        map
+    }
+}
+
+/// Loader of hexagonal Tiled (`.tmx`) maps
+///
+/// Supports maps with `orientation="hexagonal"`, `staggerindex="odd"` and layers stored with
+/// CSV encoding. Tile properties `passable` (bool, `true` by default) and `opaqueness` (int, `1`
+/// by default) are read from tilesets embedded in the map; tiles of external tilesets get the
+/// defaults. Layers inside of groups are included.
+#[cfg(feature = "tiled")]
+pub mod tiled {
+    use hex2d::Coordinate;
+
+    use std::cmp;
+    use std::error;
+    use std::fmt;
+    use std::collections::HashMap;
+
+    use geom::Bounds;
+    use map::HexMap;
+
+    /// Tiled map loading error
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub enum Error {
+        /// Malformed document
+        Parse(String),
+        /// Valid, but unsupported map feature
+        Unsupported(String),
+    }
+
+    impl fmt::Display for Error {
+        fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+            match *self {
+                Error::Parse(ref msg) => write!(f, "malformed Tiled map: {}", msg),
+                Error::Unsupported(ref msg) => write!(f, "unsupported Tiled map: {}", msg),
+            }
+        }
+    }
+
+    impl error::Error for Error {}
+
+    /// Tiled map converted to `HexMap`s
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct TiledMap {
+        /// Tile IDs (global, `0` for empty) of every layer, by layer name
+        pub layers : Vec<(String, HexMap<u32>)>,
+        /// Can every Coordinate be passed: `passable` property of all its tiles is true
+        pub passable : HexMap<bool>,
+        /// Opaqueness of every Coordinate: biggest `opaqueness` property of its tiles
+        pub opaqueness : HexMap<i32>,
+    }
+
+    impl From<roxmltree::Error> for Error {
+        fn from(e : roxmltree::Error) -> Error {
+            Error::Parse(e.to_string())
+        }
+    }
+
+    fn number<T : ::std::str::FromStr>(node : roxmltree::Node, name : &str) -> Result<T, Error> {
+        node.attribute(name)
+            .and_then(|v| v.trim().parse().ok())
+            .ok_or_else(|| Error::Parse(format!("missing or invalid `{}` of <{}>", name, node.tag_name().name())))
+    }
+
+    /// Properties of a tile
+    #[derive(Copy, Clone)]
+    struct Properties {
+        passable : bool,
+        opaqueness : i32,
+    }
+
+    const DEFAULT : Properties = Properties { passable: true, opaqueness: 1 };
+
+    /// Flip flags stored in the high bits of tile IDs
+    const FLIP_MASK : u32 = 0xf000_0000;
+
+    /// Parse a `.tmx` document
+    pub fn parse(xml : &str) -> Result<TiledMap, Error> {
+        let doc = roxmltree::Document::parse(xml)?;
+        let root = doc.root_element();
+        if root.tag_name().name() != "map" {
+            return Err(Error::Parse("no <map> element".into()));
+        }
+        if root.attribute("orientation") != Some("hexagonal") {
+            return Err(Error::Unsupported("orientation is not hexagonal".into()));
+        }
+        if root.attribute("staggerindex") != Some("odd") {
+            return Err(Error::Unsupported("staggerindex is not odd".into()));
+        }
+        let width : i32 = number(root, "width")?;
+        let height : i32 = number(root, "height")?;
+        if width < 0 || height < 0 {
+            return Err(Error::Parse("negative map size".into()));
+        }
+        let stagger_x = root.attribute("staggeraxis") == Some("x");
+
+        // Odd rows (or columns) shifted: rows along `z` match `Bounds::rectangle`. Staggered
+        // columns are mirrored, so columns become rows.
+        let bounds = if stagger_x {
+            Bounds::rectangle(Coordinate::new(0, 0), height, width)
+        } else {
+            Bounds::rectangle(Coordinate::new(0, 0), width, height)
+        };
+        let offset_of = |c : Coordinate| if stagger_x {
+            (c.z(), c.x + c.z() / 2)
+        } else {
+            (c.x + c.z() / 2, c.z())
+        };
+
+        let mut properties : HashMap<u32, Properties> = HashMap::new();
+        for tileset in root.children().filter(|n| n.has_tag_name("tileset")) {
+            let firstgid : u32 = number(tileset, "firstgid")?;
+            for tile in tileset.children().filter(|n| n.has_tag_name("tile")) {
+                let id : u32 = number(tile, "id")?;
+                let gid = firstgid.checked_add(id).ok_or_else(|| Error::Parse("tile id out of range".into()))?;
+                for property in tile.descendants().filter(|n| n.has_tag_name("property")) {
+                    let props = properties.entry(gid).or_insert(DEFAULT);
+                    match property.attribute("name") {
+                        Some("passable") => props.passable = number(property, "value")?,
+                        Some("opaqueness") => props.opaqueness = number(property, "value")?,
+                        _ => {},
+                    }
+                }
+            }
+        }
+
+        // Layers can be nested in groups
+        let mut layers = vec!();
+        for layer in root.descendants().filter(|n| n.has_tag_name("layer")) {
+            let name = layer.attribute("name").unwrap_or("").to_owned();
+            let data = match layer.children().find(|n| n.has_tag_name("data")) {
+                Some(data) => data,
+                None => return Err(Error::Parse(format!("no <data> in layer `{}`", name))),
+            };
+            if data.attribute("encoding") != Some("csv") {
+                return Err(Error::Unsupported("layer data encoding is not csv".into()));
+            }
+            let text : String = data.children().filter(|n| n.is_text()).filter_map(|n| n.text()).collect();
+            let ids = text.split(',')
+                .map(|id| id.trim().parse::<u32>().map(|id| id & !FLIP_MASK))
+                .collect::<Result<Vec<u32>, _>>()
+                .map_err(|_| Error::Parse(format!("invalid tile id in layer `{}`", name)))?;
+            if ids.len() != (width as usize) * (height as usize) {
+                return Err(Error::Parse(format!("wrong number of tiles in layer `{}`", name)));
+            }
+            let map = HexMap::from_fn(bounds, |c| {
+                let (col, row) = offset_of(c);
+                ids[(row * width + col) as usize]
+            });
+            layers.push((name, map));
+        }
+
+        let props = |c : Coordinate| layers.iter()
+            .map(move |(_, map) : &(String, HexMap<u32>)| map[c])
+            .filter(|&gid| gid != 0)
+            .map(|gid| properties.get(&gid).cloned().unwrap_or(DEFAULT));
+        let passable = HexMap::from_fn(bounds, |c| props(c).all(|p| p.passable));
+        let opaqueness = HexMap::from_fn(bounds, |c| props(c).fold(1, |o, p| cmp::max(o, p.opaqueness)));
+
+        Ok(TiledMap { layers, passable, opaqueness })
+    }
+}
+
+/// ASCII map layouts, for tests and prototypes
+///
+/// Uses the layout of `debug::render`: every line is a row of Coordinates with the same `z`,
+/// tiles are separated by spaces and every row is shifted by half a tile from the previous
+/// one, so the text looks like the hex grid:
+///
+/// ```text
+///  # # # #
+/// # . S . #
+///  # . D #
+/// ```
+pub mod ascii {
+    use hex2d::Coordinate;
+
+    use hashing::{InternalHashMap, InternalHashSet, OutputHashMap};
+    use geom::Bounds;
+    use map::HexMap;
+
+    /// Parse an ASCII layout into a map of tile characters and labeled markers
+    ///
+    /// Letters and digits are markers: their positions are returned by label, and the map has
+    /// a floor (`.`) under them. Any other non-space character is a tile.
+    ///
+    /// The layout must have the shape of a rectangle (`Bounds::rectangle` with the first tile at
+    /// `(0, 0)`) or of a hexagon (`Bounds::hexagon` centered at `(0, 0)`).
+    ///
+    /// Panics if the layout is malformed, or if a marker label is used more than once.
+    pub fn parse(s : &str) -> (HexMap<char>, OutputHashMap<char, Coordinate>) {
+        let lines : Vec<&str> = s.lines().filter(|l| !l.trim().is_empty()).collect();
+
+        let mut cells = vec!();
+        let mut parity = None;
+        for (z, line) in lines.iter().enumerate() {
+            let z = z as i32;
+            for (col, ch) in line.chars().enumerate().filter(|&(_, ch)| ch != ' ') {
+                let dx = col as i32 - z;
+                let p = *parity.get_or_insert(dx.rem_euclid(2));
+                assert!(dx.rem_euclid(2) == p, "ascii::parse: tile `{}` is not aligned to the grid", ch);
+                cells.push((Coordinate::new((dx - p) / 2, -(dx - p) / 2 - z), ch));
+            }
+        }
+        assert!(!cells.is_empty(), "ascii::parse: empty layout");
+
+        let coords : InternalHashSet<Coordinate> = cells.iter().map(|&(c, _)| c).collect();
+        let height = lines.len() as i32;
+        let first = cells[0].0;
+
+        let width = cells.iter().filter(|&&(c, _)| c.z() == 0).count() as i32;
+        let rectangle = Bounds::rectangle(first, width, height);
+        let middle : Vec<Coordinate> = cells.iter().map(|&(c, _)| c).filter(|c| c.z() == height / 2).collect();
+        let hexagon = Bounds::hexagon(middle[middle.len() / 2], height / 2);
+
+        let (bounds, shift) = if rectangle.len() == coords.len() && rectangle.iter().all(|c| coords.contains(&c)) {
+            (Bounds::rectangle(Coordinate::new(0, 0), width, height), first)
+        } else if height % 2 == 1 && hexagon.len() == coords.len() && hexagon.iter().all(|c| coords.contains(&c)) {
+            (Bounds::hexagon(Coordinate::new(0, 0), height / 2), middle[middle.len() / 2])
+        } else {
+            panic!("ascii::parse: layout is neither a rectangle nor a hexagon");
+        };
+
+        let mut tiles : InternalHashMap<Coordinate, char> = InternalHashMap::default();
+        let mut markers = OutputHashMap::default();
+        for (c, ch) in cells {
+            let c = c - shift;
+            if ch.is_ascii_alphanumeric() {
+                assert!(markers.insert(ch, c).is_none(), "ascii::parse: marker `{}` used more than once", ch);
+                tiles.insert(c, '.');
+            } else {
+                tiles.insert(c, ch);
+            }
+        }
+
+        (HexMap::from_fn(bounds, |c| tiles[&c]), markers)
+    }
+}
@@ -2,15 +2,75 @@
 // See LICENSE file for more information
 
 //! Dpc's hacky extensions
+//!
+//! Everything is generic over the Coordinate integer type `I`, with the same bounds
+//! everywhere: `hex2d::Integer + Hash + AddAssign` (plus `Send + Sync` for parallel ones).
+//! `i16`, `i32` and `i64` all work.
+//!
+//! Nothing in the crate uses shared ownership or interior mutability, so searchers (eg.
+//! `algo::bfs::Traverser`) are `Send` and `Sync` whenever the closures they hold are, and
+//! result types (`FovMap`, `LightMap`, `InfluenceMap`, maps...) always are. They can be
+//! handed to job systems and parallel ECS schedules.
 
 #![warn(missing_docs)]
 
 extern crate num;
 extern crate rand;
 extern crate hex2d;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde_derive;
+#[cfg(feature = "petgraph")]
+extern crate petgraph;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "tiled")]
+extern crate roxmltree;
 
 /// Useful algorithms
 pub mod algo;
 
+/// Geometry of Coordinate sets
+pub mod geom;
+
+/// Map storage
+pub mod map;
+
+/// Hashing
+///
+/// `FxHasher` is much faster than the default SipHash for Coordinates. The `fxhash` feature
+/// switches the internal `HashMap`s and `HashSet`s of searches and LoS to it.
+///
+/// The `deterministic` feature does the same for a different reason: `FxHasher` is not
+/// randomly seeded, so iteration over `FovMap`, `InfluenceMap` and `ScentMap` gives the same
+/// order in every run, for replays and lockstep multiplayer. It also switches `hashing::Output`,
+/// the hasher of all `HashMap`s and `HashSet`s returned by the crate (generator outputs,
+/// `algo::distance::from_obstacles`...), so identical inputs give identical orderings
+/// everywhere.
+pub mod hashing;
+
+/// Map generators
+///
+/// Generators take their randomness from a caller-provided `rand::Rng`. The same seeded
+/// generator (eg. `rand::rngs::StdRng::seed_from_u64`) always gives the same map.
+pub mod gen;
+
+/// Simulations ticking over maps
+pub mod sim;
+
+/// Compact binary snapshots of maps and Coordinate sets
+///
+/// Run-length encoded varints, much smaller than generic serialization for large, mostly
+/// uniform maps. Handy for sending maps, their diffs and fog-of-war over the network.
+pub mod snapshot;
+
+/// Debugging helpers
+pub mod debug;
+
+/// Interoperability with other libraries and formats
+pub mod interop;
+
 #[cfg(test)]
 mod test;
@@ -0,0 +1,1072 @@
+// Copyright 2014 Dawid Ciężarkiewicz
+// See LICENSE file for more information
+
+use hex2d;
+use hex2d::Coordinate;
+
+use std::any::Any;
+use std::hash;
+use std::mem;
+use std::ops::{Index, IndexMut};
+use std::collections::HashMap;
+
+use num::Integer;
+
+use algo::los::Light;
+use geom::Bounds;
+use hashing::InternalHashMap;
+
+/// Read access to tiles by Coordinate
+///
+/// Implemented by all the map types, so they can be handed directly to the algorithms:
+/// `passable` and `opaqueness` turn a map into closures expected by searches and LoS.
+pub trait Grid<I = i32> where
+    I : hex2d::Integer
+{
+    /// Type of a single tile
+    type Tile;
+
+    /// Tile at `c`, or `None` if `c` is outside of the map
+    fn get(&self, c : Coordinate<I>) -> Option<&Self::Tile>;
+
+    /// Is `c` part of the map
+    fn contains(&self, c : Coordinate<I>) -> bool {
+        self.get(c).is_some()
+    }
+
+    /// Passability check deciding with `pass` for every tile
+    ///
+    /// Coordinates outside of the map can't be passed.
+    fn passable<'a, FPass>(&'a self, pass : FPass) -> impl Fn(Coordinate<I>) -> bool + 'a where
+        FPass : Fn(&Self::Tile) -> bool + 'a
+    {
+        move |c| self.get(c).map(&pass).unwrap_or(false)
+    }
+
+    /// Opaqueness function taking values from `opaq` for every tile, and `outside` for
+    /// Coordinates outside of the map
+    fn opaqueness<'a, FOpaq, L>(&'a self, opaq : FOpaq, outside : L) -> impl Fn(Coordinate<I>) -> L + 'a where
+        FOpaq : Fn(&Self::Tile) -> L + 'a,
+        L : Copy + 'a
+    {
+        move |c| self.get(c).map(&opaq).unwrap_or(outside)
+    }
+}
+
+/// Write access to tiles by Coordinate
+pub trait GridMut<I = i32> : Grid<I> where
+    I : hex2d::Integer
+{
+    /// Mutable tile at `c`, or `None` if `c` is outside of the map
+    fn get_mut(&mut self, c : Coordinate<I>) -> Option<&mut Self::Tile>;
+}
+
+/// Dense map storing a tile for every Coordinate of `Bounds`
+///
+/// Tiles are kept in a `Vec`, in `Bounds` iteration order, so indexing by Coordinate is O(1).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct HexMap<T, I = i32> where
+    I : hex2d::Integer
+{
+    bounds : Bounds<I>,
+    tiles : Vec<T>,
+}
+
+impl<T, I> HexMap<T, I> where
+    I : hex2d::Integer
+{
+    /// Create a map over `bounds` with all tiles set to `value`
+    pub fn new(bounds : Bounds<I>, value : T) -> HexMap<T, I> where
+        T : Clone
+    {
+        HexMap {
+            tiles: vec!(value; bounds.len()),
+            bounds,
+        }
+    }
+
+    /// Create a map over `bounds` with tiles returned by `f`
+    pub fn from_fn<F>(bounds : Bounds<I>, f : F) -> HexMap<T, I> where
+        F : FnMut(Coordinate<I>) -> T
+    {
+        HexMap {
+            tiles: bounds.iter().map(f).collect(),
+            bounds,
+        }
+    }
+
+    /// Region covered by the map
+    pub fn bounds(&self) -> Bounds<I> {
+        self.bounds
+    }
+
+    /// Number of tiles
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    /// Is the map empty
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    /// Is `c` within the map
+    pub fn contains(&self, c : Coordinate<I>) -> bool {
+        self.bounds.contains(c)
+    }
+
+    /// Tile at `c`, or `None` if `c` is outside of the map
+    pub fn get(&self, c : Coordinate<I>) -> Option<&T> {
+        self.bounds.index(c).map(|i| &self.tiles[i])
+    }
+
+    /// Mutable tile at `c`, or `None` if `c` is outside of the map
+    pub fn get_mut(&mut self, c : Coordinate<I>) -> Option<&mut T> {
+        match self.bounds.index(c) {
+            Some(i) => Some(&mut self.tiles[i]),
+            None => None,
+        }
+    }
+
+    /// Set all tiles to `value`
+    pub fn fill(&mut self, value : T) where
+        T : Clone
+    {
+        for tile in self.tiles.iter_mut() {
+            *tile = value.clone();
+        }
+    }
+
+    /// Swap tiles at `a` and `b`
+    ///
+    /// Panics if any of them is outside of the map.
+    pub fn swap(&mut self, a : Coordinate<I>, b : Coordinate<I>) {
+        let a = self.bounds.index(a).expect("HexMap: swap out of bounds");
+        let b = self.bounds.index(b).expect("HexMap: swap out of bounds");
+        self.tiles.swap(a, b);
+    }
+
+    /// Set tile at `c` to `value`, returning the previous one
+    ///
+    /// Returns `None` (and drops `value`) if `c` is outside of the map.
+    pub fn replace(&mut self, c : Coordinate<I>, value : T) -> Option<T> {
+        self.get_mut(c).map(|tile| mem::replace(tile, value))
+    }
+
+    /// Iterator over all Coordinates and their tiles
+    pub fn iter(&self) -> impl Iterator<Item = (Coordinate<I>, &T)> {
+        self.bounds.iter().zip(self.tiles.iter())
+    }
+
+    /// Iterator over all Coordinates and their mutable tiles
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Coordinate<I>, &mut T)> {
+        self.bounds.iter().zip(self.tiles.iter_mut())
+    }
+
+    /// Create a map of the same shape with tiles converted by `f`
+    pub fn map<U, F>(&self, mut f : F) -> HexMap<U, I> where
+        F : FnMut(Coordinate<I>, &T) -> U
+    {
+        HexMap {
+            bounds: self.bounds,
+            tiles: self.iter().map(|(c, t)| f(c, t)).collect(),
+        }
+    }
+
+    /// Move the whole map by `offset`
+    ///
+    /// Tiles keep their place in the map, so it's cheap.
+    pub fn translate(&mut self, offset : Coordinate<I>) {
+        self.bounds = self.bounds.translate(offset);
+    }
+
+    /// Copy of the part of the map within `bounds`
+    ///
+    /// Panics if `bounds` are not entirely within the map.
+    pub fn crop(&self, bounds : Bounds<I>) -> HexMap<T, I> where
+        T : Clone
+    {
+        HexMap::from_fn(bounds, |c| self.get(c).expect("HexMap: crop out of bounds").clone())
+    }
+
+    /// Change the region covered by the map to `bounds`
+    ///
+    /// Tiles within both the old and new bounds are kept, the new ones are created by
+    /// `f`, and the rest are dropped.
+    pub fn resize_with<F>(&mut self, bounds : Bounds<I>, mut f : F) where
+        F : FnMut(Coordinate<I>) -> T
+    {
+        let old = self.bounds;
+        let mut tiles : Vec<Option<T>> = mem::take(&mut self.tiles).into_iter().map(Some).collect();
+        self.tiles = bounds.iter()
+            .map(|c| old.index(c).and_then(|i| tiles[i].take()).unwrap_or_else(|| f(c)))
+            .collect();
+        self.bounds = bounds;
+    }
+
+    /// Borrow the part of the map within `bounds`, without copying tiles
+    pub fn view(&self, bounds : Bounds<I>) -> HexMapView<'_, T, I> {
+        HexMapView {
+            map: self,
+            bounds,
+        }
+    }
+
+    /// Mutably borrow the part of the map within `bounds`
+    pub fn view_mut(&mut self, bounds : Bounds<I>) -> HexMapViewMut<'_, T, I> {
+        HexMapViewMut {
+            map: self,
+            bounds,
+        }
+    }
+
+    /// Copy all tiles of `other`, moved by `offset`, onto the map
+    ///
+    /// Meant for stamping separately generated prefabs into a bigger map. Tiles landing
+    /// outside of the map are skipped.
+    pub fn blit(&mut self, other : &HexMap<T, I>, offset : Coordinate<I>) where
+        T : Clone
+    {
+        for (c, tile) in other.iter() {
+            if let Some(dst) = self.get_mut(c + offset) {
+                *dst = tile.clone();
+            }
+        }
+    }
+}
+
+impl<T, I> Index<Coordinate<I>> for HexMap<T, I> where
+    I : hex2d::Integer
+{
+    type Output = T;
+
+    fn index(&self, c : Coordinate<I>) -> &T {
+        self.get(c).expect("HexMap: index out of bounds")
+    }
+}
+
+impl<T, I> IndexMut<Coordinate<I>> for HexMap<T, I> where
+    I : hex2d::Integer
+{
+    fn index_mut(&mut self, c : Coordinate<I>) -> &mut T {
+        self.get_mut(c).expect("HexMap: index out of bounds")
+    }
+}
+
+impl<T, I> Grid<I> for HexMap<T, I> where
+    I : hex2d::Integer
+{
+    type Tile = T;
+
+    fn get(&self, c : Coordinate<I>) -> Option<&T> {
+        HexMap::get(self, c)
+    }
+}
+
+impl<T, I> GridMut<I> for HexMap<T, I> where
+    I : hex2d::Integer
+{
+    fn get_mut(&mut self, c : Coordinate<I>) -> Option<&mut T> {
+        HexMap::get_mut(self, c)
+    }
+}
+
+/// Borrowed part of a `HexMap`
+///
+/// Only Coordinates within both the map and `bounds` are visible, so algorithms run on it
+/// stay inside, eg. a single room or chunk. Created with `HexMap::view`.
+#[derive(Debug)]
+pub struct HexMapView<'a, T : 'a, I : 'a = i32> where
+    I : hex2d::Integer
+{
+    map : &'a HexMap<T, I>,
+    bounds : Bounds<I>,
+}
+
+// Derives would require `T : Clone`
+impl<'a, T, I> Clone for HexMapView<'a, T, I> where
+    I : hex2d::Integer
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T, I> Copy for HexMapView<'a, T, I> where
+    I : hex2d::Integer
+{
+}
+
+impl<'a, T, I> HexMapView<'a, T, I> where
+    I : hex2d::Integer
+{
+    /// Region of the view
+    pub fn bounds(&self) -> Bounds<I> {
+        self.bounds
+    }
+
+    /// Is `c` visible through the view
+    pub fn contains(&self, c : Coordinate<I>) -> bool {
+        self.bounds.contains(c) && self.map.contains(c)
+    }
+
+    /// Tile at `c`, or `None` if `c` is outside of the view
+    pub fn get(&self, c : Coordinate<I>) -> Option<&'a T> {
+        if self.bounds.contains(c) { self.map.get(c) } else { None }
+    }
+
+    /// Iterator over all visible Coordinates and their tiles
+    pub fn iter(&self) -> impl Iterator<Item = (Coordinate<I>, &'a T)> {
+        let map = self.map;
+        self.bounds.iter().filter_map(move |c| map.get(c).map(|t| (c, t)))
+    }
+}
+
+impl<'a, T, I> Grid<I> for HexMapView<'a, T, I> where
+    I : hex2d::Integer
+{
+    type Tile = T;
+
+    fn get(&self, c : Coordinate<I>) -> Option<&T> {
+        HexMapView::get(self, c)
+    }
+}
+
+/// Mutably borrowed part of a `HexMap`
+///
+/// Like `HexMapView`, but tiles can be changed. Created with `HexMap::view_mut`.
+#[derive(Debug)]
+pub struct HexMapViewMut<'a, T : 'a, I : 'a = i32> where
+    I : hex2d::Integer
+{
+    map : &'a mut HexMap<T, I>,
+    bounds : Bounds<I>,
+}
+
+impl<'a, T, I> HexMapViewMut<'a, T, I> where
+    I : hex2d::Integer
+{
+    /// Region of the view
+    pub fn bounds(&self) -> Bounds<I> {
+        self.bounds
+    }
+
+    /// Is `c` visible through the view
+    pub fn contains(&self, c : Coordinate<I>) -> bool {
+        self.bounds.contains(c) && self.map.contains(c)
+    }
+
+    /// Tile at `c`, or `None` if `c` is outside of the view
+    pub fn get(&self, c : Coordinate<I>) -> Option<&T> {
+        if self.bounds.contains(c) { self.map.get(c) } else { None }
+    }
+
+    /// Mutable tile at `c`, or `None` if `c` is outside of the view
+    pub fn get_mut(&mut self, c : Coordinate<I>) -> Option<&mut T> {
+        if self.bounds.contains(c) { self.map.get_mut(c) } else { None }
+    }
+
+    /// Set all visible tiles to `value`
+    pub fn fill(&mut self, value : T) where
+        T : Clone
+    {
+        let bounds = self.bounds;
+        for (c, tile) in self.map.iter_mut() {
+            if bounds.contains(c) {
+                *tile = value.clone();
+            }
+        }
+    }
+
+    /// Read-only view of the same region
+    pub fn as_view(&self) -> HexMapView<'_, T, I> {
+        HexMapView {
+            map: self.map,
+            bounds: self.bounds,
+        }
+    }
+}
+
+impl<'a, T, I> Grid<I> for HexMapViewMut<'a, T, I> where
+    I : hex2d::Integer
+{
+    type Tile = T;
+
+    fn get(&self, c : Coordinate<I>) -> Option<&T> {
+        HexMapViewMut::get(self, c)
+    }
+}
+
+impl<'a, T, I> GridMut<I> for HexMapViewMut<'a, T, I> where
+    I : hex2d::Integer
+{
+    fn get_mut(&mut self, c : Coordinate<I>) -> Option<&mut T> {
+        HexMapViewMut::get_mut(self, c)
+    }
+}
+
+impl<T, I, S> Grid<I> for HashMap<Coordinate<I>, T, S> where
+    I : hex2d::Integer,
+    I : hash::Hash,
+    S : hash::BuildHasher
+{
+    type Tile = T;
+
+    fn get(&self, c : Coordinate<I>) -> Option<&T> {
+        HashMap::get(self, &c)
+    }
+}
+
+impl<T, I, S> GridMut<I> for HashMap<Coordinate<I>, T, S> where
+    I : hex2d::Integer,
+    I : hash::Hash,
+    S : hash::BuildHasher
+{
+    fn get_mut(&mut self, c : Coordinate<I>) -> Option<&mut T> {
+        HashMap::get_mut(self, &c)
+    }
+}
+
+/// Map of unlimited size, stored in chunks generated on demand
+///
+/// The plane is tiled with hexagon-shaped chunks of `radius`. A chunk is created with the
+/// generator closure the first time one of its tiles is accessed mutably, or when loaded
+/// explicitly.
+///
+/// Read access (including `Grid` implementation) only sees chunks that are already loaded, so
+/// before running searches or LoS over an area, make sure it's loaded with `load_range`.
+pub struct ChunkedMap<T, F, I = i32> where
+    I : hex2d::Integer,
+    I : hash::Hash,
+    F : FnMut(Coordinate<I>) -> T
+{
+    radius : I,
+    chunks : HashMap<Coordinate<I>, Vec<T>>,
+    generate : F,
+}
+
+impl<T, F, I> ChunkedMap<T, F, I> where
+    I : hex2d::Integer,
+    I : hash::Hash,
+    F : FnMut(Coordinate<I>) -> T
+{
+    /// Create an empty map with hexagon chunks of `radius`, with tiles created by `generate`
+    ///
+    /// Every chunk holds `3 * radius * (radius + 1) + 1` tiles.
+    ///
+    /// Panics if `radius` is negative.
+    pub fn new(radius : I, generate : F) -> ChunkedMap<T, F, I> {
+        assert!(radius >= I::zero(), "ChunkedMap: chunk radius can't be negative");
+        ChunkedMap {
+            radius,
+            chunks: HashMap::new(),
+            generate,
+        }
+    }
+
+    /// Steps between centers of neighboring chunks, along the two axes of the chunk grid
+    fn chunk_axes(&self) -> ((i64, i64), (i64, i64)) {
+        let r = self.radius.to_i64().unwrap();
+        ((2 * r + 1, -r - 1), (r, -2 * r - 1))
+    }
+
+    /// Center of `chunk`
+    pub fn chunk_center(&self, chunk : Coordinate<I>) -> Coordinate<I> {
+        let ((ax, ay), (bx, by)) = self.chunk_axes();
+        let (i, j) = (chunk.x.to_i64().unwrap(), chunk.y.to_i64().unwrap());
+        Coordinate::new(I::from_i64(i * ax + j * bx).unwrap(), I::from_i64(i * ay + j * by).unwrap())
+    }
+
+    /// Chunk containing `c`, identified by its position on the chunk grid
+    pub fn chunk_of(&self, c : Coordinate<I>) -> Coordinate<I> {
+        let ((ax, ay), (bx, by)) = self.chunk_axes();
+        let (x, y) = (c.x.to_i64().unwrap(), c.y.to_i64().unwrap());
+
+        // Position on the chunk grid, rounded down; every chunk lies within one step of the
+        // grid point of its center, so the one holding `c` is among the next four
+        let det = ax * by - ay * bx;
+        let i = Integer::div_floor(&(x * by - y * bx), &det);
+        let j = Integer::div_floor(&(ax * y - ay * x), &det);
+        for &(di, dj) in &[(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let chunk = Coordinate::new(I::from_i64(i + di).unwrap(), I::from_i64(j + dj).unwrap());
+            if self.chunk_center(chunk).distance(c) <= self.radius {
+                return chunk;
+            }
+        }
+        unreachable!("ChunkedMap: chunks tile the whole plane")
+    }
+
+    fn chunk_bounds(&self, chunk : Coordinate<I>) -> Bounds<I> {
+        Bounds::hexagon(self.chunk_center(chunk), self.radius)
+    }
+
+    fn offset(&self, chunk : Coordinate<I>, c : Coordinate<I>) -> usize {
+        self.chunk_bounds(chunk).index(c).unwrap()
+    }
+
+    /// Is the chunk containing `c` loaded
+    pub fn is_loaded(&self, c : Coordinate<I>) -> bool {
+        self.chunks.contains_key(&self.chunk_of(c))
+    }
+
+    /// Make sure chunk containing `c` is loaded
+    pub fn load(&mut self, c : Coordinate<I>) {
+        let chunk = self.chunk_of(c);
+        if self.chunks.contains_key(&chunk) {
+            return;
+        }
+
+        let tiles = self.chunk_bounds(chunk).iter().map(&mut self.generate).collect();
+        self.chunks.insert(chunk, tiles);
+    }
+
+    /// Make sure all chunks within `radius` from `center` are loaded
+    pub fn load_range(&mut self, center : Coordinate<I>, radius : I) {
+        for c in Bounds::hexagon(center, radius).iter() {
+            self.load(c);
+        }
+    }
+
+    /// Drop the chunk containing `c`
+    ///
+    /// Returns false if it wasn't loaded.
+    pub fn unload(&mut self, c : Coordinate<I>) -> bool {
+        let chunk = self.chunk_of(c);
+        self.chunks.remove(&chunk).is_some()
+    }
+
+    /// Number of loaded chunks
+    pub fn loaded_chunks(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Tile at `c`, or `None` if its chunk is not loaded
+    pub fn get(&self, c : Coordinate<I>) -> Option<&T> {
+        let chunk = self.chunk_of(c);
+        self.chunks.get(&chunk).map(|tiles| &tiles[self.offset(chunk, c)])
+    }
+
+    /// Tile at `c`, loading its chunk if needed
+    pub fn get_or_load(&mut self, c : Coordinate<I>) -> &mut T {
+        self.load(c);
+        let chunk = self.chunk_of(c);
+        let offset = self.offset(chunk, c);
+        &mut self.chunks.get_mut(&chunk).unwrap()[offset]
+    }
+}
+
+impl<T, F, I> Grid<I> for ChunkedMap<T, F, I> where
+    I : hex2d::Integer,
+    I : hash::Hash,
+    F : FnMut(Coordinate<I>) -> T
+{
+    type Tile = T;
+
+    fn get(&self, c : Coordinate<I>) -> Option<&T> {
+        ChunkedMap::get(self, c)
+    }
+}
+
+impl<T, F, I> GridMut<I> for ChunkedMap<T, F, I> where
+    I : hex2d::Integer,
+    I : hash::Hash,
+    F : FnMut(Coordinate<I>) -> T
+{
+    fn get_mut(&mut self, c : Coordinate<I>) -> Option<&mut T> {
+        Some(self.get_or_load(c))
+    }
+}
+
+/// Map of unlimited size, storing only tiles different from the default one
+///
+/// Good for mostly uniform maps, like an ocean with a few islands.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SparseMap<T, I = i32> where
+    I : hex2d::Integer,
+    I : hash::Hash
+{
+    default : T,
+    tiles : HashMap<Coordinate<I>, T>,
+}
+
+impl<T, I> SparseMap<T, I> where
+    I : hex2d::Integer,
+    I : hash::Hash
+{
+    /// Create a map with all tiles set to `default`
+    pub fn new(default : T) -> SparseMap<T, I> {
+        SparseMap {
+            default,
+            tiles: HashMap::new(),
+        }
+    }
+
+    /// The default tile
+    pub fn default_tile(&self) -> &T {
+        &self.default
+    }
+
+    /// Number of explicitly stored tiles
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    /// Are all tiles set to default
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    /// Tile at `c`
+    pub fn get(&self, c : Coordinate<I>) -> &T {
+        self.tiles.get(&c).unwrap_or(&self.default)
+    }
+
+    /// Mutable tile at `c`
+    ///
+    /// Stores a copy of the default tile at `c` if there wasn't one. Use `compact` to drop
+    /// tiles that ended up the same as the default.
+    pub fn get_mut(&mut self, c : Coordinate<I>) -> &mut T where
+        T : Clone
+    {
+        let default = &self.default;
+        self.tiles.entry(c).or_insert_with(|| default.clone())
+    }
+
+    /// Set tile at `c` to `value`, returning the previous one
+    pub fn replace(&mut self, c : Coordinate<I>, value : T) -> T where
+        T : Clone + PartialEq
+    {
+        let prev = if value == self.default {
+            self.tiles.remove(&c)
+        } else {
+            self.tiles.insert(c, value)
+        };
+        prev.unwrap_or_else(|| self.default.clone())
+    }
+
+    /// Set all tiles to `value`
+    pub fn fill(&mut self, value : T) {
+        self.tiles.clear();
+        self.default = value;
+    }
+
+    /// Swap tiles at `a` and `b`
+    pub fn swap(&mut self, a : Coordinate<I>, b : Coordinate<I>) {
+        let ta = self.tiles.remove(&a);
+        let tb = self.tiles.remove(&b);
+        if let Some(t) = ta {
+            self.tiles.insert(b, t);
+        }
+        if let Some(t) = tb {
+            self.tiles.insert(a, t);
+        }
+    }
+
+    /// Drop stored tiles that are the same as the default one
+    pub fn compact(&mut self) where
+        T : PartialEq
+    {
+        let default = &self.default;
+        self.tiles.retain(|_, t| t != default);
+    }
+
+    /// Iterator over explicitly stored Coordinates and their tiles
+    pub fn iter(&self) -> impl Iterator<Item = (Coordinate<I>, &T)> {
+        self.tiles.iter().map(|(&c, t)| (c, t))
+    }
+
+    /// Iterator over explicitly stored Coordinates and their mutable tiles
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Coordinate<I>, &mut T)> {
+        self.tiles.iter_mut().map(|(&c, t)| (c, t))
+    }
+}
+
+impl<T, I> Index<Coordinate<I>> for SparseMap<T, I> where
+    I : hex2d::Integer,
+    I : hash::Hash
+{
+    type Output = T;
+
+    fn index(&self, c : Coordinate<I>) -> &T {
+        self.get(c)
+    }
+}
+
+impl<T, I> IndexMut<Coordinate<I>> for SparseMap<T, I> where
+    I : hex2d::Integer,
+    I : hash::Hash,
+    T : Clone
+{
+    fn index_mut(&mut self, c : Coordinate<I>) -> &mut T {
+        self.get_mut(c)
+    }
+}
+
+impl<T, I> Grid<I> for SparseMap<T, I> where
+    I : hex2d::Integer,
+    I : hash::Hash
+{
+    type Tile = T;
+
+    fn get(&self, c : Coordinate<I>) -> Option<&T> {
+        Some(SparseMap::get(self, c))
+    }
+}
+
+impl<T, I> GridMut<I> for SparseMap<T, I> where
+    I : hex2d::Integer,
+    I : hash::Hash,
+    T : Clone
+{
+    fn get_mut(&mut self, c : Coordinate<I>) -> Option<&mut T> {
+        Some(SparseMap::get_mut(self, c))
+    }
+}
+
+/// Type-erased layer of `Layers`
+trait Layer<I, L> : Send + Sync where
+    I : hex2d::Integer
+{
+    fn name(&self) -> &str;
+    fn can_pass(&self, c : Coordinate<I>) -> bool;
+    fn opaqueness(&self, c : Coordinate<I>) -> L;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+struct TypedLayer<T, I, L> where
+    I : hex2d::Integer
+{
+    name : String,
+    map : HexMap<T, I>,
+    can_pass : Box<dyn Fn(&T) -> bool + Send + Sync>,
+    opaqueness : Box<dyn Fn(&T) -> L + Send + Sync>,
+}
+
+impl<T, I, L> Layer<I, L> for TypedLayer<T, I, L> where
+    T : Send + Sync + 'static,
+    I : hex2d::Integer,
+    I : Send + Sync + 'static,
+    L : 'static
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn can_pass(&self, c : Coordinate<I>) -> bool {
+        (self.can_pass)(&self.map[c])
+    }
+
+    fn opaqueness(&self, c : Coordinate<I>) -> L {
+        (self.opaqueness)(&self.map[c])
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        &self.map
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        &mut self.map
+    }
+}
+
+/// Stack of `HexMap`s of the same shape (terrain, features, items, units...)
+///
+/// Every layer knows how its tiles affect passability and opaqueness, so the whole stack
+/// can be handed to the algorithms without flattening it manually: a Coordinate can be
+/// passed if all layers agree, and its opaqueness is the sum over all layers.
+///
+/// Opaqueness can be of any `Light` type (`I` by default), independent of the Coordinate
+/// integer type. The sum is plain addition, so opaqueness of the layers must not overflow `L`
+/// when added up.
+///
+/// Tiles and closures of layers must be `Send + Sync`, so the whole stack can be shared
+/// between threads running the algorithms.
+pub struct Layers<I = i32, L = I> where
+    I : hex2d::Integer
+{
+    bounds : Bounds<I>,
+    layers : Vec<Box<dyn Layer<I, L>>>,
+}
+
+impl<I, L> Layers<I, L> where
+    I : hex2d::Integer,
+    I : Send + Sync + 'static,
+    L : Light + 'static
+{
+    /// Create an empty stack of layers covering `bounds`
+    pub fn new(bounds : Bounds<I>) -> Layers<I, L> {
+        Layers {
+            bounds,
+            layers: vec!(),
+        }
+    }
+
+    /// Region covered by the layers
+    pub fn bounds(&self) -> Bounds<I> {
+        self.bounds
+    }
+
+    /// Put `map` on top of the stack
+    ///
+    /// `can_pass` and `opaqueness` tell how tiles of this layer affect the whole stack.
+    ///
+    /// Panics if `map` doesn't cover the same `Bounds` as the stack.
+    pub fn push<T, FCanPass, FOpaqueness>(
+        &mut self,
+        name : &str,
+        map : HexMap<T, I>,
+        can_pass : FCanPass,
+        opaqueness : FOpaqueness,
+        ) where
+        T : Send + Sync + 'static,
+        FCanPass : Fn(&T) -> bool + Send + Sync + 'static,
+        FOpaqueness : Fn(&T) -> L + Send + Sync + 'static
+    {
+        assert!(map.bounds() == self.bounds, "Layers: layer bounds don't match");
+        self.layers.push(Box::new(TypedLayer {
+            name: name.to_owned(),
+            map,
+            can_pass: Box::new(can_pass),
+            opaqueness: Box::new(opaqueness),
+        }));
+    }
+
+    /// Layer called `name`, if it holds tiles of type `T`
+    pub fn layer<T : 'static>(&self, name : &str) -> Option<&HexMap<T, I>> {
+        self.layers.iter()
+            .find(|l| l.name() == name)
+            .and_then(|l| l.as_any().downcast_ref())
+    }
+
+    /// Mutable layer called `name`, if it holds tiles of type `T`
+    pub fn layer_mut<T : 'static>(&mut self, name : &str) -> Option<&mut HexMap<T, I>> {
+        self.layers.iter_mut()
+            .find(|l| l.name() == name)
+            .and_then(|l| l.as_any_mut().downcast_mut())
+    }
+
+    /// Can `c` be passed according to all the layers
+    ///
+    /// Coordinates outside of `bounds` can't be passed.
+    pub fn can_pass(&self, c : Coordinate<I>) -> bool {
+        self.bounds.contains(c) && self.layers.iter().all(|l| l.can_pass(c))
+    }
+
+    /// Total opaqueness of all the layers at `c`, or `None` if `c` is outside of `bounds`
+    pub fn opaqueness(&self, c : Coordinate<I>) -> Option<L> {
+        if !self.bounds.contains(c) {
+            return None;
+        }
+        Some(self.layers.iter().fold(L::zero(), |sum, l| sum + l.opaqueness(c)))
+    }
+
+    /// Passability closure for the algorithms
+    pub fn passable(&self) -> impl Fn(Coordinate<I>) -> bool + '_ {
+        move |c| self.can_pass(c)
+    }
+
+    /// Opaqueness closure for the algorithms, returning `outside` for Coordinates outside of
+    /// `bounds`
+    pub fn opaque(&self, outside : L) -> impl Fn(Coordinate<I>) -> L + '_ {
+        move |c| self.opaqueness(c).unwrap_or(outside)
+    }
+}
+
+/// Set of Coordinates of a bounded region, stored as bits
+///
+/// Membership tests are O(1) and set operations work on whole words, so it's much faster
+/// than `HashSet` in hot loops over FOV and reachability results.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct HexBitSet<I = i32> where
+    I : hex2d::Integer
+{
+    bounds : Bounds<I>,
+    words : Vec<u64>,
+}
+
+impl<I> HexBitSet<I> where
+    I : hex2d::Integer
+{
+    /// Create an empty set over `bounds`
+    pub fn new(bounds : Bounds<I>) -> HexBitSet<I> {
+        HexBitSet {
+            bounds,
+            words: vec!(0; bounds.len().div_ceil(64)),
+        }
+    }
+
+    /// Region that can be stored in the set
+    pub fn bounds(&self) -> Bounds<I> {
+        self.bounds
+    }
+
+    /// Number of Coordinates in the set
+    pub fn len(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    /// Is the set empty
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|&w| w == 0)
+    }
+
+    /// Does the set contain `c`
+    pub fn contains(&self, c : Coordinate<I>) -> bool {
+        match self.bounds.index(c) {
+            Some(i) => self.words[i / 64] & (1 << (i % 64)) != 0,
+            None => false,
+        }
+    }
+
+    /// Add `c` to the set
+    ///
+    /// Returns `true` if `c` was not in the set already. Coordinates outside of `bounds` can't
+    /// be stored, so they are ignored and `false` is returned.
+    pub fn insert(&mut self, c : Coordinate<I>) -> bool {
+        match self.bounds.index(c) {
+            Some(i) => {
+                let word = &mut self.words[i / 64];
+                let was = *word & (1 << (i % 64)) != 0;
+                *word |= 1 << (i % 64);
+                !was
+            },
+            None => false,
+        }
+    }
+
+    /// Remove `c` from the set
+    ///
+    /// Returns `true` if `c` was in the set.
+    pub fn remove(&mut self, c : Coordinate<I>) -> bool {
+        match self.bounds.index(c) {
+            Some(i) => {
+                let word = &mut self.words[i / 64];
+                let was = *word & (1 << (i % 64)) != 0;
+                *word &= !(1 << (i % 64));
+                was
+            },
+            None => false,
+        }
+    }
+
+    /// Remove all Coordinates from the set
+    pub fn clear(&mut self) {
+        for w in &mut self.words {
+            *w = 0;
+        }
+    }
+
+    fn combine_with<F>(&mut self, other : &HexBitSet<I>, f : F) where
+        F : Fn(u64, u64) -> u64
+    {
+        assert!(self.bounds == other.bounds, "HexBitSet: sets bounds don't match");
+        for (a, &b) in self.words.iter_mut().zip(other.words.iter()) {
+            *a = f(*a, b);
+        }
+    }
+
+    /// Add all Coordinates of `other` to the set
+    ///
+    /// Panics if sets don't have the same `Bounds`.
+    pub fn union_with(&mut self, other : &HexBitSet<I>) {
+        self.combine_with(other, |a, b| a | b)
+    }
+
+    /// Keep only Coordinates that are also in `other`
+    ///
+    /// Panics if sets don't have the same `Bounds`.
+    pub fn intersect_with(&mut self, other : &HexBitSet<I>) {
+        self.combine_with(other, |a, b| a & b)
+    }
+
+    /// Remove all Coordinates of `other` from the set
+    ///
+    /// Panics if sets don't have the same `Bounds`.
+    pub fn difference_with(&mut self, other : &HexBitSet<I>) {
+        self.combine_with(other, |a, b| a & !b)
+    }
+
+    /// Iterator over Coordinates in the set, in `Bounds` iteration order
+    pub fn iter(&self) -> impl Iterator<Item = Coordinate<I>> + '_ {
+        self.bounds.iter()
+            .enumerate()
+            .filter(move |&(i, _)| self.words[i / 64] & (1 << (i % 64)) != 0)
+            .map(|(_, c)| c)
+    }
+}
+
+impl<I> Extend<Coordinate<I>> for HexBitSet<I> where
+    I : hex2d::Integer
+{
+    /// Insert all Coordinates, ignoring those outside of `bounds`
+    fn extend<It : IntoIterator<Item = Coordinate<I>>>(&mut self, iter : It) {
+        for c in iter {
+            self.insert(c);
+        }
+    }
+}
+
+/// Change log of map edits
+///
+/// Every edit reported with `touch` bumps the version and records the Coordinate. Caches of
+/// algorithm results (`fov::FovMap`, `apsp::AllPairs`, `astar::GoalBounds`,
+/// `astar::Landmarks`) record the version they were computed at with `set_revision`, and their
+/// `is_stale` checks whether any edit since then affects them, instead of being invalidated
+/// manually.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MapRevision<I = i32> where
+    I : hex2d::Integer,
+    I : hash::Hash
+{
+    version : u64,
+    changed : InternalHashMap<Coordinate<I>, u64>,
+}
+
+impl<I> MapRevision<I> where
+    I : hex2d::Integer,
+    I : hash::Hash
+{
+    /// Create a change log at version `0`
+    pub fn new() -> MapRevision<I> {
+        MapRevision {
+            version: 0,
+            changed: InternalHashMap::default(),
+        }
+    }
+
+    /// Current version, increasing with every edit
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Record an edit of `c`
+    pub fn touch(&mut self, c : Coordinate<I>) {
+        self.version += 1;
+        self.changed.insert(c, self.version);
+    }
+
+    /// Was `c` edited after `version`
+    pub fn is_changed_since(&self, version : u64, c : Coordinate<I>) -> bool {
+        self.changed.get(&c).is_some_and(|&v| v > version)
+    }
+
+    /// Was anything edited after `version`
+    pub fn is_dirty_since(&self, version : u64) -> bool {
+        self.version > version
+    }
+
+    /// Coordinates edited after `version`
+    pub fn changed_since(&self, version : u64) -> impl Iterator<Item = Coordinate<I>> + '_ {
+        self.changed.iter().filter(move |&(_, &v)| v > version).map(|(&c, _)| c)
+    }
+
+    /// Forget edits made up to `version`, eg. once all the caches caught up
+    pub fn compact(&mut self, version : u64) {
+        self.changed.retain(|_, &mut v| v > version);
+    }
+}
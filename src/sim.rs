@@ -0,0 +1,440 @@
+// Copyright 2014 Dawid Ciężarkiewicz
+// See LICENSE file for more information
+
+/// Gas and fluid spreading
+pub mod fluid {
+    use hex2d;
+    use hex2d::Coordinate;
+
+    use map::HexMap;
+
+    /// Fluid simulation configuration
+    #[derive(Clone, Debug)]
+    pub struct Config {
+        /// Fraction of the level difference evened out between fully permeable neighbors
+        /// with every tick, from `0.0` to `1.0`
+        pub rate : f32,
+    }
+
+    impl Default for Config {
+        fn default() -> Config {
+            Config {
+                rate: 0.5,
+            }
+        }
+    }
+
+    /// Advance the spreading of `amount` by one tick
+    ///
+    /// The level of a Coordinate is its `amount` plus its `height`, if given: gas spreads
+    /// evenly, while water flows downhill and fills up the lowest areas. Quantity flows to
+    /// every neighbor with a lower level, slowed down by the smaller `permeability` of the
+    /// two Coordinates: `1.0` for open space, `0.0` for walls. No Coordinate gives more than
+    /// it has, and nothing flows outside of the map, so the total amount never changes.
+    ///
+    /// Panics if `height` doesn't cover the same `Bounds` as `amount`.
+    pub fn tick<FPermeability, I>(
+        amount : &mut HexMap<f32, I>,
+        height : Option<&HexMap<f32, I>>,
+        permeability : FPermeability,
+        config : &Config,
+        ) where
+        I : hex2d::Integer,
+        FPermeability : Fn(Coordinate<I>) -> f32
+    {
+        if let Some(height) = height {
+            assert!(height.bounds() == amount.bounds(), "fluid::tick: height bounds don't match");
+        }
+        let level = |c : Coordinate<I>, a : f32| a + height.map_or(0.0, |h| h[c]);
+
+        let mut delta = HexMap::new(amount.bounds(), 0.0);
+
+        for (c, &a) in amount.iter() {
+            if a <= 0.0 {
+                continue;
+            }
+            let l = level(c, a);
+            let p = permeability(c);
+
+            let mut flows = [(c, 0.0); 6];
+            let mut total = 0.0;
+            for (flow, &n) in flows.iter_mut().zip(c.neighbors().iter()) {
+                if let Some(&na) = amount.get(n) {
+                    let diff = l - level(n, na);
+                    if diff > 0.0 {
+                        let f = diff * config.rate * p.min(permeability(n)) / 6.0;
+                        *flow = (n, f);
+                        total += f;
+                    }
+                }
+            }
+
+            let scale = if total > a { a / total } else { 1.0 };
+            for &(n, f) in flows.iter().filter(|&&(_, f)| f > 0.0) {
+                delta[c] -= f * scale;
+                delta[n] += f * scale;
+            }
+        }
+
+        for (c, a) in amount.iter_mut() {
+            // Rounding errors could leave tiny negative amounts behind
+            *a = (*a + delta[c]).max(0.0);
+        }
+    }
+}
+
+/// Fire spreading over flammable terrain
+pub mod fire {
+    use hex2d;
+    use hex2d::Coordinate;
+
+    use rand::Rng;
+
+    use map::HexMap;
+
+    /// State of a Coordinate
+    #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub enum State {
+        /// Not burning (yet)
+        Unburnt,
+        /// On fire
+        Burning,
+        /// Burnt out, can't burn again
+        Burnt,
+    }
+
+    /// Fire over a map of flammability and fuel
+    #[derive(Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct Fire<I = i32> where
+        I : hex2d::Integer
+    {
+        state : HexMap<State, I>,
+        flammability : HexMap<f32, I>,
+        fuel : HexMap<u32, I>,
+    }
+
+    impl<I> Fire<I> where
+        I : hex2d::Integer
+    {
+        /// Create a Fire with nothing burning yet
+        ///
+        /// `flammability` is the chance (from `0.0` to `1.0`) that a Coordinate catches fire
+        /// from each burning neighbor in a single tick. `fuel` is the number of ticks it burns
+        /// for; Coordinates without fuel never burn. Flammability outside of the `[0.0, 1.0]`
+        /// range is clamped to it, and NaN counts as `0.0`.
+        ///
+        /// Panics if maps don't cover the same `Bounds`.
+        pub fn new(mut flammability : HexMap<f32, I>, fuel : HexMap<u32, I>) -> Fire<I> {
+            assert!(flammability.bounds() == fuel.bounds(), "Fire::new: maps bounds don't match");
+            for (_, f) in flammability.iter_mut() {
+                *f = if f.is_nan() { 0.0 } else { f.clamp(0.0, 1.0) };
+            }
+            Fire {
+                state: HexMap::new(fuel.bounds(), State::Unburnt),
+                flammability,
+                fuel,
+            }
+        }
+
+        /// Set `c` on fire
+        ///
+        /// Returns false if it can't burn: it's outside of the map, already burning or burnt,
+        /// or has no fuel.
+        pub fn ignite(&mut self, c : Coordinate<I>) -> bool {
+            let can_burn = self.state.get(c) == Some(&State::Unburnt) && self.fuel[c] > 0;
+            if can_burn {
+                self.state[c] = State::Burning;
+            }
+            can_burn
+        }
+
+        /// State of `c`, or `None` if it's outside of the map
+        pub fn state(&self, c : Coordinate<I>) -> Option<State> {
+            self.state.get(c).cloned()
+        }
+
+        /// Remaining fuel of `c`, or `None` if it's outside of the map
+        pub fn fuel(&self, c : Coordinate<I>) -> Option<u32> {
+            self.fuel.get(c).cloned()
+        }
+
+        /// States of all Coordinates
+        pub fn states(&self) -> &HexMap<State, I> {
+            &self.state
+        }
+
+        /// Is anything still burning
+        pub fn is_burning(&self) -> bool {
+            self.state.iter().any(|(_, &s)| s == State::Burning)
+        }
+
+        /// Advance the fire by one tick
+        ///
+        /// Burning Coordinates use up a unit of fuel, and burn out when there's none left.
+        /// Unburnt ones catch fire from their burning neighbors. Returns the Coordinates whose
+        /// state changed, with their new state, in `Bounds` iteration order. The same `rng`
+        /// state always gives the same result.
+        pub fn tick<R>(&mut self, rng : &mut R) -> Vec<(Coordinate<I>, State)> where
+            R : Rng
+        {
+            let mut changes = vec!();
+
+            for (c, &state) in self.state.iter() {
+                match state {
+                    State::Burning => {
+                        self.fuel[c] -= 1;
+                        if self.fuel[c] == 0 {
+                            changes.push((c, State::Burnt));
+                        }
+                    },
+                    State::Unburnt if self.fuel[c] > 0 => {
+                        let chance = self.flammability[c] as f64;
+                        let burning = c.neighbors().iter()
+                            .filter(|&&n| self.state.get(n) == Some(&State::Burning))
+                            .count();
+                        if (0..burning).any(|_| rng.gen_bool(chance)) {
+                            changes.push((c, State::Burning));
+                        }
+                    },
+                    _ => {},
+                }
+            }
+
+            for &(c, state) in changes.iter() {
+                self.state[c] = state;
+            }
+            changes
+        }
+    }
+}
+
+/// Territory growth of competing factions
+pub mod territory {
+    use hex2d;
+    use hex2d::Coordinate;
+
+    use rand::Rng;
+
+    use std::hash;
+
+    use geom::Bounds;
+    use hashing::InternalHashMap;
+    use map::HexMap;
+
+    /// Faction identifier, in the order factions were added
+    #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Ord, PartialOrd)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct FactionId(pub u16);
+
+    /// How to resolve Coordinates claimed by several factions at once
+    #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub enum Contest {
+        /// The faction with the highest rate wins, the first added one on ties
+        Fastest,
+        /// A random claiming faction wins
+        Random,
+    }
+
+    #[derive(Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    struct Faction<I = i32> where
+        I : hex2d::Integer
+    {
+        rate : f32,
+        progress : f32,
+        frontier : Vec<Coordinate<I>>,
+    }
+
+    /// Ownership of a map by factions expanding from their seeds
+    ///
+    /// Every tick each faction grows by `rate` steps (fractional rates accumulate), like
+    /// a multi-source BFS in which every source has its own speed.
+    #[derive(Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct Territory<I = i32> where
+        I : hex2d::Integer
+    {
+        owner : HexMap<Option<FactionId>, I>,
+        factions : Vec<Faction<I>>,
+        contest : Contest,
+    }
+
+    impl<I> Territory<I> where
+        I : hex2d::Integer,
+        I : hash::Hash
+    {
+        /// Create a Territory of `bounds` without any factions
+        pub fn new(bounds : Bounds<I>, contest : Contest) -> Territory<I> {
+            Territory {
+                owner: HexMap::new(bounds, None),
+                factions: vec!(),
+                contest,
+            }
+        }
+
+        /// Add a faction owning `seeds` and growing `rate` steps per tick
+        ///
+        /// Seeds outside of the map or already owned are skipped.
+        pub fn add_faction(&mut self, seeds : &[Coordinate<I>], rate : f32) -> FactionId {
+            let id = FactionId(self.factions.len() as u16);
+            let mut frontier = vec!();
+            for &c in seeds {
+                if self.owner.get(c) == Some(&None) {
+                    self.owner[c] = Some(id);
+                    frontier.push(c);
+                }
+            }
+            self.factions.push(Faction { rate, progress: 0.0, frontier });
+            id
+        }
+
+        /// Owner of `c`, if any
+        pub fn owner(&self, c : Coordinate<I>) -> Option<FactionId> {
+            self.owner.get(c).cloned().unwrap_or(None)
+        }
+
+        /// Owners of all Coordinates
+        pub fn owners(&self) -> &HexMap<Option<FactionId>, I> {
+            &self.owner
+        }
+
+        /// Can any faction still grow
+        pub fn is_growing(&self) -> bool {
+            self.factions.iter().any(|f| !f.frontier.is_empty())
+        }
+
+        /// Advance growth by one tick
+        ///
+        /// Factions grow only into unowned Coordinates for which `can_grow` returns true.
+        /// Returns newly claimed Coordinates with their owners, in the order they were
+        /// claimed. The same `rng` state always gives the same result.
+        pub fn tick<FCanGrow, R>(&mut self, mut can_grow : FCanGrow, rng : &mut R) -> Vec<(Coordinate<I>, FactionId)> where
+            FCanGrow : FnMut(Coordinate<I>) -> bool,
+            R : Rng
+        {
+            let steps : Vec<u32> = self.factions.iter_mut().map(|f| {
+                f.progress += f.rate;
+                let steps = f.progress.floor();
+                f.progress -= steps;
+                steps as u32
+            }).collect();
+
+            let mut changes = vec!();
+            for step in 0..steps.iter().cloned().max().unwrap_or(0) {
+                // Claimed Coordinates, in the order of first claim, with claiming factions
+                let mut claims : Vec<(Coordinate<I>, Vec<usize>)> = vec!();
+                let mut index = InternalHashMap::default();
+
+                for (i, faction) in self.factions.iter().enumerate().filter(|&(i, _)| steps[i] > step) {
+                    for &c in faction.frontier.iter() {
+                        for &n in c.neighbors().iter() {
+                            if self.owner.get(n) != Some(&None) || !can_grow(n) {
+                                continue;
+                            }
+                            let j = *index.entry(n).or_insert_with(|| {
+                                claims.push((n, vec!()));
+                                claims.len() - 1
+                            });
+                            if claims[j].1.last() != Some(&i) {
+                                claims[j].1.push(i);
+                            }
+                        }
+                    }
+                }
+
+                for (i, faction) in self.factions.iter_mut().enumerate() {
+                    if steps[i] > step {
+                        faction.frontier.clear();
+                    }
+                }
+
+                for (c, claimants) in claims {
+                    let winner = match self.contest {
+                        Contest::Random => claimants[rng.gen_range(0..claimants.len())],
+                        Contest::Fastest => claimants.iter().cloned().fold(claimants[0], |best, i| {
+                            if self.factions[i].rate > self.factions[best].rate { i } else { best }
+                        }),
+                    };
+                    let id = FactionId(winner as u16);
+                    self.owner[c] = Some(id);
+                    self.factions[winner].frontier.push(c);
+                    changes.push((c, id));
+                }
+            }
+            changes
+        }
+    }
+}
+
+/// Projectile flight
+pub mod projectile {
+    use hex2d;
+    use hex2d::Coordinate;
+    use hex2d::Direction;
+
+    /// Where a projectile is shot
+    #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+    pub enum Aim<I = i32> where
+        I : hex2d::Integer
+    {
+        /// Toward a Coordinate, and further past it
+        At(Coordinate<I>),
+        /// Straight in a Direction
+        Toward(Direction),
+    }
+
+    impl<I> From<Coordinate<I>> for Aim<I> where
+        I : hex2d::Integer
+    {
+        fn from(c : Coordinate<I>) -> Aim<I> {
+            Aim::At(c)
+        }
+    }
+
+    impl<I> From<Direction> for Aim<I> where
+        I : hex2d::Integer
+    {
+        fn from(dir : Direction) -> Aim<I> {
+            Aim::Toward(dir)
+        }
+    }
+
+    /// Fly a projectile from `from` along `aim`, up to `max_range` steps
+    ///
+    /// `hit` is called for every Coordinate the projectile passes, in order, and returns
+    /// true if the projectile collides there. Returns the Coordinate of the impact, or `None`
+    /// if nothing was hit in range.
+    ///
+    /// The straight line is traversed the same way as in `los_ref`: where it runs exactly
+    /// between two Coordinates, `hit` is called for both, and the projectile slips through
+    /// unless both collide.
+    pub fn trace<A, FHit, I>(from : Coordinate<I>, aim : A, max_range : I, mut hit : FHit) -> Option<Coordinate<I>> where
+        I : hex2d::Integer,
+        I : ::std::ops::AddAssign,
+        A : Into<Aim<I>>,
+        FHit : FnMut(Coordinate<I>) -> bool
+    {
+        let far = match aim.into() {
+            Aim::At(to) if to == from => return None,
+            Aim::At(to) => {
+                let dist = from.distance(to);
+                let times = (max_range + dist - I::one()) / dist;
+                from + (to - from).scale(times)
+            },
+            Aim::Toward(dir) => from + Coordinate::from(dir).scale(max_range),
+        };
+
+        let range = max_range.to_usize().unwrap_or(0);
+        for (c1, c2) in from.line_to_with_edge_detection_iter(far).skip(1).take(range) {
+            let hit1 = hit(c1);
+            let hit2 = if c1 == c2 { hit1 } else { hit(c2) };
+            if hit1 && hit2 {
+                return Some(c1);
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,392 @@
+// Copyright 2014 Dawid Ciężarkiewicz
+// See LICENSE file for more information
+
+use hex2d;
+use hex2d::Coordinate;
+
+use std::cmp;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::hash;
+
+use geom::Bounds;
+use hashing::OutputHashSet;
+use map::HexMap;
+
+/// Largest number of Coordinates a decoded snapshot can cover
+///
+/// Snapshots often come from the network; bigger ones are rejected before anything is
+/// allocated for them.
+pub const MAX_LEN : usize = 1 << 24;
+
+/// Tile that can be stored in a snapshot
+///
+/// Small codes take less space, so the most common tiles should get the smallest ones.
+pub trait Code : Copy + PartialEq {
+    /// Numeric code of the tile
+    fn to_code(self) -> u64;
+
+    /// Tile with a given code, or `None` if the code is not valid
+    fn from_code(code : u64) -> Option<Self>;
+}
+
+macro_rules! unsigned_code {
+    ($($t:ty),*) => { $(
+        impl Code for $t {
+            fn to_code(self) -> u64 {
+                self as u64
+            }
+
+            fn from_code(code : u64) -> Option<Self> {
+                <$t>::try_from(code).ok()
+            }
+        }
+    )* }
+}
+
+macro_rules! signed_code {
+    ($($t:ty),*) => { $(
+        impl Code for $t {
+            fn to_code(self) -> u64 {
+                zigzag(self as i64)
+            }
+
+            fn from_code(code : u64) -> Option<Self> {
+                <$t>::try_from(unzigzag(code)).ok()
+            }
+        }
+    )* }
+}
+
+unsigned_code!(u8, u16, u32, u64, usize);
+signed_code!(i8, i16, i32, i64, isize);
+
+impl Code for bool {
+    fn to_code(self) -> u64 {
+        self as u64
+    }
+
+    fn from_code(code : u64) -> Option<Self> {
+        match code {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+}
+
+fn zigzag(v : i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+fn unzigzag(v : u64) -> i64 {
+    ((v >> 1) as i64) ^ -((v & 1) as i64)
+}
+
+fn write_varint(out : &mut Vec<u8>, mut v : u64) {
+    while v >= 0x80 {
+        out.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn read_varint(input : &mut &[u8]) -> Option<u64> {
+    let mut v = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = input.split_first()?;
+        *input = rest;
+        v |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(v);
+        }
+    }
+    None
+}
+
+fn write_int<I>(out : &mut Vec<u8>, v : I) where
+    I : hex2d::Integer
+{
+    write_varint(out, zigzag(v.to_i64().unwrap()));
+}
+
+fn read_int<I>(input : &mut &[u8]) -> Option<I> where
+    I : hex2d::Integer
+{
+    I::from_i64(unzigzag(read_varint(input)?))
+}
+
+fn write_bounds<I>(out : &mut Vec<u8>, bounds : Bounds<I>) where
+    I : hex2d::Integer
+{
+    match bounds {
+        Bounds::Hexagon { center, radius } => {
+            out.push(0);
+            write_int(out, center.x);
+            write_int(out, center.y);
+            write_int(out, radius);
+        },
+        Bounds::Rectangle { origin, width, height } => {
+            out.push(1);
+            write_int(out, origin.x);
+            write_int(out, origin.y);
+            write_int(out, width);
+            write_int(out, height);
+        },
+        Bounds::Ring { center, inner, outer } => {
+            out.push(2);
+            write_int(out, center.x);
+            write_int(out, center.y);
+            write_int(out, inner);
+            write_int(out, outer);
+        },
+    }
+}
+
+/// Read a dimension (radius, width...) of `Bounds`, rejecting negative and oversized ones
+fn read_dimension<I>(input : &mut &[u8]) -> Option<I> where
+    I : hex2d::Integer
+{
+    let v : I = read_int(input)?;
+    let v64 = v.to_i64().unwrap();
+    if v64 < 0 || v64 > MAX_LEN as i64 {
+        return None;
+    }
+    Some(v)
+}
+
+/// Can a Coordinate with `x` and `z` be represented with `I`
+fn fits<I>(x : i64, z : i64) -> bool where
+    I : hex2d::Integer
+{
+    let y = match x.checked_neg().and_then(|nx| nx.checked_sub(z)) {
+        Some(y) => y,
+        None => return false,
+    };
+    I::from_i64(x).is_some() && I::from_i64(y).is_some() && I::from_i64(z).is_some()
+}
+
+/// Do all Coordinates of `bounds` fit in `I`
+///
+/// Every coordinate axis is linear along the rows of a region, so it's enough to check its
+/// corners.
+fn fits_bounds<I>(bounds : Bounds<I>) -> bool where
+    I : hex2d::Integer
+{
+    let (c, corners) = match bounds {
+        Bounds::Hexagon { center: c, radius: r } | Bounds::Ring { center: c, outer: r, .. } => {
+            let r = r.to_i64().unwrap();
+            (c, vec!((r, -r), (r, 0), (0, r), (-r, r), (-r, 0), (0, -r)))
+        },
+        Bounds::Rectangle { origin: c, width, height } => {
+            let (w, h) = (width.to_i64().unwrap(), height.to_i64().unwrap());
+            let last = cmp::max(w - 1, 0);
+            let shift = -(cmp::max(h - 1, 0) / 2);
+            (c, vec!((0, 0), (last, 0), (shift, cmp::max(h - 1, 0)), (shift + last, cmp::max(h - 1, 0))))
+        },
+    };
+
+    let x = c.x.to_i64().unwrap();
+    let z = match x.checked_neg().and_then(|nx| nx.checked_sub(c.y.to_i64().unwrap())) {
+        Some(z) => z,
+        None => return false,
+    };
+    fits::<I>(x, z) && (bounds.is_empty() || corners.iter().all(|&(dx, dz)| {
+        match (x.checked_add(dx), z.checked_add(dz)) {
+            (Some(x), Some(z)) => fits::<I>(x, z),
+            _ => false,
+        }
+    }))
+}
+
+fn read_bounds<I>(input : &mut &[u8]) -> Option<Bounds<I>> where
+    I : hex2d::Integer
+{
+    let (&tag, rest) = input.split_first()?;
+    *input = rest;
+    let c = Coordinate::new(read_int(input)?, read_int(input)?);
+    let bounds = match tag {
+        0 => Bounds::hexagon(c, read_dimension(input)?),
+        1 => Bounds::rectangle(c, read_dimension(input)?, read_dimension(input)?),
+        2 => Bounds::ring(c, read_dimension(input)?, read_dimension(input)?),
+        _ => return None,
+    };
+    if !fits_bounds(bounds) || bounds.len() > MAX_LEN {
+        return None;
+    }
+    Some(bounds)
+}
+
+/// Write `values` as `(run length, value)` pairs, with values written by `write_value`
+fn write_runs_with<T, It, F>(out : &mut Vec<u8>, values : It, write_value : F) where
+    T : PartialEq,
+    It : Iterator<Item = T>,
+    F : Fn(&mut Vec<u8>, T)
+{
+    let mut run : Option<(u64, T)> = None;
+    for value in values {
+        run = match run {
+            Some((len, prev)) if prev == value => Some((len + 1, prev)),
+            Some((len, prev)) => {
+                write_varint(out, len);
+                write_value(out, prev);
+                Some((1, value))
+            },
+            None => Some((1, value)),
+        }
+    }
+    if let Some((len, prev)) = run {
+        write_varint(out, len);
+        write_value(out, prev);
+    }
+}
+
+/// Read exactly `len` values written by `write_runs_with`
+///
+/// Run lengths are checked against `len` before anything is allocated for them.
+fn read_runs_with<T, F>(input : &mut &[u8], len : usize, read_value : F) -> Option<Vec<T>> where
+    T : Clone,
+    F : Fn(&mut &[u8]) -> Option<T>
+{
+    let mut values = vec!();
+    while values.len() < len {
+        let run = read_varint(input)?;
+        let value = read_value(input)?;
+        if run == 0 || run > (len - values.len()) as u64 {
+            return None;
+        }
+        values.resize(values.len() + run as usize, value);
+    }
+    Some(values)
+}
+
+/// Write `codes` as `(run length, code)` pairs
+fn write_runs<It>(out : &mut Vec<u8>, codes : It) where
+    It : Iterator<Item = u64>
+{
+    write_runs_with(out, codes, write_varint);
+}
+
+/// Read exactly `len` codes written by `write_runs`
+fn read_runs(input : &mut &[u8], len : usize) -> Option<Vec<u64>> {
+    read_runs_with(input, len, read_varint)
+}
+
+/// Encode `map` into a compact binary snapshot
+pub fn encode_map<T, I>(map : &HexMap<T, I>) -> Vec<u8> where
+    T : Code,
+    I : hex2d::Integer
+{
+    let mut out = vec!();
+    write_bounds(&mut out, map.bounds());
+    write_runs(&mut out, map.iter().map(|(_, &t)| t.to_code()));
+    out
+}
+
+/// Decode a map encoded with `encode_map`
+///
+/// Returns `None` if `bytes` are not a valid snapshot.
+pub fn decode_map<T, I>(mut bytes : &[u8]) -> Option<HexMap<T, I>> where
+    T : Code,
+    I : hex2d::Integer
+{
+    let input = &mut bytes;
+    let bounds = read_bounds(input)?;
+    let tiles = read_runs(input, bounds.len())?
+        .into_iter()
+        .map(T::from_code)
+        .collect::<Option<Vec<T>>>()?;
+    if !input.is_empty() {
+        return None;
+    }
+
+    let mut tiles = tiles.into_iter();
+    Some(HexMap::from_fn(bounds, |_| tiles.next().unwrap()))
+}
+
+/// Encode Coordinates of `set` that are inside `bounds` into a compact binary snapshot
+///
+/// Good for fog-of-war and explored areas, which tend to form large continuous regions.
+pub fn encode_set<I, S>(bounds : Bounds<I>, set : &HashSet<Coordinate<I>, S>) -> Vec<u8> where
+    I : hex2d::Integer,
+    I : hash::Hash,
+    S : hash::BuildHasher
+{
+    let mut out = vec!();
+    write_bounds(&mut out, bounds);
+    write_runs(&mut out, bounds.iter().map(|c| set.contains(&c) as u64));
+    out
+}
+
+/// Decode a set encoded with `encode_set`, along with its `Bounds`
+///
+/// Returns `None` if `bytes` are not a valid snapshot.
+pub fn decode_set<I>(mut bytes : &[u8]) -> Option<(Bounds<I>, OutputHashSet<Coordinate<I>>)> where
+    I : hex2d::Integer,
+    I : hash::Hash
+{
+    let input = &mut bytes;
+    let bounds : Bounds<I> = read_bounds(input)?;
+    let codes = read_runs(input, bounds.len())?;
+    if !input.is_empty() || codes.iter().any(|&code| code > 1) {
+        return None;
+    }
+
+    let set = bounds.iter()
+        .zip(codes)
+        .filter(|&(_, code)| code == 1)
+        .map(|(c, _)| c)
+        .collect();
+    Some((bounds, set))
+}
+
+/// Encode changes needed to turn `from` into `to`
+///
+/// Every run is marked either unchanged, or changed to a given tile. Unchanged tiles cost
+/// almost nothing, so diffs of mostly static maps are tiny.
+///
+/// Panics if maps don't cover the same `Bounds`.
+pub fn encode_diff<T, I>(from : &HexMap<T, I>, to : &HexMap<T, I>) -> Vec<u8> where
+    T : Code,
+    I : hex2d::Integer
+{
+    assert!(from.bounds() == to.bounds(), "encode_diff: maps bounds don't match");
+    let mut out = vec!();
+    let changes = from.iter().zip(to.iter()).map(|((_, &a), (_, &b))| {
+        if a == b { None } else { Some(b.to_code()) }
+    });
+    write_runs_with(&mut out, changes, |out, change| match change {
+        None => write_varint(out, 0),
+        Some(code) => {
+            write_varint(out, 1);
+            write_varint(out, code);
+        },
+    });
+    out
+}
+
+/// Apply a diff encoded with `encode_diff` to `map`
+///
+/// Returns `false` and leaves `map` untouched if `bytes` are not a valid diff for it.
+pub fn apply_diff<T, I>(map : &mut HexMap<T, I>, mut bytes : &[u8]) -> bool where
+    T : Code,
+    I : hex2d::Integer
+{
+    let input = &mut bytes;
+    let changes = read_runs_with(input, map.len(), |input| match read_varint(input)? {
+        0 => Some(None),
+        1 => T::from_code(read_varint(input)?).map(Some),
+        _ => None,
+    });
+    let changes = match changes {
+        Some(ref changes) if input.is_empty() => changes,
+        _ => return false,
+    };
+
+    for ((_, tile), change) in map.iter_mut().zip(changes) {
+        if let Some(t) = *change {
+            *tile = t;
+        }
+    }
+    true
+}
@@ -1,2 +1,2965 @@
 // Copyright 2014 Dawid Ciężarkiewicz
 // See LICENSE file for more information
+
+use hex2d::Coordinate;
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use algo::distance;
+
+#[test]
+fn distance_from_obstacles_open_hexagon() {
+    let center = Coordinate::new(0, 0);
+
+    let field = distance::from_obstacles(|_| true, center.range_iter(3));
+
+    assert_eq!(field.len(), 37);
+    for (&c, &d) in field.iter() {
+        assert_eq!(d as i32, 4 - center.distance(c));
+    }
+}
+
+#[test]
+fn distance_from_obstacles_skips_walls() {
+    let center = Coordinate::new(0, 0);
+    let wall = Coordinate::new(1, 0);
+
+    let field = distance::from_obstacles(|c| c != wall, center.range_iter(3));
+
+    assert!(!field.contains_key(&wall));
+    assert_eq!(field[&center], 1);
+}
+
+#[test]
+fn influence_combine_and_decay() {
+    use algo::influence::{InfluenceMap, Falloff};
+
+    let friend = Coordinate::new(0, 0);
+    let enemy = Coordinate::new(4, 0);
+
+    let mut friendly = InfluenceMap::new();
+    friendly.add_source(friend, 10.0, Falloff::Linear(0.25), 10, |_| true);
+    let mut hostile = InfluenceMap::new();
+    hostile.add_source(enemy, 10.0, Falloff::Exponential(0.5), 10, |_| true);
+
+    assert_eq!(friendly.get(Coordinate::new(1, 0)), 7.5);
+    assert_eq!(friendly.get(Coordinate::new(4, 0)), 0.0);
+
+    let balance = friendly - &hostile;
+    assert_eq!(balance.best().unwrap().0, friend);
+    assert!(balance.get(enemy) < 0.0);
+
+    hostile.decay(0.5);
+    assert_eq!(hostile.get(enemy), 5.0);
+}
+
+#[test]
+fn influence_threat_cost() {
+    use algo::dijkstra;
+    use algo::influence::{InfluenceMap, Falloff};
+
+    let start = Coordinate::new(0, 0);
+    let dest = Coordinate::new(6, 0);
+    let enemy = Coordinate::new(3, 0);
+
+    let mut threat = InfluenceMap::new();
+    threat.add_source(enemy, -20.0, Falloff::Linear(0.25), 3, |_| true);
+
+    let path = |bravery| {
+        let cost = |_, to : Coordinate| if to.distance(start) <= 8 { Some(1) } else { None };
+        let mut search = dijkstra::Traverser::new(threat.threat_cost(cost, bravery), |c| c == dest, start);
+        assert_eq!(search.find(), Some(dest));
+        search.path(dest).unwrap()
+    };
+
+    let brave = path(1.0);
+    assert_eq!(brave.len(), 7);
+    assert!(brave.contains(&enemy));
+
+    let coward = path(0.0);
+    assert!(coward.len() > 7);
+    assert!(coward.iter().all(|c| c.distance(enemy) >= 2));
+}
+
+#[test]
+fn sound_goes_around_thick_walls() {
+    use algo::sound;
+    use std::collections::HashMap;
+
+    let start = Coordinate::new(0, 0);
+    let wall = Coordinate::new(1, 0);
+    let mut heard = HashMap::new();
+
+    sound::propagate(&|c| if c == wall { 100 } else { 0 }, &mut |c, v| { heard.insert(c, v); }, 10, 1, start);
+
+    assert_eq!(heard[&start], 10);
+    assert!(!heard.contains_key(&wall));
+    assert_eq!(heard[&Coordinate::new(2, 0)], 7);
+}
+
+#[test]
+fn scent_can_be_followed() {
+    use algo::scent::ScentMap;
+
+    let source = Coordinate::new(3, 0);
+    let mut scent = ScentMap::new();
+
+    for _ in 0..5 {
+        scent.deposit(source, 1.0);
+        scent.tick(0.5, 0.1, |_| true);
+    }
+
+    let mut pos = Coordinate::new(0, 0);
+    while let Some(dir) = scent.follow(pos) {
+        pos = pos + dir;
+    }
+    assert_eq!(pos, source);
+}
+
+#[test]
+fn geom_close_fills_holes() {
+    use geom;
+    use std::collections::HashSet;
+
+    let center = Coordinate::new(0, 0);
+    let hole = Coordinate::new(1, 0);
+    let set : HashSet<_> = center.range_iter(3).filter(|&c| c != hole).collect();
+
+    assert!(geom::close(&set).contains(&hole));
+    assert!(geom::open(&set).is_subset(&set));
+    assert_eq!(geom::erode(&geom::dilate(&set)), geom::close(&set));
+}
+
+#[test]
+fn geom_perimeter_is_continuous() {
+    use geom;
+    use std::collections::HashSet;
+
+    let set : HashSet<_> = Coordinate::new(0, 0).range_iter(2).collect();
+    let outline = geom::perimeter(&set);
+
+    assert_eq!(outline.len(), 30);
+    for w in outline.windows(2) {
+        let ((c1, _), (c2, _)) = (w[0], w[1]);
+        assert!(c1 == c2 || c1.distance(c2) == 1);
+    }
+}
+
+#[test]
+fn geom_transform_orients_templates() {
+    use geom::{Transform, Axis};
+    use hex2d::Direction;
+
+    let cone = [Coordinate::new(0, 1), Coordinate::new(0, 2), Coordinate::new(-1, 2), Coordinate::new(1, 1)];
+
+    for &dir in Direction::all().iter() {
+        let t = Transform::facing(Direction::YZ, dir);
+        assert_eq!(t.apply(Coordinate::new(0, 1)), Coordinate::from(dir));
+        assert_eq!(t.apply_dir(Direction::YZ), dir);
+        assert_eq!(t.mirrored(Axis::X).apply_all(&cone).len(), cone.len());
+    }
+
+    for &t in Transform::all().iter() {
+        for &c in cone.iter() {
+            assert_eq!(t.apply(c).distance(Coordinate::new(0, 0)), c.distance(Coordinate::new(0, 0)));
+        }
+    }
+}
+
+#[test]
+fn geom_bounds_index_matches_iteration() {
+    use geom::Bounds;
+    use map::HexMap;
+
+    let center = Coordinate::new(2, -5);
+    for bounds in [Bounds::hexagon(center, 4), Bounds::rectangle(center, 5, 7)].iter() {
+        let all : Vec<_> = bounds.iter().collect();
+        assert_eq!(all.len(), bounds.len());
+        for (i, &c) in all.iter().enumerate() {
+            assert_eq!(bounds.index(c), Some(i));
+        }
+    }
+
+    let hexagon = Bounds::hexagon(center, 4);
+    assert_eq!(hexagon.len(), 61);
+    for c in center.range_iter(6) {
+        assert_eq!(hexagon.contains(c), center.distance(c) <= 4);
+    }
+
+    // Negative dimensions make empty regions
+    for &(w, h) in [(-3, 4), (4, -3), (-3, -4), (0, 4)].iter() {
+        let empty = Bounds::rectangle(center, w, h);
+        assert_eq!(empty.len(), 0);
+        assert_eq!(empty.iter().count(), 0);
+        assert!(!empty.contains(center));
+    }
+    assert!(Bounds::hexagon(center, -2).is_empty());
+    assert!(HexMap::new(Bounds::rectangle(center, -1, 5), 0u8).is_empty());
+}
+
+#[test]
+fn gen_cellular_is_connected_and_reproducible() {
+    use gen::cellular;
+    use geom::Bounds;
+    use algo::bfs;
+
+    let bounds = Bounds::hexagon(Coordinate::new(0, 0), 12);
+    let config = cellular::Config::default();
+
+    let cave = cellular::generate(bounds, &config, &mut StdRng::seed_from_u64(7));
+    assert_eq!(cave, cellular::generate(bounds, &config, &mut StdRng::seed_from_u64(7)));
+    assert!(cave.iter().all(|&c| bounds.contains(c)));
+
+    let start = *cave.iter().next().unwrap();
+    let mut traverser = bfs::Traverser::new(|c| cave.contains(&c), |c| cave.contains(&c), start);
+    let mut reached = 0;
+    while traverser.find().is_some() {
+        reached += 1;
+    }
+    assert_eq!(reached, cave.len());
+}
+
+#[test]
+fn gen_drunkard_reaches_target() {
+    use gen::drunkard;
+    use geom::Bounds;
+
+    let bounds = Bounds::rectangle(Coordinate::new(0, 0), 20, 10);
+    let config = drunkard::Config { open_ratio: 0.5, .. Default::default() };
+
+    let floor = drunkard::generate(bounds, &[Coordinate::new(5, -8)], &config, &mut StdRng::seed_from_u64(0));
+    assert_eq!(floor.len(), 100);
+    assert!(floor.iter().all(|&c| bounds.contains(c)));
+    assert_eq!(floor, drunkard::generate(bounds, &[Coordinate::new(5, -8)], &config, &mut StdRng::seed_from_u64(0)));
+}
+
+#[test]
+fn gen_drunkard_stops_when_stalled() {
+    use gen::drunkard;
+    use geom::Bounds;
+
+    let center = Coordinate::new(0, 0);
+    let bounds = Bounds::hexagon(center, 10);
+    let config = drunkard::Config { open_ratio: 0.5, momentum: 1.0, walk_length: 100 };
+
+    let floor = drunkard::generate(bounds, &[center], &config, &mut StdRng::seed_from_u64(0));
+    assert!(floor.len() <= 61);
+    assert!(floor.iter().all(|&c| c.x == 0 || c.y == 0 || c.z() == 0));
+}
+
+#[test]
+#[should_panic(expected = "walk_length")]
+fn gen_drunkard_rejects_empty_walks() {
+    use gen::drunkard;
+    use geom::Bounds;
+
+    let config = drunkard::Config { walk_length: 0, .. Default::default() };
+    drunkard::generate(Bounds::hexagon(Coordinate::new(0, 0), 10), &[Coordinate::new(0, 0)], &config, &mut StdRng::seed_from_u64(0));
+}
+
+#[test]
+fn gen_rooms_are_connected() {
+    use gen::rooms;
+    use geom::Bounds;
+    use algo::bfs;
+
+    let bounds = Bounds::hexagon(Coordinate::new(0, 0), 20);
+    let dungeon = rooms::generate(bounds, &Default::default(), &mut StdRng::seed_from_u64(3));
+    let floor = dungeon.floor();
+
+    assert!(dungeon.rooms.len() > 1);
+    assert!(!dungeon.doors.is_empty());
+
+    let start = dungeon.rooms[0].center;
+    for room in dungeon.rooms.iter() {
+        let mut traverser = bfs::Traverser::new(|c| floor.contains(&c), |c| c == room.center, start);
+        assert_eq!(traverser.find(), Some(room.center));
+    }
+}
+
+#[test]
+fn gen_maze_is_perfect() {
+    use gen::maze;
+    use geom::Bounds;
+    use algo::bfs;
+
+    let bounds = Bounds::hexagon(Coordinate::new(0, 0), 5);
+    for &algorithm in [maze::Algorithm::Backtracker, maze::Algorithm::Kruskal].iter() {
+        let maze = maze::generate(bounds, algorithm, &mut StdRng::seed_from_u64(1));
+
+        // a perfect maze is a spanning tree: `len - 1` passages
+        let cells = maze.cells();
+        assert_eq!(cells.len(), 2 * bounds.len() - 1);
+
+        let start = Coordinate::new(0, 0);
+        let mut traverser = bfs::Traverser::new(|c| cells.contains(&c), |c| cells.contains(&c), start);
+        let mut reached = 0;
+        while traverser.find().is_some() {
+            reached += 1;
+        }
+        assert_eq!(reached, cells.len());
+    }
+}
+
+#[test]
+fn gen_wfc_respects_rules() {
+    use gen::wfc;
+    use geom::Bounds;
+    use hex2d::Direction;
+
+    // water - shore - land, water never touching land
+    let mut rules = wfc::Rules::new(vec!(1.0, 0.5, 1.0));
+    rules.allow_all(0, 0);
+    rules.allow_all(0, 1);
+    rules.allow_all(1, 1);
+    rules.allow_all(1, 2);
+    rules.allow_all(2, 2);
+
+    let bounds = Bounds::hexagon(Coordinate::new(0, 0), 6);
+    let tiles = wfc::solve(bounds, &rules, &Default::default(), &mut StdRng::seed_from_u64(0)).unwrap();
+
+    assert_eq!(tiles.len(), bounds.len());
+    for (&c, &t) in tiles.iter() {
+        for &dir in Direction::all().iter() {
+            if let Some(&n) = tiles.get(&(c + dir)) {
+                assert!(!(t == 0 && n == 2));
+            }
+        }
+    }
+}
+
+#[test]
+fn gen_erosion_flattens_spikes() {
+    use gen::erosion;
+    use geom::Bounds;
+    use map::HexMap;
+
+    let center = Coordinate::new(0, 0);
+    let spike = HexMap::from_fn(Bounds::hexagon(center, 4), |c| if c == center { 10.0 } else { 0.0 });
+
+    let mut smoothed = spike.clone();
+    erosion::smooth(&mut smoothed, 0.5);
+    assert_eq!(smoothed[center], 5.0);
+
+    let mut crumbled = spike.clone();
+    erosion::thermal(&mut crumbled, 1.0, 0.5, 10);
+    assert!(crumbled[center] < 10.0);
+    let total : f32 = crumbled.iter().map(|(_, h)| h).sum();
+    assert!((total - 10.0).abs() < 0.001);
+
+    let mut eroded = spike;
+    erosion::hydraulic(&mut eroded, &Default::default(), &mut StdRng::seed_from_u64(0));
+    let total : f32 = eroded.iter().map(|(_, h)| h).sum();
+    assert!((total - 10.0).abs() < 0.01);
+}
+
+#[test]
+fn gen_rivers_flow_downhill_and_merge() {
+    use gen::rivers;
+    use geom::Bounds;
+    use map::HexMap;
+
+    // a valley along x == 0
+    let heights = HexMap::from_fn(Bounds::hexagon(Coordinate::new(0, 0), 8), |c : Coordinate| (c.x.abs() * 10 - c.y) as f32);
+
+    let springs = [Coordinate::new(3, 2), Coordinate::new(-3, 4)];
+    let rivers = rivers::trace(&heights, &springs, &Default::default(), &mut StdRng::seed_from_u64(0));
+
+    assert_eq!(rivers.len(), 2);
+    for river in rivers.iter() {
+        for w in river.course.windows(2) {
+            assert!(heights[w[1]] < heights[w[0]]);
+        }
+    }
+    assert_eq!(rivers[1].joins.map(|(k, _)| k), Some(0));
+    assert_eq!(*rivers[0].widths.last().unwrap(), 2);
+}
+
+#[test]
+fn gen_biomes_from_regions() {
+    use gen::biomes::{self, BiomeId};
+    use geom::Bounds;
+    use map::HexMap;
+
+    let center = Coordinate::new(0, 0);
+    let bounds = Bounds::hexagon(center, 6);
+    let heights = HexMap::from_fn(bounds, |c| if c == center { 9.0 } else { 1.0 });
+    let moisture = HexMap::from_fn(bounds, |c| if c.x < 0 { 1.0 } else { 0.0 });
+    let seeds = [Coordinate::new(-4, 2), Coordinate::new(4, -2)];
+
+    let res = biomes::assign(&heights, &moisture, &seeds, |h, m| {
+        if h > 5.0 { BiomeId(2) } else if m > 0.5 { BiomeId(1) } else { BiomeId(0) }
+    });
+
+    assert_eq!(res.biomes[center], BiomeId(2));
+    assert_eq!(res.biomes[seeds[0]], BiomeId(1));
+    assert_eq!(res.biomes[seeds[1]], BiomeId(0));
+    assert!(res.adjacency.contains(&(0, 1)));
+}
+
+#[test]
+fn gen_blob_is_connected() {
+    use gen::blob;
+    use algo::bfs;
+
+    let center = Coordinate::new(0, 0);
+
+    let hexagon = blob::generate(center, |_| true, &blob::Config { size: 19, compactness: 1.0 }, &mut StdRng::seed_from_u64(0));
+    assert_eq!(hexagon, center.range_iter(2).collect());
+
+    let config = blob::Config { size: 50, compactness: 0.2 };
+    let ragged = blob::generate(center, |c| c.x != 2, &config, &mut StdRng::seed_from_u64(5));
+    assert_eq!(ragged.len(), 50);
+    assert!(ragged.iter().all(|c| c.x != 2));
+
+    let mut traverser = bfs::Traverser::new(|c| ragged.contains(&c), |c| ragged.contains(&c), center);
+    let mut reached = 0;
+    while traverser.find().is_some() {
+        reached += 1;
+    }
+    assert_eq!(reached, 50);
+}
+
+#[test]
+fn gen_prefab_stamp() {
+    use gen::prefab::Prefab;
+    use geom::{Axis, Bounds, Transform};
+    use map::HexMap;
+    use hex2d::Angle;
+
+    let vault = Prefab::from_ascii("
+        # A # #
+         # . . #
+        # # B #
+    ");
+    assert_eq!(vault.connections().len(), 2);
+
+    let mut world = HexMap::new(Bounds::hexagon(Coordinate::new(0, 0), 6), ' ');
+    let transform = Transform::new(Angle::Right).mirrored(Axis::Y);
+    let origin = Coordinate::new(-1, 1);
+    let doors = vault.stamp(&mut world, origin, transform).unwrap();
+
+    assert_eq!(world.iter().filter(|&(_, &t)| t != ' ').count(), 12);
+    assert_eq!(world.iter().filter(|&(_, &t)| t == '.').count(), 4);
+    for (label, &c) in vault.connections() {
+        assert_eq!(doors[label], origin + transform.apply(c));
+        assert_eq!(world[doors[label]], '.');
+    }
+
+    let before = world.clone();
+    assert_eq!(vault.stamp(&mut world, Coordinate::new(8, 0), transform), None);
+    assert_eq!(world, before);
+}
+
+#[test]
+fn gen_symmetry_rotates_sectors() {
+    use gen::symmetry;
+    use geom::Bounds;
+    use map::HexMap;
+    use hex2d::Angle;
+    use rand::Rng;
+
+    let center = Coordinate::new(0, 0);
+    let mut rng = StdRng::seed_from_u64(3);
+    let mut map = HexMap::from_fn(Bounds::hexagon(center, 8), |_| rng.gen_bool(0.2));
+    symmetry::symmetrize(&mut map, center, 3);
+    for (c, &wall) in map.iter() {
+        assert_eq!(map[c.rotate_around(center, Angle::LeftBack)], wall);
+    }
+
+    let spawns = symmetry::orbit(Coordinate::new(6, -3), center, 3);
+    assert_eq!(spawns.len(), 3);
+    map[center] = false;
+    for &s in &spawns {
+        map[s] = false;
+    }
+    assert!(symmetry::equidistant(&spawns, center, |c| map.get(c) == Some(&false)));
+
+    for n in spawns[1].neighbors().iter() {
+        map[*n] = true;
+    }
+    assert!(!symmetry::equidistant(&spawns, center, |c| map.get(c) == Some(&false)));
+}
+
+#[test]
+fn gen_validate_report() {
+    use gen::validate;
+    use geom::Bounds;
+    use std::collections::HashSet;
+
+    let bounds = Bounds::hexagon(Coordinate::new(0, 0), 10);
+    let west = Coordinate::new(-7, 0);
+    let east = Coordinate::new(7, 0);
+    let mut open : HashSet<_> = west.range_iter(2).chain(east.range_iter(2)).collect();
+    open.extend((-4..5).map(|x| Coordinate::new(x, 0)));
+    let stash = Coordinate::new(0, 5);
+
+    let config = validate::Config { min_open_ratio: 0.1, max_corridor: 12 };
+    let report = validate::check(bounds, &open, &[west, east], &[east, stash], &config);
+    assert_eq!(report.longest_corridor, 9);
+    assert_eq!(report.disconnected_spawns, vec!());
+    assert_eq!(report.unreachable_items, vec!(stash));
+    assert_eq!(report.problems, vec!(validate::Problem::UnreachableItems));
+
+    open.insert(stash);
+    open.remove(&Coordinate::new(0, 0));
+    let config = validate::Config { max_corridor: 2, ..config };
+    let report = validate::check(bounds, &open, &[west, east], &[], &config);
+    assert_eq!(report.disconnected_spawns, vec!(east));
+    assert_eq!(report.problems, vec!(validate::Problem::DisconnectedSpawns, validate::Problem::LongCorridor));
+
+    let mut attempts = 0;
+    let res = validate::retry(3, || { attempts += 1; attempts }, |&a| {
+        let items = if a < 2 { vec!(Coordinate::new(0, 0)) } else { vec!() };
+        validate::check(bounds, &open, &[west], &items, &validate::Config { max_corridor: 12, ..config })
+    });
+    assert_eq!(res, Ok(2));
+}
+
+#[test]
+fn gen_connect_regions() {
+    use gen::connect;
+    use geom::Bounds;
+    use map::HexMap;
+
+    let center = Coordinate::new(0, 0);
+    let mut map = HexMap::new(Bounds::hexagon(center, 6), '#');
+    for c in center.range_iter(1) {
+        map[c] = '.';
+    }
+    map[Coordinate::new(4, 0)] = '.';
+    map[Coordinate::new(-2, -2)] = '.';
+    assert_eq!(connect::regions(&map, |&t| t == '.').len(), 3);
+
+    let carved = connect::connect(&mut map, 5, |&t| t == '.', |t| *t = '.');
+    assert_eq!(carved.len(), 4);
+    assert!(carved.contains(&Coordinate::new(2, 0)) && carved.contains(&Coordinate::new(3, 0)));
+    assert_eq!(connect::regions(&map, |&t| t == '.').len(), 1);
+    assert_eq!(connect::connect(&mut map, 5, |&t| t == '.', |t| *t = '.'), vec!());
+}
+
+#[test]
+fn gen_spawn_place() {
+    use gen::spawn;
+    use geom::Bounds;
+
+    let center = Coordinate::new(0, 0);
+    let bounds = Bounds::hexagon(center, 10);
+    let config = spawn::Config {
+        count: 3,
+        min_distance: 8,
+        hazard_distance: 4,
+        open_radius: 1,
+        min_open: 7,
+        attempts: 10,
+    };
+
+    let place = |config : &spawn::Config, seed| {
+        let mut rng = StdRng::seed_from_u64(seed);
+        spawn::place(bounds, |_| true, &[center], config, &mut rng)
+    };
+    let spawns = place(&config, 1).unwrap();
+    assert_eq!(spawns.len(), 3);
+    assert_eq!(place(&config, 1), Some(spawns.clone()));
+    for &a in &spawns {
+        assert!(a.distance(center) >= 4 && a.distance(center) < 10);
+        for &b in &spawns {
+            assert!(a == b || a.distance(b) >= 8);
+        }
+    }
+
+    assert_eq!(place(&spawn::Config { count: 20, ..config }, 1), None);
+}
+
+#[test]
+fn gen_scatter_by_desirability() {
+    use gen::scatter::{self, Category};
+    use geom::Bounds;
+    use map::HexMap;
+    use std::collections::HashSet;
+
+    let entrance = Coordinate::new(0, 0);
+    let field = HexMap::from_fn(Bounds::hexagon(entrance, 6), |c| {
+        if c.x > 0 { c.distance(entrance) as f32 } else { 0.0 }
+    });
+    let categories = [
+        Category { count: 1, exclusion: 3 },
+        Category { count: 4, exclusion: 1 },
+        Category { count: 100, exclusion: 0 },
+    ];
+    let mut rng = StdRng::seed_from_u64(5);
+    let placed = scatter::scatter(&field, &categories, &mut rng);
+
+    assert_eq!(placed[0].len(), 1);
+    assert_eq!(placed[1].len(), 4);
+    let all : Vec<_> = placed.iter().flatten().cloned().collect();
+    assert!(all.iter().all(|c| c.x > 0));
+    assert_eq!(all.iter().collect::<HashSet<_>>().len(), all.len());
+    assert!(placed[1].iter().chain(&placed[2]).all(|c| c.distance(placed[0][0]) > 3));
+    for &a in &placed[1] {
+        assert!(placed[1].iter().chain(&placed[2]).all(|&b| a == b || a.distance(b) > 1));
+    }
+    assert!(placed[2].len() < 100);
+}
+
+#[test]
+fn gen_dead_ends_prune() {
+    use gen::dead_ends;
+    use geom::Bounds;
+    use map::HexMap;
+
+    let center = Coordinate::new(0, 0);
+    let mut map = HexMap::new(Bounds::hexagon(center, 6), '#');
+    for c in center.range_iter(1) {
+        map[c] = '.';
+    }
+    let long = vec!(Coordinate::new(4, 0), Coordinate::new(3, 0), Coordinate::new(2, 0));
+    let short = vec!(Coordinate::new(0, 3), Coordinate::new(0, 2));
+    for &c in long.iter().chain(&short) {
+        map[c] = '.';
+    }
+
+    let mut found = dead_ends::find(&map, |&t| t == '.');
+    found.sort_by_key(|chain| chain.len());
+    assert_eq!(found, vec!(short.clone(), long.clone()));
+
+    let filled = dead_ends::prune(&mut map, 2, |&t| t == '.', |t| *t = '#');
+    assert_eq!(filled.len(), 4);
+    assert!(short.iter().all(|&c| map[c] == '#'));
+    assert_eq!(dead_ends::find(&map, |&t| t == '.'), vec!(vec!(long[2])));
+}
+
+#[test]
+fn bfs_sample_reachable_stays_inside() {
+    use algo::bfs;
+
+    let start = Coordinate::new(0, 0);
+    let mut rng = StdRng::seed_from_u64(0);
+    let room : ::std::collections::HashSet<_> = start.range_iter(2).collect();
+
+    for _ in 0..50 {
+        let c = bfs::sample_reachable(start, |c| room.contains(&c), 10, &mut rng).unwrap();
+        assert!(room.contains(&c));
+        let c = bfs::sample_reachable(start, |_| true, 3, &mut rng).unwrap();
+        assert!(start.distance(c) <= 3);
+    }
+    assert_eq!(bfs::sample_reachable(start, |c| c != start, 3, &mut rng), None);
+}
+
+#[test]
+fn map_hexmap_access() {
+    use geom::Bounds;
+    use map::{HexMap, Grid};
+    use algo::bfs;
+
+    let center = Coordinate::new(0, 0);
+    let mut map = HexMap::new(Bounds::hexagon(center, 3), true);
+    map[Coordinate::new(1, 0)] = false;
+    map.swap(Coordinate::new(1, 0), Coordinate::new(2, 0));
+
+    assert_eq!(map.len(), 37);
+    assert_eq!(map.get(Coordinate::new(4, 0)), None);
+    assert_eq!(map.iter().filter(|&(_, &t)| !t).count(), 1);
+
+    let mut traverser = bfs::Traverser::new(map.passable(|&t| t), |c| c == Coordinate::new(3, 0), center);
+    assert_eq!(traverser.find(), Some(Coordinate::new(3, 0)));
+    assert_eq!(traverser.distance(Coordinate::new(3, 0)), Some(4));
+}
+
+#[test]
+fn map_hexmap_stamping() {
+    use geom::Bounds;
+    use map::HexMap;
+
+    let center = Coordinate::new(0, 0);
+    let room = HexMap::from_fn(Bounds::rectangle(center, 3, 3), |c : Coordinate| c.x + 10 * c.y);
+
+    let mut moved = room.clone();
+    moved.translate(Coordinate::new(5, -1));
+    assert_eq!(moved.len(), room.len());
+    for (c, &tile) in room.iter() {
+        assert_eq!(moved[c + Coordinate::new(5, -1)], tile);
+    }
+
+    let mut world = HexMap::new(Bounds::hexagon(center, 6), 0);
+    world.blit(&room, Coordinate::new(5, 0));
+    assert_eq!(world[Coordinate::new(5, 0)], room[center]);
+    assert_eq!(world.iter().filter(|&(_, &t)| t != 0).count(), room.iter().filter(|&(c, &t)| t != 0 && world.contains(c + Coordinate::new(5, 0))).count());
+
+    let cropped = world.crop(Bounds::hexagon(Coordinate::new(5, 0), 1));
+    assert_eq!(cropped.len(), 7);
+    assert_eq!(cropped[Coordinate::new(5, 0)], room[center]);
+
+    let mut grown = cropped.clone();
+    grown.resize_with(Bounds::hexagon(Coordinate::new(5, 0), 2), |_| -1);
+    assert_eq!(grown.len(), 19);
+    assert!(cropped.iter().all(|(c, t)| grown[c] == *t));
+    assert_eq!(grown.iter().filter(|&(_, &t)| t == -1).count(), 12);
+}
+
+#[test]
+fn map_hexmap_views() {
+    use geom::Bounds;
+    use map::{HexMap, Grid};
+    use algo::bfs;
+
+    let center = Coordinate::new(0, 0);
+    let room = Bounds::hexagon(Coordinate::new(4, 0), 1);
+    let mut map = HexMap::new(Bounds::hexagon(center, 4), 0);
+
+    map.view_mut(room).fill(1);
+    assert_eq!(map.iter().filter(|&(_, &t)| t == 1).count(), 4);
+
+    let view = map.view(room);
+    assert_eq!(view.iter().count(), 4);
+    assert_eq!(view.get(center), None);
+    assert_eq!(view.get(Coordinate::new(4, 0)), Some(&1));
+
+    let mut traverser = bfs::Traverser::new(view.passable(|_| true), |_| false, Coordinate::new(4, 0));
+    traverser.find();
+    assert_eq!(traverser.visited().filter(|&c| view.contains(c)).count(), 4);
+    assert_eq!(traverser.distance(Coordinate::new(1, 0)), None);
+}
+
+#[test]
+fn map_chunked_loads_on_demand() {
+    use map::{ChunkedMap, Grid};
+    use algo::bfs;
+
+    let mut map = ChunkedMap::new(8, |c : Coordinate| c.x % 5 != 0 || c.y % 3 == 0);
+    let far = Coordinate::new(-1000, 37);
+
+    assert_eq!(map.get(far), None);
+    assert_eq!(*map.get_or_load(far), far.x % 5 != 0 || far.y % 3 == 0);
+    assert_eq!(map.loaded_chunks(), 1);
+
+    let start = Coordinate::new(-13, -9);
+    let dest = Coordinate::new(12, 11);
+    map.load_range(start, 50);
+    let mut traverser = bfs::Traverser::new(map.passable(|&t| t), |c| c == dest, start);
+    assert_eq!(traverser.find(), Some(dest));
+}
+
+#[test]
+fn map_chunked_hexagon_chunks() {
+    use map::ChunkedMap;
+    use std::collections::HashMap;
+
+    for radius in 0..6 {
+        let mut map = ChunkedMap::new(radius, |c : Coordinate| c);
+        let mut sizes = HashMap::new();
+
+        for c in Coordinate::new(0, 0).range_iter(40) {
+            let chunk = map.chunk_of(c);
+            assert!(map.chunk_center(chunk).distance(c) <= radius);
+            assert_eq!(*map.get_or_load(c), c);
+            *sizes.entry(chunk).or_insert(0) += 1;
+        }
+
+        let full = 3 * radius * (radius + 1) + 1;
+        assert!(sizes.values().all(|&n| n <= full));
+        assert_eq!(sizes[&map.chunk_of(Coordinate::new(0, 0))], full);
+    }
+}
+
+#[test]
+fn map_sparse_defaults() {
+    use map::SparseMap;
+
+    let island = Coordinate::new(3, -1);
+    let mut map = SparseMap::new('~');
+
+    map[island] = '#';
+    assert_eq!(map[island], '#');
+    assert_eq!(map[Coordinate::new(100, 100)], '~');
+
+    assert_eq!(map.replace(island, '~'), '#');
+    let _ = map.get_mut(Coordinate::new(0, 0));
+    map.compact();
+    assert!(map.is_empty());
+}
+
+#[test]
+fn map_layers_compose() {
+    use geom::Bounds;
+    use map::{HexMap, Layers};
+    use algo::los;
+
+    let center = Coordinate::new(0, 0);
+    let bounds = Bounds::hexagon(center, 5);
+    let boulder = Coordinate::new(2, 0);
+    let unit = Coordinate::new(0, 2);
+
+    let mut layers = Layers::new(bounds);
+    layers.push("terrain", HexMap::new(bounds, '.'), |&t| t != '#', |&t| if t == '#' { 100 } else { 1 });
+    layers.push("units", HexMap::new(bounds, None), |u : &Option<u32>| u.is_none(), |_| 0);
+
+    layers.layer_mut::<char>("terrain").unwrap()[boulder] = '#';
+    layers.layer_mut::<Option<u32>>("units").unwrap()[unit] = Some(7);
+
+    assert!(layers.layer::<u32>("terrain").is_none());
+    assert!(!layers.can_pass(boulder));
+    assert!(!layers.can_pass(unit));
+    assert!(layers.can_pass(center));
+
+    let mut seen = ::std::collections::HashSet::new();
+    los::los(&layers.opaque(100), &mut |c, _| { seen.insert(c); }, 10, center, hex2d::Direction::all());
+    assert!(!seen.contains(&boulder));
+    assert!(!seen.contains(&Coordinate::new(3, 0)));
+    assert!(seen.contains(&unit));
+
+    // Opaqueness of another type than the Coordinates
+    let mut fog : Layers<i32, f32> = Layers::new(bounds);
+    fog.push("terrain", HexMap::new(bounds, 0.5f32), |_| true, |&t| t);
+    fog.push("smoke", HexMap::from_fn(bounds, |c : Coordinate| c.x >= 2), |_| true, |&s| if s { 2.0 } else { 0.0 });
+    assert_eq!(fog.opaqueness(boulder), Some(2.5));
+    let mut seen = ::std::collections::HashSet::new();
+    los::los(&fog.opaque(100.0), &mut |c, _| { seen.insert(c); }, 4.0, center, hex2d::Direction::all());
+    assert!(seen.contains(&Coordinate::new(-3, 0)));
+    assert!(seen.contains(&boulder));
+    assert!(!seen.contains(&Coordinate::new(3, 0)));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_derives() {
+    use serde::Serialize;
+    use serde::de::DeserializeOwned;
+
+    fn persistable<T : Serialize + DeserializeOwned>() {}
+
+    persistable::<::map::HexMap<u8>>();
+    persistable::<::map::SparseMap<u8>>();
+    persistable::<::geom::Bounds>();
+    persistable::<::geom::Transform>();
+    persistable::<::algo::influence::InfluenceMap>();
+    persistable::<::algo::scent::ScentMap>();
+    persistable::<::gen::rooms::Dungeon>();
+    persistable::<::gen::maze::Maze>();
+    persistable::<::gen::rivers::River>();
+    persistable::<::gen::biomes::Biomes>();
+    persistable::<::algo::path::Path>();
+    persistable::<::algo::path::Leg>();
+    persistable::<::algo::fog::Memory>();
+    persistable::<::map::MapRevision>();
+    persistable::<::algo::apsp::AllPairs>();
+    persistable::<::algo::astar::GoalBounds>();
+}
+
+#[test]
+fn snapshot_roundtrip() {
+    use geom::Bounds;
+    use map::HexMap;
+    use snapshot;
+    use hashing::OutputHashSet;
+
+    let bounds = Bounds::hexagon(Coordinate::new(3, -7), 20);
+    let map = HexMap::from_fn(bounds, |c : Coordinate| if c.x > 5 { -1i8 } else { 2 });
+    let bytes = snapshot::encode_map(&map);
+    assert!(bytes.len() < 200);
+    assert_eq!(snapshot::decode_map::<i8, i32>(&bytes), Some(map.clone()));
+    assert_eq!(snapshot::decode_map::<i8, i32>(&bytes[..bytes.len() - 1]), None);
+    assert_eq!(snapshot::decode_map::<bool, i32>(&bytes), None);
+
+    let mut changed = map.clone();
+    changed[Coordinate::new(0, 0)] = 5;
+    changed[Coordinate::new(10, -10)] = 5;
+    let diff = snapshot::encode_diff(&map, &changed);
+    assert!(diff.len() < 20);
+    let mut patched = map.clone();
+    assert!(snapshot::apply_diff(&mut patched, &diff));
+    assert_eq!(patched, changed);
+
+    let rect = Bounds::rectangle(Coordinate::new(0, 0), 30, 10);
+    let seen : OutputHashSet<Coordinate> = rect.iter().filter(|c| c.distance(Coordinate::new(5, -5)) < 4).collect();
+    let (decoded_bounds, decoded) = snapshot::decode_set(&snapshot::encode_set(rect, &seen)).unwrap();
+    assert_eq!(decoded_bounds, rect);
+    assert_eq!(decoded, seen);
+}
+
+#[test]
+fn snapshot_rejects_hostile_headers() {
+    use snapshot;
+
+    // Rectangle with width -1, then a run of 2^40 tiles
+    let negative = [1, 0, 0, 1, 2, 0x80, 0x80, 0x80, 0x80, 0x80, 0x20, 0];
+    assert_eq!(snapshot::decode_map::<u8, i32>(&negative), None);
+    assert_eq!(snapshot::decode_set::<i32>(&negative), None);
+
+    // Hexagon of radius 2^20
+    let huge = [0, 0, 0, 0x80, 0x80, 0x80, 0x01, 0x80, 0x80, 0x80, 0x80, 0x80, 0x20, 0];
+    assert_eq!(snapshot::decode_map::<u8, i64>(&huge), None);
+
+    // Rectangle 2 x 1 at x = i32::MAX, and hexagon of radius 1 at y = i32::MIN
+    let edge = [1, 0xfe, 0xff, 0xff, 0xff, 0x0f, 0, 4, 2, 2, 0];
+    assert_eq!(snapshot::decode_map::<u8, i32>(&edge), None);
+    assert_eq!(snapshot::decode_set::<i32>(&edge), None);
+    let edge = [0, 0, 0xff, 0xff, 0xff, 0xff, 0x0f, 2, 7, 0];
+    assert_eq!(snapshot::decode_map::<u8, i32>(&edge), None);
+
+    // z out of range even with no tiles
+    let empty = [1, 0xfe, 0xff, 0xff, 0xff, 0x0f, 0xfe, 0xff, 0xff, 0xff, 0x0f, 0, 0];
+    assert_eq!(snapshot::decode_set::<i32>(&empty), None);
+}
+
+#[test]
+fn snapshot_diff_extreme_codes() {
+    use geom::Bounds;
+    use map::HexMap;
+    use snapshot;
+
+    let bounds = Bounds::hexagon(Coordinate::new(0, 0), 3);
+    let zeros = HexMap::from_fn(bounds, |_| 0u64);
+    let extremes = HexMap::from_fn(bounds, |c : Coordinate| if c.x > 0 { u64::MAX } else { 0 });
+    let mut patched = zeros.clone();
+    assert!(snapshot::apply_diff(&mut patched, &snapshot::encode_diff(&zeros, &extremes)));
+    assert_eq!(patched, extremes);
+    assert_eq!(snapshot::decode_map::<u64, i32>(&snapshot::encode_map(&extremes)), Some(extremes));
+
+    let from = HexMap::from_fn(bounds, |_| 7i64);
+    let to = HexMap::from_fn(bounds, |c : Coordinate| if c.y > 0 { i64::MIN } else if c.y < 0 { i64::MAX } else { 7 });
+    let mut patched = from.clone();
+    assert!(snapshot::apply_diff(&mut patched, &snapshot::encode_diff(&from, &to)));
+    assert_eq!(patched, to);
+}
+
+#[test]
+fn bitset_collects_reachability_and_fov() {
+    use algo::{bfs, los};
+    use geom::Bounds;
+    use map::HexBitSet;
+
+    let center = Coordinate::new(0, 0);
+    let bounds = Bounds::hexagon(center, 6);
+    let wall = |c : Coordinate| c.x == 2 && c.y != -6;
+
+    let reach = bfs::reachable(center, |c| !wall(c), bounds);
+    let expected = bounds.iter().filter(|&c| !wall(c)).count();
+    assert_eq!(reach.len(), expected);
+    assert!(!reach.contains(Coordinate::new(2, -1)));
+    assert!(reach.contains(Coordinate::new(4, -2)));
+    assert!(!reach.contains(Coordinate::new(10, 0)));
+
+    let mut seen = HexBitSet::new(bounds);
+    los::los(&|c| if wall(c) { 100 } else { 1 }, &mut |c, _| { seen.insert(c); }, 10, center, hex2d::Direction::all());
+    assert!(seen.contains(center));
+    assert!(!seen.contains(Coordinate::new(4, -2)));
+
+    let mut both = seen.clone();
+    both.intersect_with(&reach);
+    assert_eq!(both, seen);
+    let mut hidden = reach.clone();
+    hidden.difference_with(&seen);
+    assert_eq!(hidden.len(), reach.len() - seen.len());
+    assert!(hidden.iter().all(|c| !seen.contains(c)));
+    hidden.union_with(&seen);
+    assert_eq!(hidden, reach);
+}
+
+#[test]
+fn rle_region_set_algebra() {
+    use geom::{Bounds, RleRegion};
+    use std::collections::HashSet;
+
+    let a_bounds = Bounds::hexagon(Coordinate::new(0, 0), 30);
+    let b_bounds = Bounds::rectangle(Coordinate::new(10, -10), 40, 25);
+    let a = RleRegion::from(a_bounds);
+    let b : RleRegion = b_bounds.iter().collect();
+
+    assert_eq!(a.len(), a_bounds.len());
+    assert_eq!(a.runs(), 61);
+    assert_eq!(b.len(), b_bounds.len());
+
+    let a_set : HashSet<Coordinate> = a_bounds.iter().collect();
+    let b_set : HashSet<Coordinate> = b_bounds.iter().collect();
+
+    let union = a.union(&b);
+    let intersection = a.intersection(&b);
+    let difference = a.difference(&b);
+    assert_eq!(union.iter().collect::<HashSet<_>>(), &a_set | &b_set);
+    assert_eq!(intersection.iter().collect::<HashSet<_>>(), &a_set & &b_set);
+    assert_eq!(difference.iter().collect::<HashSet<_>>(), &a_set - &b_set);
+    assert!(difference.runs() < 200);
+
+    let mut holes = a.clone();
+    holes.remove(Coordinate::new(0, 0));
+    assert!(!holes.contains(Coordinate::new(0, 0)));
+    assert!(holes.contains(Coordinate::new(1, 0)));
+    assert_eq!(holes.len(), a.len() - 1);
+    holes.insert(Coordinate::new(0, 0));
+    assert_eq!(holes, a);
+    assert!(a.difference(&a).is_empty());
+}
+
+#[test]
+fn fx_hasher() {
+    use hashing::{FxBuildHasher, FxHasher};
+    use std::collections::HashSet;
+    use std::hash::{Hash, Hasher};
+
+    let hash = |c : Coordinate| {
+        let mut hasher = FxHasher::default();
+        c.hash(&mut hasher);
+        hasher.finish()
+    };
+    assert_eq!(hash(Coordinate::new(3, -2)), hash(Coordinate::new(3, -2)));
+    assert!(hash(Coordinate::new(3, -2)) != hash(Coordinate::new(-2, 3)));
+
+    let set : HashSet<Coordinate, FxBuildHasher> = (0..100).map(|i| Coordinate::new(i, -i)).collect();
+    assert_eq!(set.len(), 100);
+    assert!(set.contains(&Coordinate::new(42, -42)));
+}
+
+#[test]
+fn los2_queries_opaqueness_once() {
+    use algo::los2;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    let calls = RefCell::new(HashMap::new());
+    let opaqueness = |c : Coordinate| {
+        *calls.borrow_mut().entry(c).or_insert(0) += 1;
+        if c == Coordinate::new(2, -1) { 100 } else { 1 }
+    };
+
+    let mut seen = ::std::collections::HashSet::new();
+    los2::los(&opaqueness, &mut |c, _| { seen.insert(c); }, 8, Coordinate::new(0, 0), hex2d::Direction::all());
+
+    assert!(seen.len() > 100);
+    assert!(calls.borrow().values().all(|&n| n == 1));
+}
+
+#[test]
+fn with_capacity_constructors() {
+    use algo::{bfs, los2};
+    use std::collections::HashSet;
+
+    let start = Coordinate::new(0, 0);
+    let can_pass = |c : Coordinate| c.distance(start) <= 5;
+    let mut traverser = bfs::Traverser::with_capacity(&can_pass, |_| true, start, 91);
+    let mut count = 0;
+    while let Some(c) = traverser.find() {
+        if can_pass(c) {
+            count += 1;
+        }
+    }
+    assert_eq!(count, 91);
+
+    let mut seen = HashSet::new();
+    let mut seen_presized = HashSet::new();
+    los2::los(&|_| 1, &mut |c, _| { seen.insert(c); }, 5, start, hex2d::Direction::all());
+    los2::los_with_capacity(&|_| 1, &mut |c, _| { seen_presized.insert(c); }, 5, start, hex2d::Direction::all(), 91);
+    assert_eq!(seen, seen_presized);
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn fov_many_matches_sequential() {
+    use algo::fov;
+
+    let opaqueness = |c : Coordinate| if c.x.abs() % 4 == 2 && c.y != 0 { 100 } else { 1 };
+    let observers : Vec<(Coordinate, i32, &[hex2d::Direction])> = (-5..5)
+        .map(|i| (Coordinate::new(i, 2 * i), 6, &hex2d::Direction::all()[..]))
+        .collect();
+
+    let maps = fov::fov_many(&opaqueness, &observers);
+    assert_eq!(maps.len(), observers.len());
+    for (map, &(pos, light, dirs)) in maps.iter().zip(observers.iter()) {
+        assert_eq!(map.origin(), pos);
+        assert!(map.is_visible(pos));
+        assert_eq!(*map, fov::fov(&opaqueness, light, pos, dirs));
+    }
+}
+
+#[test]
+fn bounds_ring() {
+    use geom::Bounds;
+    use algo::{bfs, fov};
+
+    let center = Coordinate::new(2, -5);
+    for &(inner, outer) in [(0, 4), (1, 4), (3, 3), (3, 7), (5, 2)].iter() {
+        let ring = Bounds::ring(center, inner, outer);
+        let expected : Vec<Coordinate> = Bounds::hexagon(center, outer).iter()
+            .filter(|c| c.distance(center) >= inner)
+            .collect();
+        let coords : Vec<Coordinate> = ring.iter().collect();
+
+        assert_eq!(coords, expected);
+        assert_eq!(ring.len(), expected.len());
+        for (i, &c) in coords.iter().enumerate() {
+            assert_eq!(ring.index(c), Some(i));
+        }
+        assert!(inner == 0 || !ring.contains(center));
+    }
+
+    let ring = Bounds::ring(center, 2, 3);
+    let reach = bfs::reachable(center + Coordinate::new(2, 0), |_| true, ring);
+    assert_eq!(reach.len(), ring.len());
+
+    let clamped = ring.passable(|_| true);
+    assert!(!clamped(center));
+
+    let opaqueness = ring.opaqueness(|_| 1, 100);
+    let start = center + Coordinate::new(0, -2);
+    let map = fov::fov(&opaqueness, 10, start, hex2d::Direction::all());
+    assert!(map.iter().all(|(c, _)| ring.contains(c) || c.neighbors().iter().any(|&n| ring.contains(n))));
+    assert!(!map.is_visible(center));
+}
+
+#[test]
+fn traverser_builder() {
+    use algo::bfs::Traverser;
+    use geom::Bounds;
+
+    let start = Coordinate::new(0, 0);
+
+    let mut traverser = Traverser::builder(start).max_distance(3).build();
+    let mut count = 0;
+    while traverser.find().is_some() {
+        count += 1;
+    }
+    assert_eq!(count, 37);
+
+    let bounds = Bounds::rectangle(Coordinate::new(-2, 2), 10, 4);
+    let wall = Coordinate::new(1, -1);
+    let mut traverser = Traverser::builder(start)
+        .can_pass(|c| c != wall)
+        .is_dest(|c : Coordinate| c.x == 3)
+        .bounds(bounds)
+        .capacity(40)
+        .build();
+    let mut found = 0;
+    while let Some(c) = traverser.find() {
+        assert!(bounds.contains(c));
+        assert_eq!(c.x, 3);
+        found += 1;
+    }
+    assert_eq!(found, bounds.iter().filter(|c| c.x == 3).count());
+}
+
+#[test]
+fn stateful_callbacks() {
+    use algo::bfs::Traverser;
+    use algo::{los, los2};
+
+    let start = Coordinate::new(0, 0);
+
+    let mut queries = 0;
+    let mut traverser = Traverser::new(|c : Coordinate| { queries += 1; c.distance(start) < 3 }, |_| false, start);
+    assert_eq!(traverser.find(), None);
+    drop(traverser);
+    assert!(queries >= 19);
+
+    let mut queries = 0;
+    los::los(|_| { queries += 1; 1 }, &mut |_, _| {}, 3, start, hex2d::Direction::all());
+    assert!(queries > 0);
+
+    let mut queries = 0;
+    los2::los(|_| { queries += 1; 1 }, &mut |_, _| {}, 3, start, hex2d::Direction::all());
+    assert!(queries > 0);
+}
+
+#[test]
+fn los_stops_early() {
+    use algo::{los, los2};
+    use std::ops::ControlFlow;
+
+    let start = Coordinate::new(0, 0);
+    let target = Coordinate::new(2, -1);
+
+    let mut calls = 0;
+    los2::los(&|_| 1, &mut |c, _| {
+        calls += 1;
+        if c == target { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+    }, 10, start, hex2d::Direction::all());
+    let mut all = 0;
+    los2::los(&|_| 1, &mut |_, _| { all += 1; }, 10, start, hex2d::Direction::all());
+    assert!(calls < all);
+
+    let mut seen_target = false;
+    los::los(&|_| 1, &mut |c, _| {
+        assert!(!seen_target);
+        seen_target = c == target;
+        if seen_target { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+    }, 10, start, hex2d::Direction::all());
+    assert!(seen_target);
+}
+
+#[test]
+fn los_iter() {
+    use algo::{los, los2};
+    use std::collections::HashSet;
+
+    let start = Coordinate::new(0, 0);
+    let opaqueness = |c : Coordinate| if c.y == 2 { 100 } else { 1 };
+
+    let mut expected = vec!();
+    los2::los(&opaqueness, &mut |c, l| expected.push((c, l)), 6, start, hex2d::Direction::all());
+    assert_eq!(los2::iter(&opaqueness, 6, start, hex2d::Direction::all()).collect::<Vec<_>>(), expected);
+
+    let bright : HashSet<Coordinate> = los::iter(&opaqueness, 6, start, hex2d::Direction::all())
+        .filter(|&(_, l)| l > 3)
+        .map(|(c, _)| c)
+        .collect();
+    assert!(bright.contains(&start));
+    assert!(bright.iter().all(|c| c.distance(start) < 3));
+
+    let mut expected = vec!();
+    los::los(&opaqueness, &mut |c, l| expected.push((c, l)), 6, start, hex2d::Direction::all());
+    assert_eq!(los::iter(&opaqueness, 6, start, hex2d::Direction::all()).collect::<Vec<_>>(), expected);
+
+    // Stopping early skips the rest of the traversal
+    let calls = ::std::cell::Cell::new(0);
+    let counted = |c| { calls.set(calls.get() + 1); opaqueness(c) };
+    assert_eq!(los::iter(&counted, 6, start, hex2d::Direction::all()).next(), Some((start, 5)));
+    assert_eq!(calls.get(), 1);
+    assert!(los2::iter(&counted, 6, start, hex2d::Direction::all()).nth(3).is_some());
+    assert!(calls.get() < 10);
+}
+
+#[test]
+fn debug_render() {
+    use debug::render::{self, Overlay};
+    use geom::Bounds;
+
+    let bounds = Bounds::hexagon(Coordinate::new(0, 0), 1);
+    assert_eq!(render::render(bounds, |_| 'o'), " o o\no o o\n o o\n");
+
+    let mut overlay = Overlay::new();
+    overlay.start = Some(Coordinate::new(0, 0));
+    overlay.dest = Some(Coordinate::new(1, -1));
+    overlay.blocked.insert(Coordinate::new(-1, 1));
+    overlay.visible.insert(Coordinate::new(0, 1));
+    overlay.path.push(Coordinate::new(1, 0));
+    let diagram = overlay.render(bounds);
+    assert_eq!(diagram.matches('S').count(), 1);
+    assert_eq!(diagram.matches('D').count(), 1);
+    assert_eq!(diagram.matches('#').count(), 1);
+    assert_eq!(diagram.matches('.').count(), 1);
+    assert_eq!(diagram.matches('*').count(), 1);
+    assert_eq!(diagram.matches('-').count(), 2);
+    assert_eq!(diagram.lines().nth(1), Some("# S D"));
+
+    let rect = Bounds::rectangle(Coordinate::new(0, 0), 3, 2);
+    assert_eq!(render::render(rect, |_| 'o'), "o o o\n o o o\n");
+}
+
+#[test]
+fn los_ref_validates_los2() {
+    use algo::{los2, los_ref};
+    use std::collections::HashSet;
+
+    let start = Coordinate::new(0, 0);
+    let pillars = [Coordinate::new(2, -1), Coordinate::new(-3, 1), Coordinate::new(0, 4)];
+    let opaqueness = |c : Coordinate| if pillars.contains(&c) { 100 } else { 1 };
+
+    let mut reference = HashSet::new();
+    los_ref::los(&opaqueness, &mut |c, _| { reference.insert(c); }, 8, start, 7);
+    let mut fast = HashSet::new();
+    los2::los(&opaqueness, &mut |c, _| { fast.insert(c); }, 8, start, hex2d::Direction::all());
+
+    assert!(reference.contains(&start));
+    assert!(pillars.iter().all(|p| reference.contains(p)));
+    assert!(!reference.contains(&Coordinate::new(4, -2)));
+    assert!(reference.iter().all(|c| c.distance(start) <= 7));
+
+    let artifacts = reference.symmetric_difference(&fast).count();
+    assert!(artifacts * 5 < reference.len(), "{} artifacts", artifacts);
+}
+
+#[test]
+fn traverser_visit_order_is_stable() {
+    use algo::bfs::Traverser;
+
+    let start = Coordinate::new(0, 0);
+    let run = || {
+        let mut traverser = Traverser::builder(start).max_distance(4).build();
+        while traverser.find().is_some() {}
+        let order : Vec<Coordinate> = traverser.visited().collect();
+        let dists : Vec<u32> = order.iter().map(|&c| traverser.distance(c).unwrap()).collect();
+        (order, dists)
+    };
+
+    let (order, dists) = run();
+    assert_eq!(order[0], start);
+    assert_eq!(order.len(), 61);
+    assert!(dists.windows(2).all(|w| w[0] <= w[1]));
+    assert_eq!(run().0, order);
+}
+
+#[cfg(feature = "deterministic")]
+#[test]
+fn deterministic_result_iteration() {
+    use algo::{blast, bfs, fov};
+    use gen::{cellular, maze};
+    use geom::Bounds;
+    use map::HexMap;
+
+    let run = || {
+        let map = fov::fov(&|c : Coordinate| if c.y == 2 { 100 } else { 1 }, 8, Coordinate::new(0, 0), hex2d::Direction::all());
+        map.iter().collect::<Vec<_>>()
+    };
+    assert_eq!(run(), run());
+
+    let returned = || {
+        let can_pass = |c : Coordinate| c.x != 2 || c.y == 0;
+        let distances = distance::from_obstacles(can_pass, Coordinate::new(0, 0).range_iter(6));
+        let ranges = bfs::walk_ranges_intersection(&[(Coordinate::new(0, 0), 5), (Coordinate::new(3, 0), 4)], can_pass);
+        let cave = cellular::generate(Bounds::hexagon(Coordinate::new(0, 0), 8), &cellular::Config::default(), &mut StdRng::seed_from_u64(4));
+        let maze = maze::generate(Bounds::hexagon(Coordinate::new(0, 0), 4), maze::Algorithm::Kruskal, &mut StdRng::seed_from_u64(4));
+        let mut resistance = HexMap::from_fn(Bounds::hexagon(Coordinate::new(0, 0), 6), |c : Coordinate| if c.x == 2 { 3 } else { 0 });
+        let blast = blast::explode(&mut resistance, 6, 1, Coordinate::new(0, 0));
+        (
+            distances.into_iter().collect::<Vec<_>>(),
+            ranges.into_iter().collect::<Vec<_>>(),
+            cave.into_iter().collect::<Vec<_>>(),
+            maze.cells().into_iter().collect::<Vec<_>>(),
+            blast.damage.into_iter().collect::<Vec<_>>(),
+        )
+    };
+    assert_eq!(returned(), returned());
+}
+
+#[test]
+fn interop_graph_export() {
+    use geom::Bounds;
+    use interop::graph::Graph;
+
+    let bounds = Bounds::hexagon(Coordinate::new(0, 0), 2);
+    let wall = Coordinate::new(1, 0);
+    let graph = Graph::from_region(bounds, |c| c != wall, |a : Coordinate, b : Coordinate| a.distance(b) as u32);
+
+    assert_eq!(graph.node_count(), bounds.len() - 1);
+    assert_eq!(graph.node(wall), None);
+    for (i, &c) in graph.nodes().iter().enumerate() {
+        assert_eq!(graph.node(c), Some(i));
+        assert_eq!(graph.coordinate(i), c);
+    }
+
+    let center = graph.node(Coordinate::new(0, 0)).unwrap();
+    assert_eq!(graph.edges().iter().filter(|&&(from, _, _)| from == center).count(), 5);
+    assert!(graph.edges().iter().all(|&(from, to, w)| {
+        w == 1 && graph.edges().iter().any(|&(f, t, _)| f == to && t == from)
+    }));
+}
+
+#[cfg(feature = "petgraph")]
+#[test]
+fn interop_graph_petgraph() {
+    use geom::Bounds;
+    use interop::graph::{self, Graph};
+    use petgraph::algo::dijkstra;
+
+    let center = Coordinate::new(0, 0);
+    let bounds = Bounds::hexagon(center, 3);
+    let wall = Coordinate::new(1, 0);
+    let graph = Graph::from_region(bounds, |c| c != wall, |_ : Coordinate, _ : Coordinate| 1u32);
+
+    let directed = graph.to_petgraph();
+    assert_eq!(directed.edge_count(), graph.edges().len());
+    let undirected = graph.to_petgraph_undirected();
+    assert_eq!(undirected.edge_count() * 2, graph.edges().len());
+
+    let start = graph.node_index(center).unwrap();
+    assert_eq!(undirected[start], center);
+    let distances = dijkstra(&undirected, start, None, |e| *e.weight());
+    let map = graph::to_map(&undirected, distances, bounds, u32::MAX);
+    assert_eq!(map[wall], u32::MAX);
+    assert_eq!(map[Coordinate::new(2, 0)], 3);
+    assert_eq!(map[Coordinate::new(-3, 0)], 3);
+}
+
+#[cfg(feature = "tiled")]
+#[test]
+fn interop_tiled_import() {
+    use interop::tiled;
+
+    let tmx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="hexagonal" renderorder="right-down" width="3" height="2"
+     tilewidth="32" tileheight="32" hexsidelength="16" staggeraxis="y" staggerindex="odd">
+ <tileset firstgid="1" name="terrain" tilewidth="32" tileheight="32" tilecount="2">
+  <!-- wall -->
+  <tile id="1">
+   <properties>
+    <property name="passable" type="bool" value="false"/>
+    <property name="opaqueness" type="int" value="100"/>
+   </properties>
+  </tile>
+ </tileset>
+ <layer id="1" name="ground" width="3" height="2">
+  <data encoding="csv">
+1,2,1,
+1,1,2147483649
+</data>
+ </layer>
+</map>"#;
+
+    let map = tiled::parse(tmx).unwrap();
+    assert_eq!(map.layers.len(), 1);
+    let (ref name, ref ground) = map.layers[0];
+    assert_eq!(name, "ground");
+    assert_eq!(ground.len(), 6);
+
+    // Second row is shifted, so its first tile is south-east of the first tile of the first row
+    let wall = Coordinate::new(1, -1);
+    assert_eq!(ground[wall], 2);
+    assert_eq!(ground[Coordinate::new(0, -1)], 1);
+    assert_eq!(ground[Coordinate::new(2, -3)], 1);
+    assert!(!map.passable[wall]);
+    assert_eq!(map.opaqueness[wall], 100);
+    assert!(map.passable[Coordinate::new(0, 0)]);
+    assert_eq!(map.opaqueness[Coordinate::new(0, 0)], 1);
+
+    assert_eq!(tiled::parse(&tmx.replace("hexagonal", "orthogonal")),
+               Err(tiled::Error::Unsupported("orientation is not hexagonal".into())));
+    assert!(tiled::parse(&tmx.replace("1,1,2147483649", "1,1")).is_err());
+}
+
+#[cfg(feature = "tiled")]
+#[test]
+fn interop_tiled_import_tiled_export() {
+    use interop::tiled;
+
+    // Layout of a Tiled 1.10 save, with escaped and raw `>`, entities, CDATA and a layer group
+    let tmx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" tiledversion="1.10.2" orientation="hexagonal" renderorder="right-down" width="2" height="2" tilewidth="28" tileheight="32" hexsidelength="16" staggeraxis="x" staggerindex="odd" infinite="0" nextlayerid="4" nextobjectid="1">
+ <properties>
+  <property name="note" value="a &gt; b &amp;&amp; c > d"/>
+ </properties>
+ <tileset firstgid="1" name="hex &amp; co" tilewidth="28" tileheight="32" tilecount="2" columns="2">
+  <image source="hex.png" width="56" height="32"/>
+  <tile id="0">
+   <properties>
+    <property name="comment" value="&lt;floor&gt;"/>
+   </properties>
+  </tile>
+  <tile id="1">
+   <properties>
+    <property name="opaqueness" type="int" value="7"/>
+    <property name="passable" type="bool" value="false"/>
+   </properties>
+  </tile>
+ </tileset>
+ <group id="3" name="terrain">
+  <layer id="1" name="walls &amp; floors" width="2" height="2">
+   <data encoding="csv"><![CDATA[
+1,2,
+1,1
+]]></data>
+  </layer>
+ </group>
+</map>
+"#;
+
+    let map = tiled::parse(tmx).unwrap();
+    assert_eq!(map.layers.len(), 1);
+    assert_eq!(map.layers[0].0, "walls & floors");
+    assert_eq!(map.passable.iter().filter(|&(_, &p)| !p).count(), 1);
+    assert_eq!(map.opaqueness.iter().map(|(_, &o)| o).max(), Some(7));
+    assert!(tiled::parse(&tmx.replace("</map>", "")).is_err());
+}
+
+#[test]
+fn interop_ascii_parse() {
+    use debug::render;
+    use interop::ascii;
+    use algo::bfs::Traverser;
+    use map::Grid;
+
+    let (map, markers) = ascii::parse("
+        # # # # #
+         # S . # #
+        # . # D #
+         # # # # #
+    ");
+    assert_eq!(map.len(), 20);
+    assert_eq!(map.get(Coordinate::new(0, 0)), Some(&'#'));
+    let start = markers[&'S'];
+    let dest = markers[&'D'];
+    assert_eq!(start, Coordinate::new(1, -2));
+    assert_eq!(map[start], '.');
+
+    let can_pass = map.passable(|&t| t != '#');
+    let mut traverser = Traverser::new(&can_pass, |c| c == dest, start);
+    assert_eq!(traverser.find(), Some(dest));
+    assert_eq!(traverser.distance(dest), Some(2));
+
+    let (hex, markers) = ascii::parse("
+         . .
+        . A .
+         . .
+    ");
+    assert_eq!(hex.bounds(), ::geom::Bounds::hexagon(Coordinate::new(0, 0), 1));
+    assert_eq!(markers[&'A'], Coordinate::new(0, 0));
+    assert_eq!(render::render(hex.bounds(), |c| hex[c]), " . .\n. . .\n . .\n");
+}
+
+#[cfg(feature = "image")]
+#[test]
+fn debug_image_heatmap() {
+    use debug::image;
+    use geom::Bounds;
+    use map::HexMap;
+    use hex2d::Spacing;
+
+    let bounds = Bounds::hexagon(Coordinate::new(0, 0), 4);
+    let map = HexMap::from_fn(bounds, |c : Coordinate| c.distance(Coordinate::new(0, 0)) as f32);
+
+    for &spacing in [Spacing::PointyTop(6.0), Spacing::FlatTop(6.0)].iter() {
+        let png = image::hexmap(&map, spacing);
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[12..16], b"IHDR");
+        let width = u32::from_be_bytes([png[16], png[17], png[18], png[19]]) as usize;
+        let height = u32::from_be_bytes([png[20], png[21], png[22], png[23]]) as usize;
+        assert!(width > 80 && height > 80);
+
+        // Stored deflate blocks: data + 5 bytes per block + zlib header and checksum
+        let idat = u32::from_be_bytes([png[33], png[34], png[35], png[36]]) as usize;
+        let raw = (width * 3 + 1) * height;
+        assert_eq!(idat, raw + 5 * raw.div_ceil(0xffff) + 6);
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+    }
+}
+
+/// Run the public APIs with Coordinates of integer type `I`
+fn integer_type_roundtrip<I>() where
+    I : hex2d::Integer,
+    I : ::std::hash::Hash,
+    I : ::std::ops::AddAssign,
+    I : ::std::fmt::Debug,
+    I : Send + Sync + 'static
+{
+    use algo::{bfs, fov, los, los2, los_ref};
+    use geom::Bounds;
+    use map::{HexBitSet, HexMap};
+    use gen::cellular;
+    use snapshot;
+
+    let int = |v : i8| I::from_i8(v).unwrap();
+    let center = Coordinate::new(int(0), int(0));
+    let wall = Coordinate::new(int(1), int(0));
+    let bounds = Bounds::hexagon(center, int(3));
+    let can_pass = |c : Coordinate<I>| c != wall;
+    let opaqueness = |c : Coordinate<I>| if c == wall { 10 } else { 1 };
+    let dirs = &hex2d::Direction::all()[..];
+
+    let mut bfs = bfs::Traverser::builder(center)
+        .can_pass(can_pass)
+        .bounds(bounds)
+        .build();
+    while bfs.find().is_some() {}
+    assert_eq!(bfs.visited().count(), 37);
+    assert_eq!(bfs.distance(Coordinate::new(int(2), int(0))), Some(3));
+    assert_eq!(bfs::reachable(center, can_pass, bounds).len(), 36);
+
+    let mut seen = 0;
+    los::los(opaqueness, &mut |_, _| seen += 1, 4, center, dirs);
+    los2::los(opaqueness, &mut |_, _| seen += 1, 4, center, dirs);
+    los_ref::los(opaqueness, &mut |_, _| seen += 1, 4, center, int(3));
+    assert!(seen > 0);
+
+    #[cfg(feature = "rayon")]
+    {
+        let fovs = fov::fov_many(&opaqueness, &[(center, 4, dirs)]);
+        assert_eq!(fovs[0].len(), fov::fov(opaqueness, 4, center, dirs).len());
+    }
+    assert!(!fov::fov(opaqueness, 4, center, dirs).is_visible(Coordinate::new(int(2), int(0))));
+
+    let map = HexMap::from_fn(bounds, |c| c == wall);
+    assert_eq!(snapshot::decode_map::<bool, I>(&snapshot::encode_map(&map)), Some(map));
+
+    let mut set = HexBitSet::new(bounds);
+    set.extend(bounds.iter().filter(|&c| can_pass(c)));
+    assert_eq!(set.len(), 36);
+
+    let config = cellular::Config::default();
+    let cave = cellular::generate(bounds, &config, &mut StdRng::seed_from_u64(3));
+    assert!(cave.iter().all(|&c| bounds.contains(c)));
+}
+
+#[test]
+fn integer_types() {
+    integer_type_roundtrip::<i16>();
+    integer_type_roundtrip::<i32>();
+    integer_type_roundtrip::<i64>();
+}
+
+#[test]
+fn fov_light_types() {
+    use algo::fov;
+
+    let center = Coordinate::new(0, 0);
+    let wall = |c : Coordinate| c.x == 2;
+    let dirs = &hex2d::Direction::all()[..];
+
+    let int = fov::fov(|c| if wall(c) { 100 } else { 1 }, 6, center, dirs);
+    let unsigned = fov::fov(|c| if wall(c) { 100u32 } else { 1 }, 6, center, dirs);
+    let float = fov::fov(|c| if wall(c) { 100.0f32 } else { 0.5 }, 6.0, center, dirs);
+
+    assert_eq!(unsigned.len(), int.len());
+    for (c, l) in int.iter() {
+        assert_eq!(unsigned.light(c), Some(if l < 0 { 0 } else { l as u32 }));
+    }
+    assert_eq!(unsigned.light(Coordinate::new(2, -1)), Some(0));
+
+    // Cheaper light goes further, but walls still block
+    assert!(float.len() > int.len());
+    assert!(!float.is_visible(Coordinate::new(3, -1)));
+    assert!(float.light(center).unwrap() > 5.0);
+}
+
+#[test]
+fn bfs_position_one_way_door() {
+    use algo::bfs_position::Traverser;
+    use hex2d::{Direction, Position};
+
+    // A wall along x == 1 with a door that can only be entered heading east
+    let door = Coordinate::new(1, 0);
+    let can_pass = |p : Position| {
+        p.coord.distance(Coordinate::new(0, 0)) <= 3 &&
+            (p.coord.x != 1 || (p.coord == door && p.dir == Direction::XZ))
+    };
+
+    let start = Position::new(Coordinate::new(-1, 1), Direction::YZ);
+    let dest = Coordinate::new(2, -1);
+    let mut bfs = Traverser::new(can_pass, |p : Position| p.coord == dest, start);
+    let found = bfs.find().unwrap();
+    let path = bfs.path(found).unwrap();
+
+    assert_eq!(path[0], start);
+    assert_eq!(*path.last().unwrap(), found);
+    assert_eq!(bfs.distance(found), Some(path.len() as u32 - 1));
+    assert!(path.contains(&Position::new(door, Direction::XZ)));
+    for w in path.windows(2) {
+        assert_eq!(w[0].coord + w[1].dir, w[1].coord);
+    }
+
+    // Nothing can come back through the door
+    let mut back = Traverser::new(can_pass, |p : Position| p.coord == start.coord, Position::new(dest, Direction::YZ));
+    assert_eq!(back.find(), None);
+}
+
+#[test]
+fn searches_neighbor_filter() {
+    use algo::{astar, bfs, bfs_position, dijkstra};
+    use hex2d::{Angle, Direction, Position};
+
+    let start = Coordinate::new(0, 0);
+    let forward = |_, _, dir| dir == Direction::YZ || dir == Direction::XZ;
+
+    let mut search = bfs::Traverser::builder(start)
+        .max_distance(3)
+        .neighbor_filter(forward)
+        .build();
+    while search.find().is_some() {}
+    assert_eq!(search.visited().count(), 10);
+    let cone : Vec<_> = search.visited().collect();
+
+    let mut search = dijkstra::Traverser::builder(start)
+        .cost(|_, to : Coordinate| if to.distance(start) <= 3 { Some(1) } else { None })
+        .neighbor_filter(forward)
+        .build();
+    let mut reached = vec!();
+    while let Some(c) = search.find() {
+        reached.push(c);
+    }
+    assert!(reached.len() == cone.len() && reached.iter().all(|c| cone.contains(c)));
+
+    let cost = |_, to : Coordinate| if to.distance(start) <= 3 { Some(1) } else { None };
+    let behind = start + Direction::ZY;
+    assert!(astar::find(start, behind, dijkstra::filter_steps(cost, forward), |_| 0).is_none());
+    assert_eq!(astar::find(start, behind, cost, |_| 0).map(|(_, cost)| cost), Some(1));
+
+    // Never turn back
+    let origin = Position::new(start, Direction::YZ);
+    let mut search = bfs_position::Traverser::new(|p : Position| p.coord.distance(start) <= 3, |p : Position| p.coord == behind, origin)
+        .neighbor_filter(|from : Position, _, dir| dir != from.dir + Angle::Back);
+    let found = search.find().unwrap();
+    assert_eq!(search.distance(found), Some(2));
+}
+
+#[test]
+fn topology_cylinder_wraps() {
+    use algo::{bfs, fov};
+    use geom::{Bounds, Topology};
+
+    let origin = Coordinate::new(0, 0);
+    let bounds = Bounds::rectangle(origin, 10, 6);
+    let topology = Topology::cylinder(origin, 10);
+    let east_edge = Coordinate::new(9, -9);
+
+    for c in Bounds::rectangle(Coordinate::new(-7, 7), 30, 6).iter() {
+        assert!(bounds.contains(topology.wrap(c)));
+    }
+    for c in bounds.iter() {
+        assert_eq!(topology.wrap(c), c);
+    }
+    assert_eq!(topology.distance(origin, east_edge), 1);
+    assert_eq!(Topology::Flat.distance(origin, east_edge), 9);
+
+    let mut bfs = bfs::Traverser::builder(origin)
+        .bounds(bounds)
+        .topology(topology)
+        .is_dest(|c| c == east_edge)
+        .build();
+    assert_eq!(bfs.find(), Some(east_edge));
+    assert_eq!(bfs.distance(east_edge), Some(1));
+    assert!(bfs.visited().all(|c| bounds.contains(c)));
+
+    let seen = fov::fov_wrapped(topology, |_| 1, 4, origin, hex2d::Direction::all());
+    assert!(seen.is_visible(east_edge));
+    assert!(seen.iter().all(|(c, _)| topology.wrap(c) == c));
+}
+
+#[test]
+fn dijkstra_portals() {
+    use algo::dijkstra::Traverser;
+
+    let center = Coordinate::new(0, 0);
+    // Two halves of a hexagon split by a wall along x == 0
+    let cost = |_ : Coordinate, to : Coordinate| {
+        if to.distance(center) <= 5 && to.x != 0 { Some(1) } else { None }
+    };
+    let start = Coordinate::new(-3, 0);
+    let dest = Coordinate::new(3, 0);
+    let stairs = (Coordinate::new(-1, 0), Coordinate::new(1, 0), 5);
+    let teleporter = (start, dest, 20);
+
+    let mut walled = Traverser::builder(start).cost(cost).is_dest(|c| c == dest).build();
+    assert_eq!(walled.find(), None);
+
+    let mut search = Traverser::builder(start)
+        .cost(cost)
+        .is_dest(|c| c == dest)
+        .portals(&[stairs, teleporter])
+        .build();
+    assert_eq!(search.find(), Some(dest));
+    assert_eq!(search.distance(dest), Some(9));
+    assert_eq!(search.path(dest).unwrap(), vec!(
+        start, Coordinate::new(-2, 0), stairs.0, stairs.1, Coordinate::new(2, 0), dest,
+    ));
+    assert_eq!(search.backtrace(stairs.1), Some(stairs.0));
+}
+
+#[test]
+fn dijkstra_best_target() {
+    use algo::dijkstra::best_target;
+
+    let start = Coordinate::new(0, 0);
+    let cost = |_ : Coordinate, to : Coordinate| if to.distance(start) <= 15 { Some(1) } else { None };
+    let gold = (Coordinate::new(3, 0), 1.0);
+    let near_food = (Coordinate::new(-6, 0), 3.0);
+    let far_food = (Coordinate::new(0, 12), 3.0);
+
+    let (best, path) = best_target(start, cost, &[gold, near_food]).unwrap();
+    assert_eq!(best, near_food.0);
+    assert_eq!(path.len(), 7);
+    assert_eq!((path[0], path[6]), (start, near_food.0));
+
+    let (best, path) = best_target(start, cost, &[far_food, gold]).unwrap();
+    assert_eq!(best, gold.0);
+    assert_eq!(path.len(), 4);
+
+    assert_eq!(best_target(start, cost, &[(Coordinate::new(20, 0), 1.0)]), None);
+    assert_eq!(best_target(start, cost, &[(gold.0, 0.0)]), None);
+}
+
+#[test]
+fn dijkstra_flee() {
+    use algo::dijkstra;
+
+    let threats = [Coordinate::new(0, 0), Coordinate::new(0, 3)];
+    let start = Coordinate::new(1, 1);
+    let wall = Coordinate::new(2, 1);
+    let cost = |_, to : Coordinate| if to != wall { Some(1) } else { None };
+    let safety = |c : Coordinate| threats.iter().map(|&t| t.distance(c)).min().unwrap() as f32;
+
+    let (safe, path) = dijkstra::flee(start, 3, cost, safety);
+    assert_eq!(safety(safe), 4.0);
+    assert_eq!(path.len(), 4);
+    assert_eq!(path[0], start);
+    assert_eq!(*path.last().unwrap(), safe);
+    assert!(!path.contains(&wall));
+
+    assert_eq!(dijkstra::flee(start, 0, cost, safety), (start, vec!(start)));
+    assert_eq!(dijkstra::flee(start, 5, cost, |_| 0.0), (start, vec!(start)));
+}
+
+#[test]
+fn ambush_spots_behind_wall() {
+    use algo::{ambush, fov};
+
+    let path : Vec<Coordinate> = (-3..=3).map(|x| Coordinate::new(x, -x)).collect();
+    let wall = |c : Coordinate| c.z() == -2 && c.x.abs() <= 6;
+    let opaqueness = |c : Coordinate| if wall(c) { 100 } else { 1 };
+
+    let spots = ambush::spots(&path, opaqueness, |c| !wall(c), 8, 3);
+
+    assert!(!spots.is_empty());
+    assert!(spots.iter().all(|c| c.z() == -3));
+    for &pos in path.iter() {
+        let seen = fov::fov(opaqueness, 8, pos, hex2d::Direction::all());
+        assert!(spots.iter().all(|&c| !seen.is_visible(c)));
+    }
+    assert!(ambush::spots(&path, |_| 1, |_| true, 8, 3).is_empty());
+}
+
+#[test]
+fn cover_and_flanking() {
+    use algo::cover::{self, Cover};
+    use hex2d::Direction;
+
+    let target = Coordinate::new(0, 0);
+    let rock = target + Direction::XY;
+    let blocks = |c : Coordinate| c == rock;
+
+    assert_eq!(cover::from_direction(target, Direction::XY, blocks), Cover::Full);
+    assert_eq!(cover::from_direction(target, Direction::XY + hex2d::Angle::Left, blocks), Cover::Partial);
+    assert_eq!(cover::from_direction(target, -Direction::XY, blocks), Cover::None);
+
+    let behind_rock = target + Coordinate::new(3 * (rock.x - target.x), 3 * (rock.y - target.y));
+    assert_eq!(cover::against(target, behind_rock, blocks), Cover::Full);
+    assert_eq!(cover::against(target, rock, |_| true), Cover::None);
+    assert_eq!(cover::against(target, Coordinate::new(-3, 3), blocks), Cover::None);
+
+    // A line running exactly along the rock's edge only grazes it
+    let grazing = (-6..=6)
+        .flat_map(|x| (-6..=6).map(move |y| Coordinate::new(x, y)))
+        .filter(|&c| cover::against(target, c, blocks) == Cover::Partial)
+        .count();
+    assert!(grazing > 0);
+
+    assert!(cover::is_flanked(target, Coordinate::new(2, 0), Coordinate::new(-2, 0)));
+    assert!(cover::is_flanked(target, Coordinate::new(2, 0), Coordinate::new(0, -2)));
+    assert!(!cover::is_flanked(target, Coordinate::new(2, 0), Coordinate::new(0, 2)));
+    assert!(!cover::is_flanked(target, Coordinate::new(2, 0), Coordinate::new(2, -1)));
+    assert!(!cover::is_flanked(target, target, Coordinate::new(2, 0)));
+}
+
+#[test]
+fn zone_of_control_stops_movement() {
+    use algo::{bfs, dijkstra};
+
+    let start = Coordinate::new(0, 0);
+    let enemy = Coordinate::new(3, 0);
+    let in_zoc = bfs::zone_of_control(|c| c == enemy);
+    let can_pass = |c : Coordinate| c != enemy;
+
+    let mut bfs = bfs::Traverser::builder(start)
+        .can_pass(can_pass)
+        .max_distance(10)
+        .zone_of_control(&in_zoc)
+        .build();
+    while bfs.find().is_some() {}
+
+    assert!(bfs.visited().any(&in_zoc));
+    for c in bfs.visited().filter(|&c| c != start) {
+        let prev = bfs.backtrace(c).unwrap();
+        assert!(prev == start || !in_zoc(prev));
+    }
+    // Right behind the enemy, only reachable around its zone
+    let behind = Coordinate::new(4, 0);
+    assert_eq!(bfs.distance(behind), Some(7));
+
+    let mut dijkstra = dijkstra::Traverser::builder(start)
+        .cost(|_, to| if to.distance(start) <= 10 && can_pass(to) { Some(1) } else { None })
+        .zone_of_control(&in_zoc)
+        .is_dest(|c| c == behind)
+        .build();
+    assert_eq!(dijkstra.find(), Some(behind));
+    assert_eq!(dijkstra.distance(behind), Some(7));
+
+    // Units starting in a zone of control can leave it
+    let mut leaving = bfs::Traverser::builder(Coordinate::new(2, 0))
+        .zone_of_control(&in_zoc)
+        .max_distance(1)
+        .build();
+    while leaving.find().is_some() {}
+    assert_eq!(leaving.visited().count(), 7);
+}
+
+#[test]
+fn blast_destroys_weak_blockers() {
+    use algo::blast;
+    use geom::Bounds;
+    use map::HexMap;
+
+    let center = Coordinate::new(0, 0);
+    let wall = |c : Coordinate| {
+        match (c.distance(center), c.x) {
+            (2, 2) => 100,
+            (2, _) => 3,
+            _ => 0,
+        }
+    };
+    let mut resistance = HexMap::from_fn(Bounds::hexagon(center, 6), wall);
+
+    let weak = blast::explode(&mut resistance.clone(), 3, 1, center);
+    assert!(weak.destroyed.is_empty());
+    assert_eq!(weak.damage.len(), 19);
+
+    let res = blast::explode(&mut resistance, 10, 1, center);
+    assert_eq!(res.damage[&center], 10);
+    assert_eq!(res.damage[&Coordinate::new(2, 0)], 8);
+    assert_eq!(res.damage[&Coordinate::new(-3, 0)], 4);
+    assert_eq!(res.damage[&Coordinate::new(-6, 0)], 1);
+
+    assert_eq!(res.destroyed.len(), 12 - 3);
+    for c in Bounds::hexagon(center, 6).iter() {
+        let expected = if res.destroyed.contains(&c) { 0 } else { wall(c) };
+        assert_eq!(resistance[c], expected);
+    }
+
+    // Power of other types than the Coordinates
+    let mut unsigned = HexMap::from_fn(Bounds::hexagon(center, 6), |c| wall(c) as u32);
+    let res_u32 = blast::explode(&mut unsigned, 10u32, 1, center);
+    assert_eq!(res_u32.destroyed, res.destroyed);
+    assert!(res.damage.iter().all(|(c, &d)| res_u32.damage[c] == d as u32));
+
+    let mut float = HexMap::from_fn(Bounds::hexagon(center, 6), |c| wall(c) as f32);
+    let res_f32 = blast::explode(&mut float, 2.5f32, 0.5, center);
+    assert_eq!(res_f32.damage[&Coordinate::new(2, 0)], 1.5);
+    assert!(res_f32.destroyed.is_empty());
+}
+
+#[test]
+fn sim_fluid_spreads_and_flows_downhill() {
+    use geom::Bounds;
+    use map::HexMap;
+    use sim::fluid;
+
+    let center = Coordinate::new(0, 0);
+    let bounds = Bounds::hexagon(center, 5);
+    let config = fluid::Config::default();
+    let total = |m : &HexMap<f32>| m.iter().map(|(_, &a)| a).sum::<f32>();
+
+    // Smoke in a room closed by a ring of walls
+    let wall = |c : Coordinate| c.distance(center) == 3;
+    let mut gas = HexMap::new(bounds, 0.0);
+    gas[center] = 100.0;
+    for _ in 0..50 {
+        fluid::tick(&mut gas, None, |c| if wall(c) { 0.0 } else { 1.0 }, &config);
+    }
+    assert!((total(&gas) - 100.0).abs() < 0.01);
+    assert!(gas[center] < 50.0);
+    assert!(gas[Coordinate::new(2, 0)] > 1.0);
+    assert!(bounds.iter().filter(|&c| c.distance(center) >= 3).all(|c| gas[c] == 0.0));
+
+    // Water poured on a slope ends up at the bottom
+    let height = HexMap::from_fn(bounds, |c| c.x as f32);
+    let mut water = HexMap::new(bounds, 0.0);
+    water[Coordinate::new(5, -5)] = 10.0;
+    for _ in 0..200 {
+        fluid::tick(&mut water, Some(&height), |_| 1.0, &config);
+    }
+    assert!((total(&water) - 10.0).abs() < 0.01);
+    assert!(water.iter().all(|(_, &a)| a >= 0.0));
+    let low : f32 = water.iter().filter(|&(c, _)| c.x <= -3).map(|(_, &a)| a).sum();
+    assert!(low > 5.0);
+}
+
+#[test]
+fn sim_fire_spreads_and_burns_out() {
+    use geom::Bounds;
+    use map::HexMap;
+    use sim::fire::{Fire, State};
+
+    let center = Coordinate::new(0, 0);
+    let bounds = Bounds::hexagon(center, 6);
+    // A river of water along x == 3 with nothing to burn
+    let fuel = HexMap::from_fn(bounds, |c| if c.x == 3 { 0 } else { 3 });
+    let flammability = HexMap::new(bounds, 0.5);
+
+    let run = |seed| {
+        let mut fire = Fire::new(flammability.clone(), fuel.clone());
+        assert!(fire.ignite(center));
+        assert!(!fire.ignite(center));
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut log = vec!();
+        while fire.is_burning() {
+            log.push(fire.tick(&mut rng));
+        }
+        (fire, log)
+    };
+
+    let (fire, log) = run(7);
+    assert_eq!(log, run(7).1);
+    assert_eq!(fire.state(center), Some(State::Burnt));
+    assert_eq!(fire.fuel(center), Some(0));
+    assert!(fire.states().iter().filter(|&(_, &s)| s == State::Burnt).count() > 10);
+    assert!(fire.states().iter().all(|(c, &s)| c.x < 3 || s == State::Unburnt));
+    for changes in log.iter() {
+        assert!(changes.iter().all(|&(_, s)| s != State::Unburnt));
+    }
+
+    let flammability = HexMap::from_fn(bounds, |c| if c.x > 0 { f32::NAN } else { 2.0 });
+    let mut fire = Fire::new(flammability, fuel.clone());
+    assert!(fire.ignite(center));
+    let mut rng = StdRng::seed_from_u64(7);
+    while fire.is_burning() {
+        fire.tick(&mut rng);
+    }
+    assert!(fire.states().iter().all(|(c, &s)| (c.x <= 0) == (s == State::Burnt)));
+}
+
+#[test]
+fn sim_territory_growth() {
+    use geom::Bounds;
+    use sim::territory::{Contest, FactionId, Territory};
+
+    let bounds = Bounds::hexagon(Coordinate::new(0, 0), 6);
+    let lake = |c : Coordinate| c == Coordinate::new(0, 3);
+    let count = |t : &Territory, id| t.owners().iter().filter(|&(_, &o)| o == Some(id)).count();
+
+    let run = |contest, seed| {
+        let mut territory = Territory::new(bounds, contest);
+        let fast = territory.add_faction(&[Coordinate::new(-5, 0)], 2.0);
+        let slow = territory.add_faction(&[Coordinate::new(5, 0), Coordinate::new(-5, 0)], 0.5);
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut claimed = 2;
+        while territory.is_growing() {
+            claimed += territory.tick(|c| !lake(c), &mut rng).len();
+        }
+        assert_eq!(claimed, bounds.len() - 1);
+        (territory, fast, slow)
+    };
+
+    let (territory, fast, slow) = run(Contest::Fastest, 1);
+    assert_eq!((fast, slow), (FactionId(0), FactionId(1)));
+    assert_eq!(territory.owner(Coordinate::new(-5, 0)), Some(fast));
+    assert_eq!(territory.owner(Coordinate::new(5, 0)), Some(slow));
+    assert_eq!(territory.owner(Coordinate::new(0, 3)), None);
+    assert!(count(&territory, fast) > 3 * count(&territory, slow));
+
+    let (a, _, _) = run(Contest::Random, 5);
+    let (b, _, _) = run(Contest::Random, 5);
+    assert_eq!(a.owners(), b.owners());
+}
+
+#[test]
+fn sim_projectile_trace() {
+    use hex2d::Direction;
+    use sim::projectile;
+
+    let from = Coordinate::new(0, 0);
+    let wall = Coordinate::new(4, 0);
+
+    let mut passed = vec!();
+    let impact = projectile::trace(from, Coordinate::new(2, 0), 5, |c| { passed.push(c); c == wall });
+    assert_eq!(impact, Some(wall));
+    assert_eq!(passed, (1..=4).map(|x| Coordinate::new(x, 0)).collect::<Vec<_>>());
+
+    assert_eq!(projectile::trace(from, Coordinate::new(1, 0), 3, |c| c == wall), None);
+    assert_eq!(projectile::trace(from, from, 3, |_| true), None);
+
+    let mut count = 0;
+    assert_eq!(projectile::trace(from, Direction::XY, 5, |_| { count += 1; false }), None);
+    assert_eq!(count, 5);
+
+    // Shots between two Coordinates hit only if both are blocked
+    let (a, b) = (Coordinate::new(1, 0), Coordinate::new(0, 1));
+    let target = Coordinate::new(1, 1);
+    assert_eq!(projectile::trace(from, target, 3, |c| c == a), None);
+    let impact = projectile::trace(from, target, 3, |c| c == a || c == b);
+    assert!(impact == Some(a) || impact == Some(b));
+}
+
+#[test]
+fn path_advance_by_turns() {
+    use algo::path::Path;
+
+    let coords : Vec<Coordinate> = (0..=5).map(|x| Coordinate::new(x, 0)).collect();
+    let swamp = Coordinate::new(3, 0);
+    let cost = |_ : Coordinate, to : Coordinate| Some(if to == swamp { 5 } else { 1 });
+
+    let path = Path::from(coords.clone());
+    assert_eq!((path.start(), path.end(), path.len()), (coords[0], coords[5], 5));
+
+    let (reached, path) = path.advance(3, cost);
+    assert_eq!((reached, path.len(), path.progress()), (coords[2], 3, 1));
+    let (reached, path) = path.advance(3, cost);
+    assert_eq!((reached, path.progress()), (coords[2], 4));
+    let (reached, path) = path.advance(3, cost);
+    assert_eq!(reached, coords[5]);
+    assert!(path.is_empty());
+    assert_eq!(path.advance(10, cost).0, coords[5]);
+
+    let blocked = Coordinate::new(2, 0);
+    let (reached, path) = Path::new(coords.clone()).advance(10, |_, to| if to == blocked { None } else { Some(1) });
+    assert_eq!(reached, coords[1]);
+    assert_eq!(path.coordinates(), &coords[1..]);
+    assert_eq!(path.progress(), 0);
+}
+
+#[test]
+fn traffic_accumulates_paths() {
+    use algo::dijkstra;
+    use algo::path::Path;
+    use algo::traffic::Traffic;
+    use geom::Bounds;
+
+    let center = Coordinate::new(0, 0);
+    let bounds = Bounds::hexagon(center, 5);
+    let door = Coordinate::new(0, 0);
+    let mut traffic = Traffic::new(bounds);
+    assert_eq!(traffic.busiest(), None);
+
+    for y in 0..=4 {
+        let start = Coordinate::new(-4, y);
+        let dest = Coordinate::new(4, -y);
+        let wall = |c : Coordinate| c.x == 0 && c != door;
+        let mut search = dijkstra::Traverser::builder(start)
+            .cost(|_, to : Coordinate| if bounds.contains(to) && !wall(to) { Some(1) } else { None })
+            .is_dest(|c| c == dest)
+            .build();
+        search.find().unwrap();
+        traffic.add_path(&Path::new(search.path(dest).unwrap()));
+    }
+
+    assert_eq!(traffic.busiest(), Some((door, 5)));
+    assert_eq!(traffic.get(Coordinate::new(-4, 0)), 0);
+    assert!(traffic.get(Coordinate::new(4, -2)) >= 1);
+    assert_eq!(traffic.get(Coordinate::new(40, 0)), 0);
+    assert_eq!(traffic.penalty(door, 2.0), 10);
+
+    traffic.decay(0.5);
+    assert_eq!(traffic.get(door), 2);
+    assert!(traffic.counts().iter().all(|(_, &count)| count <= 2));
+}
+
+#[test]
+fn traffic_crowd_spreads_over_doors() {
+    use algo::traffic;
+    use geom::Bounds;
+
+    let bounds = Bounds::hexagon(Coordinate::new(0, 0), 6);
+    let doors = [Coordinate::new(0, 2), Coordinate::new(0, -2)];
+    let cost = |_, to : Coordinate| {
+        if bounds.contains(to) && (to.x != 0 || doors.contains(&to)) { Some(1) } else { None }
+    };
+    let agents = vec!((Coordinate::new(-4, 2), Coordinate::new(4, -2)); 6);
+    let through = |paths : &[Option<::algo::path::Path>], door| {
+        paths.iter().filter(|p| p.as_ref().unwrap().coordinates().contains(&door)).count()
+    };
+
+    let single_file = traffic::plan_crowd(bounds, &agents, cost, 0);
+    assert!(doors.iter().any(|&door| through(&single_file, door) == 6));
+
+    let spread = traffic::plan_crowd(bounds, &agents, cost, 3);
+    assert!(doors.iter().all(|&door| through(&spread, door) >= 2));
+    assert!(spread.iter().all(|p| p.as_ref().unwrap().end() == agents[0].1));
+
+    let walled = traffic::plan_crowd(bounds, &[(agents[0].0, Coordinate::new(4, -2))], |_, to : Coordinate| {
+        if bounds.contains(to) && to.x != 0 { Some(1) } else { None }
+    }, 3);
+    assert_eq!(walled, vec!(None));
+}
+
+#[test]
+fn formation_squeezes_through_gap() {
+    use algo::formation;
+    use algo::path::Path;
+    use hex2d::Direction;
+    use std::collections::HashSet;
+
+    let gap = Coordinate::new(4, 0);
+    let can_pass = |c : Coordinate| c.distance(Coordinate::new(0, 0)) <= 10 && (c.x != 4 || c == gap);
+    let path = Path::new((0..10).map(|x| Coordinate::new(x, 0)).collect());
+    let facing = Coordinate::new(0, 0).direction_to_cw(Coordinate::new(1, 0)).unwrap();
+    let offsets = [Coordinate::from(Direction::XY), Coordinate::from(Direction::ZX), Coordinate::from(Direction::ZY).scale(2)];
+    let slot = |leader : Coordinate, offset : Coordinate| leader + offset.rotate_around_zero(facing - Direction::YZ);
+    let units : Vec<_> = offsets.iter().map(|&offset| (slot(path.start(), offset), offset)).collect();
+
+    let plans = formation::plan(&path, &units, can_pass);
+
+    let ticks = plans[0].len();
+    assert!(ticks > path.len());
+    assert!(plans.iter().all(|plan| plan.len() == ticks));
+    for t in 0..ticks {
+        let leader = path.coordinates()[t.min(path.len())];
+        let mut taken : HashSet<_> = plans.iter().map(|plan| plan[t]).collect();
+        taken.insert(leader);
+        assert_eq!(taken.len(), plans.len() + 1);
+        for plan in plans.iter() {
+            assert!(can_pass(plan[t]));
+            assert!(t == 0 || plan[t].distance(plan[t - 1]) <= 1);
+        }
+    }
+
+    for (plan, &offset) in plans.iter().zip(offsets.iter()) {
+        assert_eq!(plan[1], slot(path.coordinates()[1], offset));
+        assert!(plan.contains(&gap));
+        assert_eq!(plan[ticks - 1], slot(path.end(), offset));
+    }
+}
+
+#[test]
+fn steering_next_step() {
+    use algo::steering::next_step;
+
+    let from = Coordinate::new(0, 0);
+    let goal = Coordinate::new(5, 0);
+    let ahead = Coordinate::new(1, 0);
+    let sides = [Coordinate::new(1, -1), Coordinate::new(0, 1)];
+
+    assert_eq!(next_step(from, goal, |_| true, |_| false), ahead);
+    assert_eq!(next_step(goal, goal, |_| true, |_| false), goal);
+
+    let side = next_step(from, goal, |c| c != ahead, |_| false);
+    assert!(sides.contains(&side));
+
+    let other = next_step(from, goal, |_| true, |c| c == ahead || c == side);
+    assert!(sides.contains(&other) && other != side);
+
+    assert_eq!(next_step(from, goal, |c| c != ahead, |c| sides.contains(&c)), from);
+}
+
+#[test]
+fn los_ref_visibility_matrix() {
+    use algo::los_ref;
+    use std::cell::RefCell;
+    use std::collections::HashSet;
+
+    let pillar = Coordinate::new(0, 0);
+    let opaqueness = |c : Coordinate| if c == pillar { 100 } else { 1 };
+    let observers = [
+        Coordinate::new(-2, 0),
+        Coordinate::new(2, 0),
+        Coordinate::new(0, -2),
+        Coordinate::new(-2, 2),
+    ];
+
+    let queries = RefCell::new(vec!());
+    let matrix = los_ref::visibility_matrix(|c| { queries.borrow_mut().push(c); opaqueness(c) }, 10, &observers);
+
+    for (i, row) in matrix.iter().enumerate() {
+        assert!(row[i]);
+        for (j, &visible) in row.iter().enumerate() {
+            assert_eq!(visible, matrix[j][i]);
+            if i < j {
+                assert_eq!(visible, los_ref::can_see(opaqueness, 10, observers[i], observers[j]));
+            }
+        }
+    }
+    assert!(!matrix[0][1]);
+    assert!(matrix[0][3]);
+
+    let queries = queries.into_inner();
+    assert_eq!(queries.iter().collect::<HashSet<_>>().len(), queries.len());
+}
+
+#[test]
+fn fov_diff_after_step() {
+    use algo::fov;
+    use hex2d::Direction;
+
+    let wall = |c : Coordinate| c.x == 3;
+    let opaqueness = |c : Coordinate| if wall(c) { 100 } else { 1 };
+    let before = fov::fov(opaqueness, 5, Coordinate::new(0, 0), Direction::all());
+    let after = fov::fov(opaqueness, 5, Coordinate::new(-1, 0), Direction::all());
+
+    let (gained, lost) = before.diff(&after);
+    assert!(!gained.is_empty() && !lost.is_empty());
+    assert!(gained.iter().all(|&c| after.is_visible(c) && !before.is_visible(c)));
+    assert!(lost.iter().all(|&c| before.is_visible(c) && !after.is_visible(c)));
+    assert_eq!(after.len(), before.len() + gained.len() - lost.len());
+
+    let (back_gained, back_lost) = after.diff(&before);
+    assert_eq!((back_gained.len(), back_lost.len()), (lost.len(), gained.len()));
+    assert_eq!(before.diff(&before), (vec!(), vec!()));
+}
+
+#[test]
+fn fov_shadow_behind_pillar() {
+    use algo::fov;
+    use std::collections::HashSet;
+
+    let light = Coordinate::new(0, 0);
+    let none = HashSet::new();
+    assert!(fov::shadow(light, &none, 5).is_empty());
+
+    let pillar : HashSet<Coordinate> = [Coordinate::new(2, 0)].iter().cloned().collect();
+    let shadow = fov::shadow(light, &pillar, 5);
+    assert!(shadow.contains(&Coordinate::new(4, 0)));
+    assert!(shadow.contains(&Coordinate::new(5, 0)));
+    assert!(!shadow.contains(&Coordinate::new(2, 0)));
+    assert!(!shadow.contains(&Coordinate::new(-3, 0)));
+    assert!(!shadow.contains(&Coordinate::new(6, 0)));
+    assert!(shadow.iter().all(|c| light.distance(*c) <= 5 && c.x > 0));
+}
+
+#[test]
+fn lighting_channels_blend() {
+    use algo::lighting::LightMap;
+
+    let opaqueness = |c : Coordinate| if c == Coordinate::new(2, 0) { 100 } else { 1 };
+
+    let mut map : LightMap = LightMap::new();
+    map.add_source(Coordinate::new(0, 0), [1.0, 0.0, 0.0], 4, opaqueness);
+    map.add_source(Coordinate::new(1, 0), [0.0, 0.0, 1.0], 4, opaqueness);
+
+    let between = map.get(Coordinate::new(1, 0));
+    assert!(between[0] > 0.0 && between[1] == 0.0 && between[2] > 0.0);
+    assert!(between[2] > between[0]);
+    assert_eq!(map.get(Coordinate::new(10, 0)), [0.0; 3]);
+    assert!(map.iter().all(|(_, color)| color[1] == 0.0));
+
+    let mut green : LightMap<i32, 1> = LightMap::new();
+    green.add_source(Coordinate::new(0, 0), [2.0], 2, |_| 1);
+    let mut both = green.clone();
+    both += &green;
+    assert!(green.get(Coordinate::new(0, 0))[0] > 0.0);
+    assert_eq!(both.get(Coordinate::new(0, 0))[0], 2.0 * green.get(Coordinate::new(0, 0))[0]);
+}
+
+#[test]
+fn fog_memory_ages_and_forgets() {
+    use algo::fog::{Forget, Memory};
+    use algo::fov;
+    use hex2d::Direction;
+
+    let mut memory = Memory::new(Forget::After(2));
+    let map = fov::fov(|_| 1, 2, Coordinate::new(0, 0), Direction::all());
+    memory.see_fov(&map);
+    assert!(memory.is_known(Coordinate::new(1, 0)));
+    assert_eq!(memory.age(Coordinate::new(1, 0)), Some(0));
+    assert_eq!(memory.age(Coordinate::new(9, 0)), None);
+
+    memory.tick();
+    memory.see(Coordinate::new(9, 0));
+    memory.tick();
+    assert_eq!(memory.age(Coordinate::new(1, 0)), Some(2));
+    assert_eq!(memory.last_seen(Coordinate::new(9, 0)), Some(1));
+    assert_eq!(memory.seen_within(1).collect::<Vec<_>>(), vec!(Coordinate::new(9, 0)));
+
+    memory.tick();
+    assert!(!memory.is_known(Coordinate::new(1, 0)));
+    assert_eq!(memory.len(), 1);
+
+    let mut forever : Memory = Memory::new(Forget::Never);
+    forever.see(Coordinate::new(0, 0));
+    for _ in 0..100 {
+        forever.tick();
+    }
+    assert_eq!(forever.age(Coordinate::new(0, 0)), Some(100));
+}
+
+#[test]
+fn fov_by_distance() {
+    use algo::fov;
+    use hex2d::Direction;
+
+    let origin = Coordinate::new(2, -1);
+    let map = fov::fov(|_| 1, 4, origin, Direction::all());
+    let ordered = map.by_distance();
+    assert_eq!(ordered.len(), map.len());
+    assert_eq!(ordered[0].0, origin);
+    assert!(ordered.windows(2).all(|w| origin.distance(w[0].0) <= origin.distance(w[1].0)));
+    assert!(ordered.iter().all(|&(c, l)| map.light(c) == Some(l)));
+}
+
+#[test]
+fn fov_facing_attenuates_sides() {
+    use algo::fov;
+    use hex2d::Direction;
+
+    let pos = Coordinate::new(0, 0);
+    let map = fov::fov_facing(|_| 1.0, 5.0, pos, Direction::XY, 0.5);
+    let full = fov::fov(|_| 1.0, 5.0, pos, Direction::all());
+
+    let ahead = pos + Coordinate::from(Direction::XY).scale(2);
+    let side = pos + Coordinate::from(Direction::XY + hex2d::Angle::Right).scale(2);
+    let behind = pos + Coordinate::from(Direction::YX).scale(2);
+
+    assert_eq!(map.light(ahead), full.light(ahead));
+    assert_eq!(map.light(side), full.light(side).map(|l| l * 0.5));
+    assert!(!map.is_visible(behind));
+    assert!(map.is_visible(pos));
+    assert!(map.len() < full.len());
+}
+
+#[test]
+fn stealth_detection_map() {
+    use algo::cover::Cover;
+    use algo::fov;
+    use algo::lighting::LightMap;
+    use algo::stealth;
+    use hex2d::Direction;
+
+    let observer = fov::fov(|_| 1.0, 6.0, Coordinate::new(0, 0), Direction::all());
+    let mut light : LightMap = LightMap::new();
+    light.add_source(Coordinate::new(3, 0), [1.0, 1.0, 1.0], 3, |_| 1);
+
+    let crate_at = Coordinate::new(3, -1);
+    let map = stealth::detection_map(&observer, &light, |c| if c == crate_at { Cover::Partial } else { Cover::None });
+
+    let near = map[&Coordinate::new(2, 0)];
+    let lamp = map[&Coordinate::new(3, 0)];
+    assert!(near > 0.0 && lamp > 0.0 && near <= 1.0);
+    assert!(!map.contains_key(&Coordinate::new(-3, 0)));
+    assert!(map[&crate_at] < lamp);
+
+    let hidden = stealth::detection_map(&observer, &light, |_| Cover::Full);
+    assert!(hidden.is_empty());
+}
+
+#[test]
+fn graph_mst() {
+    use algo::graph;
+
+    let points = [
+        Coordinate::new(0, 0),
+        Coordinate::new(10, 0),
+        Coordinate::new(2, 0),
+        Coordinate::new(11, 0),
+        Coordinate::new(0, 3),
+    ];
+    let hex = |a : Coordinate, b : Coordinate| Some(a.distance(b) as u32);
+    let edges = graph::mst(&points, hex);
+    assert_eq!(edges.len(), points.len() - 1);
+    let total : i32 = edges.iter().map(|&(a, b)| a.distance(b)).sum();
+    assert_eq!(total, 2 + 3 + 8 + 1);
+    assert!(edges.contains(&(Coordinate::new(10, 0), Coordinate::new(11, 0))));
+
+    let river = |a : Coordinate, b : Coordinate| if (a.x < 5) == (b.x < 5) { hex(a, b) } else { None };
+    assert_eq!(graph::mst(&points, river).len(), points.len() - 2);
+    assert!(graph::mst::<_, i32>(&[], hex).is_empty());
+}
+
+#[test]
+fn graph_patrol_route() {
+    use algo::graph;
+
+    let waypoints = [
+        Coordinate::new(0, 0),
+        Coordinate::new(6, 0),
+        Coordinate::new(0, 6),
+        Coordinate::new(6, -6),
+        Coordinate::new(-6, 6),
+        Coordinate::new(-6, 0),
+    ];
+    let cost = |a : Coordinate, b : Coordinate| if b.distance(Coordinate::new(0, 0)) > 8 || a == b { None } else { Some(1) };
+    let route = graph::patrol_route(&waypoints, graph::walk_metric(cost));
+
+    assert_eq!(route.len(), waypoints.len());
+    assert_eq!(route[0], waypoints[0]);
+    assert!(waypoints.iter().all(|w| route.contains(w)));
+    let length : i32 = (0..route.len()).map(|i| route[i].distance(route[(i + 1) % route.len()])).sum();
+    assert_eq!(length, 6 * 6);
+
+    assert_eq!(graph::patrol_route::<_, i32>(&[], |_, _| Some(1)), vec!());
+}
+
+#[test]
+fn graph_patrol_route_asymmetric() {
+    use algo::graph;
+    use rand::Rng;
+
+    for seed in 0..64 {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let waypoints : Vec<Coordinate> = (0..rng.gen_range(4..9)).map(|i| Coordinate::new(i, 0)).collect();
+        let costs : Vec<Vec<u32>> = waypoints.iter().map(|_| {
+            waypoints.iter().map(|_| rng.gen_range(1..20)).collect()
+        }).collect();
+        let metric = |a : Coordinate, b : Coordinate| Some(costs[a.x as usize][b.x as usize]);
+        let length = |route : &[Coordinate]| -> u32 {
+            (0..route.len()).map(|i| metric(route[i], route[(i + 1) % route.len()]).unwrap()).sum()
+        };
+
+        let route = graph::patrol_route(&waypoints, metric);
+        assert_eq!(route[0], waypoints[0]);
+        assert!(waypoints.iter().all(|w| route.contains(w)));
+
+        // No reversal of a section makes the loop shorter
+        for i in 1..route.len() {
+            for j in i + 1..route.len() {
+                let mut other = route.clone();
+                other[i..j + 1].reverse();
+                assert!(length(&other) >= length(&route));
+            }
+        }
+    }
+}
+
+#[test]
+fn bfs_closest_between() {
+    use algo::bfs;
+    use std::collections::HashSet;
+
+    let a : HashSet<Coordinate> = Coordinate::new(0, 0).range_iter(2).collect();
+    let b : HashSet<Coordinate> = Coordinate::new(10, 0).range_iter(2).collect();
+    let open = |c : Coordinate| c.y == 0 || c.x == 5;
+
+    let (from, to, path) = bfs::closest_between(&a, &b, open).unwrap();
+    assert_eq!((from, to), (Coordinate::new(2, 0), Coordinate::new(8, 0)));
+    assert_eq!(path.len(), 7);
+    assert_eq!((path[0], *path.last().unwrap()), (from, to));
+    assert!(path.windows(2).all(|w| w[0].distance(w[1]) == 1));
+
+    let walled = |c : Coordinate| c.distance(Coordinate::new(0, 0)) < 4;
+    assert!(bfs::closest_between(&a, &b, walled).is_none());
+}
+
+#[test]
+fn ranges_intersection() {
+    use algo::bfs;
+    use geom;
+
+    let (a, b) = (Coordinate::new(0, 0), Coordinate::new(4, 0));
+    let both = geom::ranges_intersection(&[(a, 3), (b, 2)]);
+    assert_eq!(both.len(), 4);
+    assert!(both.iter().all(|c| a.distance(*c) <= 3 && b.distance(*c) <= 2));
+    assert!(geom::ranges_intersection::<i32>(&[]).is_empty());
+    assert!(geom::ranges_intersection(&[(a, 1), (b, 1)]).is_empty());
+
+    let open = |c : Coordinate| c.distance(a) <= 10;
+    assert_eq!(bfs::walk_ranges_intersection(&[(a, 3), (b, 2)], open), both);
+
+    let wall = |c : Coordinate| c.distance(a) <= 10 && !(c.x == 2 && c.y != -6);
+    let around = bfs::walk_ranges_intersection(&[(a, 3), (b, 2)], wall);
+    assert!(around.is_empty());
+    let far = bfs::walk_ranges_intersection(&[(a, 12), (b, 2)], wall);
+    assert!(!far.is_empty() && far.iter().all(|c| c.x > 2));
+}
+
+#[test]
+fn distance_largest_open_hexagon() {
+    use algo::distance;
+    use geom::Bounds;
+
+    let bounds = Bounds::hexagon(Coordinate::new(0, 0), 10);
+    let arena = Coordinate::new(4, -2);
+    let can_pass = |c : Coordinate| c.distance(arena) <= 3 || c.x < -6;
+
+    assert_eq!(distance::largest_open_hexagon(can_pass, bounds), Some((arena, 3)));
+    assert_eq!(distance::open_hexagon_centers(can_pass, bounds, 3), vec!(arena));
+    assert_eq!(distance::open_hexagon_centers(can_pass, bounds, 2).len(), 7);
+    assert_eq!(distance::largest_open_hexagon(|_ : Coordinate| false, bounds), None);
+}
+
+#[test]
+fn metrics_openness_and_stats() {
+    use algo::metrics;
+    use geom::Bounds;
+
+    let bounds = Bounds::hexagon(Coordinate::new(0, 0), 10);
+    let corridor = |c : Coordinate| c.y == 0 || (c.y.abs() <= 1 && c.x > 3);
+
+    let openness = metrics::openness(corridor, bounds, 1);
+    assert_eq!(openness[Coordinate::new(0, 5)], 0.0);
+    assert_eq!(openness[Coordinate::new(-5, 0)], 3.0);
+    assert!(openness[Coordinate::new(6, 0)] > openness[Coordinate::new(-5, 0)]);
+    assert_eq!(openness.bounds(), bounds);
+
+    let all = metrics::stats(|_ : Coordinate| true, bounds);
+    assert_eq!(all.open_ratio, 1.0);
+    let narrow = metrics::stats(|c : Coordinate| c.y == 0, bounds);
+    assert_eq!(narrow.average_width, 1.0);
+    let stats = metrics::stats(corridor, bounds);
+    assert!(stats.open_ratio > 0.0 && stats.open_ratio < 0.5);
+    assert!(stats.average_width > 1.0 && stats.average_width < all.average_width);
+}
+
+#[test]
+fn map_revision_invalidates_fov() {
+    use algo::fov;
+    use hex2d::Direction;
+    use map::MapRevision;
+
+    let mut revision = MapRevision::new();
+    let wall = Coordinate::new(2, 0);
+    revision.touch(Coordinate::new(-40, 0));
+    let mut map = fov::fov(|c| if c == wall { 10 } else { 1 }, 5, Coordinate::new(0, 0), Direction::all());
+    assert_eq!(map.revision(), 0);
+    map.set_revision(&revision);
+    let computed_at = revision.version();
+    assert_eq!(map.revision(), computed_at);
+    assert!(!map.is_stale(&revision));
+
+    revision.touch(Coordinate::new(40, 40));
+    assert!(revision.is_dirty_since(computed_at));
+    assert!(!map.is_stale(&revision));
+
+    revision.touch(Coordinate::new(2, 0));
+    assert!(map.is_stale(&revision));
+    assert!(revision.is_changed_since(computed_at, Coordinate::new(2, 0)));
+    assert!(!revision.is_changed_since(revision.version(), Coordinate::new(2, 0)));
+    assert_eq!(revision.changed_since(2).collect::<Vec<_>>(), vec!(Coordinate::new(2, 0)));
+
+    revision.compact(2);
+    assert_eq!(revision.changed_since(0).count(), 1);
+}
+
+#[test]
+fn map_revision_invalidates_path_tables() {
+    use algo::apsp::AllPairs;
+    use algo::astar::{GoalBounds, Landmarks};
+    use geom::Bounds;
+    use map::MapRevision;
+
+    let mut revision = MapRevision::new();
+    let bounds = Bounds::hexagon(Coordinate::new(0, 0), 3);
+    let wall = Coordinate::new(1, 0);
+    let cost = |_, b| if bounds.contains(b) && b != wall { Some(1) } else { None };
+
+    let mut all_pairs = AllPairs::new(bounds, cost);
+    let mut goal_bounds = GoalBounds::new(bounds, cost);
+    let mut landmarks = Landmarks::new(&[Coordinate::new(-3, 0)], cost);
+    assert!(!all_pairs.is_stale(&revision));
+
+    revision.touch(Coordinate::new(10, 0));
+    all_pairs.set_revision(&revision);
+    goal_bounds.set_revision(&revision);
+    landmarks.set_revision(&revision);
+    assert_eq!(all_pairs.revision(), 1);
+
+    revision.touch(Coordinate::new(20, 0));
+    assert!(!all_pairs.is_stale(&revision));
+    assert!(!goal_bounds.is_stale(&revision));
+    assert!(!landmarks.is_stale(&revision));
+
+    // Opening the wall changes paths, even though it was never reached
+    revision.touch(wall);
+    assert!(all_pairs.is_stale(&revision));
+    assert!(goal_bounds.is_stale(&revision));
+    assert!(landmarks.is_stale(&revision));
+}
+
+#[test]
+fn searches_run_for_budget() {
+    use algo::bfs::{self, SearchStatus};
+    use algo::dijkstra;
+
+    let dest = Coordinate::new(6, 0);
+    let can_pass = |c : Coordinate| c.distance(Coordinate::new(0, 0)) <= 8;
+
+    let mut search = bfs::Traverser::new(can_pass, |c| c == dest, Coordinate::new(0, 0));
+    let mut frames = 0;
+    let found = loop {
+        frames += 1;
+        match search.run_for(10) {
+            SearchStatus::InProgress => {},
+            status => break status,
+        }
+    };
+    assert_eq!(found, SearchStatus::Found(dest));
+    assert!(frames > 1);
+    assert_eq!(search.distance(dest), Some(6));
+    while search.run_for(10) == SearchStatus::InProgress {}
+    assert_eq!(search.run_for(10), SearchStatus::Exhausted);
+
+    let cost = |_, to : Coordinate| if can_pass(to) { Some(2) } else { None };
+    let mut search = dijkstra::Traverser::new(cost, |c| c == dest, Coordinate::new(0, 0));
+    assert_eq!(search.run_for(0), SearchStatus::InProgress);
+    assert_eq!(search.run_for(5), SearchStatus::InProgress);
+    assert_eq!(search.run_for(1000), SearchStatus::Found(dest));
+    assert_eq!(search.distance(dest), Some(12));
+}
+
+#[test]
+fn searches_cancellable() {
+    use algo::bfs::{self, SearchStatus};
+    use algo::dijkstra;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    let flag = Arc::new(AtomicBool::new(false));
+    let mut search = bfs::Traverser::new(|_| true, |_ : Coordinate| false, Coordinate::new(0, 0));
+    let mut checks = 0;
+    let status = search.find_unless(|| {
+        checks += 1;
+        if checks == 3 {
+            flag.store(true, Ordering::Relaxed);
+        }
+        flag.load(Ordering::Relaxed)
+    });
+    assert_eq!(status, SearchStatus::InProgress);
+    assert_eq!(checks, 3);
+
+    let dest = Coordinate::new(5, 5);
+    let mut search = dijkstra::Traverser::new(|_, _| Some(1), |c| c == dest, Coordinate::new(0, 0));
+    assert_eq!(search.find_unless(|| true), SearchStatus::InProgress);
+    assert_eq!(search.find_unless(|| false), SearchStatus::Found(dest));
+}
+
+#[test]
+fn searches_random_tie_breaking() {
+    use algo::{bfs, dijkstra};
+    use geom::Bounds;
+    use std::collections::HashSet;
+
+    let start = Coordinate::new(0, 0);
+    let dest = Coordinate::new(4, 2);
+    let bounds = Bounds::hexagon(start, 8);
+
+    let bfs_path = |seed : u64| {
+        let mut search = bfs::Traverser::builder(start)
+            .is_dest(|c| c == dest)
+            .bounds(bounds)
+            .shuffle(&mut StdRng::seed_from_u64(seed))
+            .build();
+        assert_eq!(search.find(), Some(dest));
+        let mut path = vec!(dest);
+        while *path.last().unwrap() != start {
+            path.push(search.backtrace(*path.last().unwrap()).unwrap());
+        }
+        path
+    };
+    let dijkstra_path = |seed : u64| {
+        let mut search = dijkstra::Traverser::builder(start)
+            .cost(|_, to : Coordinate| if bounds.contains(to) { Some(1) } else { None })
+            .is_dest(|c| c == dest)
+            .shuffle(&mut StdRng::seed_from_u64(seed))
+            .build();
+        assert_eq!(search.find(), Some(dest));
+        search.path(dest).unwrap()
+    };
+
+    assert_eq!(bfs_path(7), bfs_path(7));
+    assert_eq!(dijkstra_path(7), dijkstra_path(7));
+
+    let bfs_paths : HashSet<_> = (0..8).map(bfs_path).collect();
+    let dijkstra_paths : HashSet<_> = (0..8).map(dijkstra_path).collect();
+    assert!(bfs_paths.len() > 1);
+    assert!(dijkstra_paths.len() > 1);
+    assert!(bfs_paths.iter().chain(dijkstra_paths.iter()).all(|p| p.len() == 7));
+}
+
+#[test]
+fn searches_prefer_straight() {
+    use algo::{bfs, dijkstra};
+    use geom::Bounds;
+
+    let start = Coordinate::new(0, 0);
+    let dest = Coordinate::new(3, 2);
+    let bounds = Bounds::hexagon(start, 8);
+    let open = |c : Coordinate| c == start || bounds.contains(c) && (c.x * 7 + c.y * 13).rem_euclid(5) != 0;
+    let turns = |path : &[Coordinate]| {
+        path.windows(3).filter(|w| w[2] - w[1] != w[1] - w[0]).count()
+    };
+
+    let bfs_path = |straight : bool| {
+        let mut builder = bfs::Traverser::builder(start)
+            .can_pass(open)
+            .is_dest(|c| c == dest);
+        if straight {
+            builder = builder.prefer_straight();
+        }
+        let mut search = builder.build();
+        assert_eq!(search.find(), Some(dest));
+        let mut path = vec!(dest);
+        while *path.last().unwrap() != start {
+            path.push(search.backtrace(*path.last().unwrap()).unwrap());
+        }
+        path
+    };
+    let dijkstra_path = |straight : bool| {
+        let mut builder = dijkstra::Traverser::builder(start)
+            .cost(|_, to| if open(to) { Some(1) } else { None })
+            .is_dest(|c| c == dest);
+        if straight {
+            builder = builder.prefer_straight();
+        }
+        let mut search = builder.build();
+        assert_eq!(search.find(), Some(dest));
+        search.path(dest).unwrap()
+    };
+
+    for (default, straight) in &[(bfs_path(false), bfs_path(true)), (dijkstra_path(false), dijkstra_path(true))] {
+        assert_eq!(straight.len(), default.len());
+        assert_eq!(turns(default), 3);
+        assert_eq!(turns(straight), 1);
+    }
+}
+
+#[test]
+fn send_sync_handles() {
+    use algo::{bfs, dijkstra, fog, fov, influence, lighting, path, scent, traffic};
+    use map;
+
+    fn thread_safe<T : Send + Sync>(_ : &T) {}
+    fn thread_safe_type<T : Send + Sync>() {}
+
+    let blocked = ::std::sync::Arc::new(vec!(Coordinate::new(1, 0)));
+
+    let can_pass = { let blocked = blocked.clone(); move |c| !blocked.contains(&c) };
+    thread_safe(&bfs::Traverser::new(can_pass, |_| false, Coordinate::new(0, 0)));
+    thread_safe(&bfs::Traverser::builder(Coordinate::new(0, 0)).max_distance(3).build());
+    thread_safe(&dijkstra::Traverser::builder(Coordinate::new(0, 0)).build());
+    let cost = move |_, to| if blocked.contains(&to) { None } else { Some(1) };
+    thread_safe(&dijkstra::Traverser::new(cost, |_| false, Coordinate::new(0, 0)));
+
+    thread_safe_type::<fov::FovMap>();
+    thread_safe_type::<lighting::LightMap>();
+    thread_safe_type::<fog::Memory>();
+    thread_safe_type::<influence::InfluenceMap>();
+    thread_safe_type::<scent::ScentMap>();
+    thread_safe_type::<path::Path>();
+    thread_safe_type::<traffic::Traffic>();
+    thread_safe_type::<map::HexMap<u8>>();
+    thread_safe_type::<map::MapRevision>();
+    thread_safe_type::<map::Layers>();
+}
+
+#[test]
+fn astar_with_landmarks() {
+    use algo::astar::{self, Landmarks};
+    use algo::dijkstra;
+    use std::cell::Cell;
+
+    // Serpentine: walls along every other `y` row, with gaps on alternating ends
+    let open = |c : Coordinate| {
+        let (min_x, max_x) = ((-12).max(-12 - c.y), 12.min(12 - c.y));
+        c.distance(Coordinate::new(0, 0)) <= 12 && match c.y.rem_euclid(4) {
+            1 => c.x >= max_x - 1,
+            3 => c.x <= min_x + 1,
+            _ => true,
+        }
+    };
+    let calls = Cell::new(0);
+    let cost = |_, to : Coordinate| { calls.set(calls.get() + 1); if open(to) { Some(1) } else { None } };
+
+    let (start, dest) = (Coordinate::new(0, 0), Coordinate::new(-6, 12));
+    let mut search = dijkstra::Traverser::new(&cost, |c| c == dest, start);
+    search.find().unwrap();
+    let expected = search.distance(dest).unwrap();
+    let plain_calls = calls.replace(0);
+
+    let landmarks = Landmarks::farthest(Coordinate::new(0, 0), 4, &cost);
+    assert_eq!(landmarks.landmarks().len(), 4);
+    calls.set(0);
+    let (path, total) = astar::find(start, dest, &cost, |c| landmarks.heuristic(c, dest)).unwrap();
+    assert_eq!(total, expected);
+    assert_eq!(path.len() as u32, expected + 1);
+    assert_eq!((path[0], *path.last().unwrap()), (start, dest));
+    assert!(calls.get() < plain_calls / 2);
+
+    assert_eq!(landmarks.heuristic(start, start), 0);
+    assert!(astar::find(start, Coordinate::new(30, 0), &cost, |_| 0).is_none());
+}
+
+#[test]
+fn apsp_tables() {
+    use algo::apsp::AllPairs;
+    use algo::dijkstra;
+    use geom::Bounds;
+
+    let bounds = Bounds::hexagon(Coordinate::new(0, 0), 4);
+    let wall = |c : Coordinate| c.x == 1 && c.y > -3;
+    // Stepping down in `y` is one-way and cheap
+    let cost = |from : Coordinate, to : Coordinate| {
+        if wall(to) { None } else if to.y < from.y { Some(1) } else { Some(3) }
+    };
+    let table = AllPairs::new(bounds, cost);
+
+    for &(a, b) in &[(Coordinate::new(0, 0), Coordinate::new(3, 0)), (Coordinate::new(3, 0), Coordinate::new(0, 0)), (Coordinate::new(-2, 4), Coordinate::new(2, -4))] {
+        let within = |from, to : Coordinate| if bounds.contains(to) { cost(from, to) } else { None };
+        let mut search = dijkstra::Traverser::new(within, |c| c == b, a);
+        search.find().unwrap();
+        assert_eq!(table.cost(a, b), search.distance(b));
+
+        let path = table.path(a, b).unwrap();
+        assert_eq!((path[0], *path.last().unwrap()), (a, b));
+        let total : u32 = path.windows(2).map(|w| cost(w[0], w[1]).unwrap()).sum();
+        assert_eq!(Some(total), table.cost(a, b));
+    }
+    assert_ne!(table.cost(Coordinate::new(0, 0), Coordinate::new(0, 2)), table.cost(Coordinate::new(0, 2), Coordinate::new(0, 0)));
+
+    assert_eq!(table.cost(Coordinate::new(0, 0), Coordinate::new(1, 0)), None);
+    assert_eq!(table.next_step(Coordinate::new(0, 0), Coordinate::new(0, 0)), None);
+    assert_eq!(table.path(Coordinate::new(0, 0), Coordinate::new(0, 0)), Some(vec!(Coordinate::new(0, 0))));
+    assert_eq!(table.cost(Coordinate::new(0, 0), Coordinate::new(9, 0)), None);
+}
+
+#[test]
+fn astar_goal_bounds() {
+    use algo::astar::{self, GoalBounds};
+    use geom::Bounds;
+    use std::cell::Cell;
+
+    let bounds = Bounds::hexagon(Coordinate::new(0, 0), 8);
+    let wall = |c : Coordinate| c.y == 0 && c.x > -6;
+    let calls = Cell::new(0);
+    let cost = |_, to : Coordinate| {
+        calls.set(calls.get() + 1);
+        if !bounds.contains(to) || wall(to) { None } else { Some(1) }
+    };
+    let goals = GoalBounds::new(bounds, &cost);
+
+    for &(start, dest) in &[(Coordinate::new(3, 2), Coordinate::new(3, -2)), (Coordinate::new(0, 5), Coordinate::new(-5, 5))] {
+        calls.set(0);
+        let (_, expected) = astar::find(start, dest, &cost, |c| c.distance(dest) as u32).unwrap();
+        let plain_calls = calls.replace(0);
+        let (path, total) = astar::find(start, dest, goals.prune(dest, &cost), |c| c.distance(dest) as u32).unwrap();
+        assert_eq!(total, expected);
+        assert_eq!(path.len() as u32, total + 1);
+        assert!(calls.get() < plain_calls);
+    }
+
+    assert!(!goals.allows(Coordinate::new(3, 2), Coordinate::new(4, 2), Coordinate::new(-7, 2)));
+    assert!(goals.allows(Coordinate::new(30, 2), Coordinate::new(31, 2), Coordinate::new(-7, 2)));
+}
+
+#[test]
+fn path_corridor() {
+    use algo::path::Path;
+
+    let path = Path::new((0..5).map(|x| Coordinate::new(x, 0)).collect());
+    assert_eq!(path.corridor(0, |_| true).len(), 5);
+
+    let wide = path.corridor(1, |_| true);
+    assert_eq!(wide.len(), 5 + 2 * 6 + 2);
+    assert!(wide.contains(&Coordinate::new(-1, 0)) && wide.contains(&Coordinate::new(2, 1)));
+
+    let walled = path.corridor(2, |c : Coordinate| c.y < 1);
+    assert!(walled.iter().all(|c| c.y < 1));
+    assert!(walled.contains(&Coordinate::new(2, -2)));
+}
+
+#[test]
+fn path_simplify_to_legs() {
+    use algo::path::{Leg, Path};
+
+    let mut coordinates : Vec<Coordinate> = (0..=20).map(|x| Coordinate::new(x, 0)).collect();
+    coordinates.extend((1..=15).map(|y| Coordinate::new(20, y)));
+    coordinates.push(Coordinate::new(-5, -5));
+    coordinates.push(Coordinate::new(-5, -4));
+    let path = Path::new(coordinates.clone());
+
+    let legs = path.simplify();
+    assert_eq!(legs.len(), 4);
+    assert_eq!(legs[0], Leg::Walk { start: Coordinate::new(0, 0), direction: Coordinate::new(0, 0).direction_to_cw(Coordinate::new(1, 0)).unwrap(), length: 20 });
+    assert_eq!(legs[1].end(), Coordinate::new(20, 15));
+    assert_eq!(legs[2], Leg::Jump { from: Coordinate::new(20, 15), to: Coordinate::new(-5, -5) });
+    assert!(legs.windows(2).all(|w| w[0].end() == w[1].start()));
+
+    assert_eq!(Path::from_legs(&legs), path);
+    assert!(Path::new(vec!(Coordinate::new(1, 1))).simplify().is_empty());
+}
+
+#[test]
+fn path_append_split_truncate_reverse() {
+    use algo::path::Path;
+
+    let line = |from : i32, to : i32| Path::new((from..=to).map(|x| Coordinate::new(x, 0)).collect());
+
+    let mut path = line(0, 3);
+    path.append(line(3, 6));
+    assert_eq!(path, line(0, 6));
+    assert_eq!(path.len(), 6);
+
+    let (head, tail) = path.split_at(Coordinate::new(2, 0)).unwrap();
+    assert_eq!((head, tail), (line(0, 2), line(2, 6)));
+    assert!(path.split_at(Coordinate::new(9, 9)).is_none());
+
+    let reversed = path.reverse();
+    assert_eq!((reversed.start(), reversed.end()), (path.end(), path.start()));
+    assert_eq!(reversed.reverse(), path);
+
+    let mut cut = path.clone();
+    assert!(cut.truncate_at_first_blocked(|c| c.x != 4));
+    assert_eq!(cut, line(0, 3));
+    assert!(!cut.truncate_at_first_blocked(|_| true));
+    assert!(cut.truncate_at_first_blocked(|c| c.x == 0) && cut.is_empty());
+}